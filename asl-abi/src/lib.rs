@@ -0,0 +1,371 @@
+//! The interface between autosplitter scripts compiled to WebAssembly and the
+//! runtime executing them. The runtime's import resolver, the compiler and the
+//! Rust SDK are all built from or tested against these tables. Modules can be
+//! checked against them with `validate`.
+
+extern crate parity_wasm;
+
+mod validate;
+
+pub use validate::{validate, Issue, Report};
+
+/// The version of the ABI described by this crate. Modules store the version
+/// they target as a little endian `u32` in a custom section. Modules without
+/// that section target version 0.
+pub const VERSION: u32 = 8;
+
+/// The name of the custom section storing the targeted ABI version.
+pub const VERSION_SECTION: &str = "asl_abi_version";
+
+/// The payload of the version section for the current version.
+pub const VERSION_SECTION_PAYLOAD: [u8; 4] = [
+    VERSION as u8,
+    (VERSION >> 8) as u8,
+    (VERSION >> 16) as u8,
+    (VERSION >> 24) as u8,
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+use ValueType::*;
+
+/// A function the runtime provides to the script.
+#[derive(Debug)]
+pub struct Import {
+    pub name: &'static str,
+    pub params: &'static [ValueType],
+    pub result: Option<ValueType>,
+    /// The ABI version that introduced the function.
+    pub since: u32,
+}
+
+#[derive(Debug)]
+pub enum ExportKind {
+    Memory,
+    Function {
+        params: &'static [ValueType],
+        result: Option<ValueType>,
+    },
+}
+
+/// Something the script provides to the runtime.
+#[derive(Debug)]
+pub struct Export {
+    pub name: &'static str,
+    pub kind: ExportKind,
+    pub required: bool,
+}
+
+/// Stored in the upper bits of the pointer type passed when registering a
+/// pointer path. The value and the pointers along the way are big endian.
+pub const BIG_ENDIAN_FLAG: u32 = 1 << 8;
+
+/// Stored in the upper bits of the pointer type passed when registering a
+/// pointer path. The pointers along the way are 32-bit, regardless of the
+/// process.
+pub const POINTER_SIZE_32_FLAG: u32 = 1 << 9;
+
+/// Stored in the upper bits of the pointer type passed when registering a
+/// pointer path. The pointers along the way are 64-bit, regardless of the
+/// process.
+pub const POINTER_SIZE_64_FLAG: u32 = 1 << 10;
+
+/// The module all imports are expected to be imported from.
+pub const IMPORT_MODULE: &str = "env";
+
+pub const IMPORTS: &[Import] = &[
+    Import {
+        name: "set_process_name",
+        params: &[I32, I32],
+        result: None,
+        since: 0,
+    },
+    Import {
+        name: "push_pointer_path",
+        params: &[I32, I32, I32],
+        result: Some(I32),
+        since: 0,
+    },
+    Import {
+        name: "push_offset",
+        params: &[I32, I64],
+        result: None,
+        since: 0,
+    },
+    Import {
+        name: "get_u8",
+        params: &[I32, I32],
+        result: Some(I32),
+        since: 0,
+    },
+    Import {
+        name: "get_u16",
+        params: &[I32, I32],
+        result: Some(I32),
+        since: 0,
+    },
+    Import {
+        name: "get_u32",
+        params: &[I32, I32],
+        result: Some(I32),
+        since: 0,
+    },
+    Import {
+        name: "get_u64",
+        params: &[I32, I32],
+        result: Some(I64),
+        since: 0,
+    },
+    Import {
+        name: "get_i8",
+        params: &[I32, I32],
+        result: Some(I32),
+        since: 0,
+    },
+    Import {
+        name: "get_i16",
+        params: &[I32, I32],
+        result: Some(I32),
+        since: 0,
+    },
+    Import {
+        name: "get_i32",
+        params: &[I32, I32],
+        result: Some(I32),
+        since: 0,
+    },
+    Import {
+        name: "get_i64",
+        params: &[I32, I32],
+        result: Some(I64),
+        since: 0,
+    },
+    Import {
+        name: "get_f32",
+        params: &[I32, I32],
+        result: Some(F32),
+        since: 0,
+    },
+    Import {
+        name: "get_f64",
+        params: &[I32, I32],
+        result: Some(F64),
+        since: 0,
+    },
+    Import {
+        name: "set_pointer_path_name",
+        params: &[I32, I32, I32],
+        result: None,
+        since: 1,
+    },
+    Import {
+        name: "set_pointer_path_offsets",
+        params: &[I32, I32, I32],
+        result: None,
+        since: 1,
+    },
+    Import {
+        name: "set_pointer_path_module",
+        params: &[I32, I32, I32],
+        result: None,
+        since: 1,
+    },
+    Import {
+        name: "read_memory",
+        params: &[I32, I32, I32, I32, I32, I32],
+        result: Some(I32),
+        since: 1,
+    },
+    Import {
+        name: "add_setting",
+        params: &[I32, I32, I32],
+        result: Some(I32),
+        since: 2,
+    },
+    Import {
+        name: "get_setting",
+        params: &[I32],
+        result: Some(I32),
+        since: 2,
+    },
+    Import {
+        name: "set_tick_rate",
+        params: &[F64],
+        result: None,
+        since: 2,
+    },
+    Import {
+        name: "print_message",
+        params: &[I32, I32],
+        result: None,
+        since: 2,
+    },
+    Import {
+        name: "set_emulator",
+        params: &[I32, I32],
+        result: None,
+        since: 3,
+    },
+    Import {
+        name: "push_emulator_pointer_path",
+        params: &[I32, I32, I64, I32],
+        result: Some(I32),
+        since: 3,
+    },
+    Import {
+        name: "set_pointer_path_size",
+        params: &[I32, I32],
+        result: None,
+        since: 6,
+    },
+    Import {
+        name: "get_bytes",
+        params: &[I32, I32, I32, I32, I32],
+        result: None,
+        since: 6,
+    },
+    Import {
+        name: "get_module_address",
+        params: &[I32, I32],
+        result: Some(I64),
+        since: 7,
+    },
+    Import {
+        name: "get_module_size",
+        params: &[I32, I32],
+        result: Some(I64),
+        since: 7,
+    },
+    Import {
+        name: "get_module_export",
+        params: &[I32, I32, I32, I32],
+        result: Some(I64),
+        since: 7,
+    },
+    Import {
+        name: "get_memory_region",
+        params: &[I64, I32],
+        result: Some(I32),
+        since: 7,
+    },
+    Import {
+        name: "push_mono_pointer_path",
+        params: &[I32, I32, I32, I32, I32],
+        result: Some(I32),
+        since: 8,
+    },
+];
+
+pub const EXPORTS: &[Export] = &[
+    Export {
+        name: "memory",
+        kind: ExportKind::Memory,
+        required: true,
+    },
+    Export {
+        name: "configure",
+        kind: ExportKind::Function {
+            params: &[],
+            result: None,
+        },
+        required: true,
+    },
+    Export {
+        name: "should_start",
+        kind: ExportKind::Function {
+            params: &[],
+            result: Some(I32),
+        },
+        required: false,
+    },
+    Export {
+        name: "should_split",
+        kind: ExportKind::Function {
+            params: &[],
+            result: Some(I32),
+        },
+        required: false,
+    },
+    Export {
+        name: "should_reset",
+        kind: ExportKind::Function {
+            params: &[],
+            result: Some(I32),
+        },
+        required: false,
+    },
+    Export {
+        name: "is_loading",
+        kind: ExportKind::Function {
+            params: &[],
+            result: Some(I32),
+        },
+        required: false,
+    },
+    Export {
+        name: "game_time",
+        kind: ExportKind::Function {
+            params: &[],
+            result: Some(F64),
+        },
+        required: false,
+    },
+];
+
+pub fn import(name: &str) -> Option<&'static Import> {
+    IMPORTS.iter().find(|i| i.name == name)
+}
+
+/// Parses the payload of the version section.
+pub fn parse_version(payload: &[u8]) -> Option<u32> {
+    if payload.len() != 4 {
+        return None;
+    }
+    Some(payload.iter().rev().fold(0, |v, &b| v << 8 | b as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!(parse_version(&VERSION_SECTION_PAYLOAD), Some(VERSION));
+        assert_eq!(parse_version(&[7, 0, 0, 0]), Some(7));
+        assert_eq!(parse_version(&[0x04, 0x03, 0x02, 0x01]), Some(0x01020304));
+        assert_eq!(parse_version(&[]), None);
+        assert_eq!(parse_version(&[8, 0, 0]), None);
+        assert_eq!(parse_version(&[8, 0, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn flags_are_distinct_from_pointer_types() {
+        let flags = [BIG_ENDIAN_FLAG, POINTER_SIZE_32_FLAG, POINTER_SIZE_64_FLAG];
+        for (i, &flag) in flags.iter().enumerate() {
+            assert_eq!(flag.count_ones(), 1);
+            // The pointer type itself is stored in the lower 8 bits.
+            assert!(flag > 0xFF);
+            assert!(flags[i + 1..].iter().all(|&other| other != flag));
+        }
+    }
+
+    #[test]
+    fn imports_are_unique_and_not_newer_than_the_abi() {
+        for (i, import) in IMPORTS.iter().enumerate() {
+            assert!(import.since <= VERSION, "{}", import.name);
+            assert!(
+                IMPORTS[i + 1..]
+                    .iter()
+                    .all(|other| other.name != import.name),
+                "{}",
+                import.name
+            );
+        }
+    }
+}
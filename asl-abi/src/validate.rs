@@ -0,0 +1,591 @@
+use parity_wasm::deserialize_buffer;
+use parity_wasm::elements::{
+    External, Internal, Module, Section, Type, ValueType as WasmValueType,
+};
+use std::error::Error;
+use std::fmt;
+use {
+    import, parse_version, ExportKind, ValueType, EXPORTS, IMPORT_MODULE, VERSION, VERSION_SECTION,
+};
+
+/// A single problem found while validating a module.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    /// The binary isn't a well-formed WebAssembly module.
+    InvalidModule(String),
+    /// The ABI version section doesn't contain a version.
+    InvalidAbiVersion,
+    /// The module targets a newer ABI than the runtime supports.
+    UnsupportedAbiVersion(u32),
+    /// The module imports a function the environment doesn't provide. All
+    /// functions need to be imported from `IMPORT_MODULE`.
+    UnknownImport { module: String, field: String },
+    /// The module imports a global, memory or table. The environment only
+    /// provides functions.
+    UnsupportedImport { module: String, field: String },
+    /// The module imports a function with a signature that doesn't match the
+    /// one the environment provides.
+    ImportSignatureMismatch {
+        field: String,
+        expected: String,
+        found: String,
+    },
+    /// The module imports a function that was introduced in a later version of
+    /// the ABI than the one it targets.
+    ImportTooNew {
+        field: String,
+        since: u32,
+        version: u32,
+    },
+    /// An export the runtime requires is missing or of the wrong kind.
+    MissingExport(&'static str),
+    /// An export the runtime calls has the wrong signature.
+    ExportSignatureMismatch {
+        field: &'static str,
+        expected: String,
+        found: String,
+    },
+    /// None of the timer callbacks are exported, so the script can never
+    /// affect the timer.
+    NoTimerExports,
+}
+
+impl Issue {
+    /// Errors prevent the module from running, everything else is a warning.
+    pub fn is_error(&self) -> bool {
+        match self {
+            Issue::NoTimerExports => false,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::InvalidModule(message) => write!(f, "Invalid WebAssembly module: {}", message),
+            Issue::InvalidAbiVersion => write!(f, "The ABI version section is malformed"),
+            Issue::UnsupportedAbiVersion(version) => write!(
+                f,
+                "The module targets ABI version {}, but the runtime only supports up to version {}",
+                version, VERSION
+            ),
+            Issue::UnknownImport { module, field } => write!(
+                f,
+                "The import {}::{} isn't provided by the environment",
+                module, field
+            ),
+            Issue::UnsupportedImport { module, field } => write!(
+                f,
+                "The import {}::{} isn't a function, but the environment only provides functions",
+                module, field
+            ),
+            Issue::ImportSignatureMismatch {
+                field,
+                expected,
+                found,
+            } => write!(
+                f,
+                "The import {} has the signature {}, but the environment provides {}",
+                field, found, expected
+            ),
+            Issue::ImportTooNew {
+                field,
+                since,
+                version,
+            } => write!(
+                f,
+                "The import {} requires ABI version {}, but the module targets version {}",
+                field, since, version
+            ),
+            Issue::MissingExport(field) => write!(f, "The export {} is missing", field),
+            Issue::ExportSignatureMismatch {
+                field,
+                expected,
+                found,
+            } => write!(
+                f,
+                "The export {} has the signature {}, but it needs to be {}",
+                field, found, expected
+            ),
+            Issue::NoTimerExports => write!(
+                f,
+                "None of should_start, should_split and should_reset are exported"
+            ),
+        }
+    }
+}
+
+/// The outcome of validating a module against the environment.
+#[derive(Debug, Default)]
+pub struct Report {
+    issues: Vec<Issue>,
+    abi_version: u32,
+}
+
+impl Report {
+    /// The ABI version the module targets.
+    pub fn abi_version(&self) -> u32 {
+        self.abi_version
+    }
+
+    pub fn issues(&self) -> &[Issue] {
+        &self.issues
+    }
+
+    /// Whether the module can be run. Warnings don't count.
+    pub fn is_valid(&self) -> bool {
+        !self.issues.iter().any(Issue::is_error)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for issue in &self.issues {
+            let severity = if issue.is_error() { "error" } else { "warning" };
+            writeln!(f, "{}: {}", severity, issue)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for Report {
+    fn description(&self) -> &str {
+        "The WebAssembly module doesn't match the environment"
+    }
+}
+
+/// Checks a module's imports and exports against the environment without
+/// instantiating it.
+pub fn validate(binary: &[u8]) -> Report {
+    let mut report = Report::default();
+    let module: Module = match deserialize_buffer(binary) {
+        Ok(module) => module,
+        Err(e) => {
+            report.issues.push(Issue::InvalidModule(e.to_string()));
+            return report;
+        }
+    };
+
+    for section in module.sections() {
+        if let Section::Custom(custom) = section {
+            if custom.name() == VERSION_SECTION {
+                match parse_version(custom.payload()) {
+                    Some(version) => report.abi_version = version,
+                    None => report.issues.push(Issue::InvalidAbiVersion),
+                }
+            }
+        }
+    }
+    if report.abi_version > VERSION {
+        report
+            .issues
+            .push(Issue::UnsupportedAbiVersion(report.abi_version));
+        return report;
+    }
+
+    for entry in module.import_section().map_or(&[][..], |s| s.entries()) {
+        let module_name = entry.module().to_owned();
+        let field = entry.field().to_owned();
+        let type_ref = match *entry.external() {
+            External::Function(type_ref) => type_ref,
+            _ => {
+                report.issues.push(Issue::UnsupportedImport {
+                    module: module_name,
+                    field,
+                });
+                continue;
+            }
+        };
+        let import = match import(&field) {
+            Some(import) if module_name == IMPORT_MODULE => import,
+            _ => {
+                report.issues.push(Issue::UnknownImport {
+                    module: module_name,
+                    field,
+                });
+                continue;
+            }
+        };
+        let found = signature(&module, type_ref);
+        let expected = format_signature(import.params, import.result);
+        if found.as_ref() != Some(&expected) {
+            report.issues.push(Issue::ImportSignatureMismatch {
+                field,
+                expected,
+                found: found.unwrap_or_else(|| String::from("<invalid>")),
+            });
+        } else if import.since > report.abi_version {
+            report.issues.push(Issue::ImportTooNew {
+                field,
+                since: import.since,
+                version: report.abi_version,
+            });
+        }
+    }
+
+    let mut timer_exports = 0;
+    for export in EXPORTS {
+        let exported = match &export.kind {
+            ExportKind::Memory => {
+                let exported = match find_export(&module, export.name) {
+                    Some(Internal::Memory(_)) => true,
+                    _ => false,
+                };
+                if !exported && export.required {
+                    report.issues.push(Issue::MissingExport(export.name));
+                }
+                exported
+            }
+            ExportKind::Function { params, result } => check_function_export(
+                &mut report,
+                &module,
+                export.name,
+                params,
+                *result,
+                export.required,
+            ),
+        };
+        if exported && export.name.starts_with("should_") {
+            timer_exports += 1;
+        }
+    }
+    if timer_exports == 0 {
+        report.issues.push(Issue::NoTimerExports);
+    }
+
+    report
+}
+
+fn find_export<'a>(module: &'a Module, name: &str) -> Option<&'a Internal> {
+    module
+        .export_section()?
+        .entries()
+        .iter()
+        .find(|e| e.field() == name)
+        .map(|e| e.internal())
+}
+
+/// Returns whether the function is exported at all.
+fn check_function_export(
+    report: &mut Report,
+    module: &Module,
+    name: &'static str,
+    params: &[ValueType],
+    result: Option<ValueType>,
+    required: bool,
+) -> bool {
+    let func_index = match find_export(module, name) {
+        Some(&Internal::Function(index)) => index,
+        _ => {
+            if required {
+                report.issues.push(Issue::MissingExport(name));
+            }
+            return false;
+        }
+    };
+
+    let expected = format_signature(params, result);
+    let found = function_type_ref(module, func_index).and_then(|t| signature(module, t));
+    if found.as_ref() != Some(&expected) {
+        report.issues.push(Issue::ExportSignatureMismatch {
+            field: name,
+            expected,
+            found: found.unwrap_or_else(|| String::from("<invalid>")),
+        });
+    }
+    true
+}
+
+/// Resolves the type of a function in the function index space, which starts
+/// with the imported functions.
+fn function_type_ref(module: &Module, func_index: u32) -> Option<u32> {
+    let imported = module
+        .import_section()
+        .map_or(&[][..], |s| s.entries())
+        .iter()
+        .filter_map(|e| match *e.external() {
+            External::Function(type_ref) => Some(type_ref),
+            _ => None,
+        }).collect::<Vec<_>>();
+
+    let func_index = func_index as usize;
+    if let Some(&type_ref) = imported.get(func_index) {
+        return Some(type_ref);
+    }
+    module
+        .function_section()?
+        .entries()
+        .get(func_index - imported.len())
+        .map(|f| f.type_ref())
+}
+
+fn signature(module: &Module, type_ref: u32) -> Option<String> {
+    let func_type = match module.type_section()?.types().get(type_ref as usize)? {
+        Type::Function(func_type) => func_type,
+    };
+    let params = func_type
+        .params()
+        .iter()
+        .map(|&t| value_type(t))
+        .collect::<Vec<_>>();
+    let result = func_type.return_type().map(value_type);
+    Some(format_signature(&params, result))
+}
+
+fn value_type(ty: WasmValueType) -> ValueType {
+    match ty {
+        WasmValueType::I32 => ValueType::I32,
+        WasmValueType::I64 => ValueType::I64,
+        WasmValueType::F32 => ValueType::F32,
+        WasmValueType::F64 => ValueType::F64,
+    }
+}
+
+fn format_signature(params: &[ValueType], result: Option<ValueType>) -> String {
+    let name = |ty: &ValueType| match ty {
+        ValueType::I32 => "i32",
+        ValueType::I64 => "i64",
+        ValueType::F32 => "f32",
+        ValueType::F64 => "f64",
+    };
+    let params = params.iter().map(name).collect::<Vec<_>>().join(", ");
+    match result {
+        Some(result) => format!("fn({}) -> {}", params, name(&result)),
+        None => format!("fn({})", params),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parity_wasm::builder::{self, ModuleBuilder};
+    use parity_wasm::elements::{CustomSection, ImportEntry, MemoryType, Serialize};
+    use VERSION_SECTION_PAYLOAD;
+
+    /// Builds a module with a memory and `configure` and `should_split`
+    /// exports, which is all the runtime needs.
+    fn module<F>(version: &[u8], build: F) -> Vec<u8>
+    where
+        F: FnOnce(ModuleBuilder) -> ModuleBuilder,
+    {
+        let builder = build(builder::module())
+            .memory()
+            .build()
+            .export()
+            .field("memory")
+            .internal()
+            .memory(0)
+            .build();
+        let builder = function(builder, "configure", &[], None);
+        let builder = function(builder, "should_split", &[], Some(WasmValueType::I32));
+
+        let mut module = builder.build();
+        let imported = module.import_section().map_or(0, |s| s.functions() as u32);
+        if let Some(exports) = module.export_section_mut() {
+            for export in exports.entries_mut() {
+                if let Internal::Function(index) = export.internal_mut() {
+                    *index += imported;
+                }
+            }
+        }
+
+        let mut section = CustomSection::default();
+        *section.name_mut() = VERSION_SECTION.to_owned();
+        *section.payload_mut() = version.to_vec();
+        module.sections_mut().push(Section::Custom(section));
+
+        let mut binary = Vec::new();
+        module.serialize(&mut binary).unwrap();
+        binary
+    }
+
+    /// Exports a function with the signature. The index of the function gets
+    /// shifted past the imported functions once the module is built.
+    fn function(
+        builder: ModuleBuilder,
+        name: &str,
+        params: &[WasmValueType],
+        result: Option<WasmValueType>,
+    ) -> ModuleBuilder {
+        let mut builder = builder;
+        let location = builder.push_function(
+            builder::function()
+                .signature()
+                .with_params(params.to_vec())
+                .with_return_type(result)
+                .build()
+                .build(),
+        );
+        builder
+            .export()
+            .field(name)
+            .internal()
+            .func(location.body)
+            .build()
+    }
+
+    fn import(
+        builder: ModuleBuilder,
+        module: &str,
+        field: &str,
+        params: &[WasmValueType],
+        result: Option<WasmValueType>,
+    ) -> ModuleBuilder {
+        let mut builder = builder;
+        let type_ref = builder.push_signature(
+            builder::signature()
+                .with_params(params.to_vec())
+                .with_return_type(result)
+                .build_sig(),
+        );
+        builder
+            .import()
+            .path(module, field)
+            .external()
+            .func(type_ref)
+            .build()
+    }
+
+    fn issues(binary: &[u8]) -> Vec<Issue> {
+        validate(binary).issues().to_vec()
+    }
+
+    #[test]
+    fn accepts_valid_modules() {
+        let binary = module(&VERSION_SECTION_PAYLOAD, |b| {
+            import(
+                b,
+                "env",
+                "set_process_name",
+                &[WasmValueType::I32, WasmValueType::I32],
+                None,
+            )
+        });
+        let report = validate(&binary);
+        assert_eq!(report.issues(), &[]);
+        assert_eq!(report.abi_version(), VERSION);
+        assert!(report.is_valid());
+    }
+
+    #[test]
+    fn rejects_invalid_modules() {
+        match &issues(b"not wasm")[..] {
+            [Issue::InvalidModule(_)] => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn checks_the_abi_version() {
+        assert_eq!(issues(&module(&[1, 0], |b| b)), [Issue::InvalidAbiVersion]);
+        assert_eq!(
+            issues(&module(&[0xFF, 0, 0, 0], |b| b)),
+            [Issue::UnsupportedAbiVersion(0xFF)]
+        );
+    }
+
+    #[test]
+    fn checks_imports() {
+        let i32_pair = &[WasmValueType::I32, WasmValueType::I32];
+        assert_eq!(
+            issues(&module(&VERSION_SECTION_PAYLOAD, |b| import(
+                b, "env", "missing", i32_pair, None
+            ))),
+            [Issue::UnknownImport {
+                module: "env".to_owned(),
+                field: "missing".to_owned(),
+            }]
+        );
+        assert_eq!(
+            issues(&module(&VERSION_SECTION_PAYLOAD, |b| import(
+                b,
+                "other",
+                "set_process_name",
+                i32_pair,
+                None
+            ))),
+            [Issue::UnknownImport {
+                module: "other".to_owned(),
+                field: "set_process_name".to_owned(),
+            }]
+        );
+        assert_eq!(
+            issues(&module(&VERSION_SECTION_PAYLOAD, |b| import(
+                b,
+                "env",
+                "set_process_name",
+                i32_pair,
+                Some(WasmValueType::I32)
+            ))),
+            [Issue::ImportSignatureMismatch {
+                field: "set_process_name".to_owned(),
+                expected: "fn(i32, i32)".to_owned(),
+                found: "fn(i32, i32) -> i32".to_owned(),
+            }]
+        );
+        assert_eq!(
+            issues(&module(&[2, 0, 0, 0], |b| import(
+                b,
+                "env",
+                "push_mono_pointer_path",
+                &[WasmValueType::I32; 5],
+                Some(WasmValueType::I32)
+            ))),
+            [Issue::ImportTooNew {
+                field: "push_mono_pointer_path".to_owned(),
+                since: 8,
+                version: 2,
+            }]
+        );
+
+        let binary = module(&VERSION_SECTION_PAYLOAD, |b| {
+            b.with_import(ImportEntry::new(
+                "env".to_owned(),
+                "memory".to_owned(),
+                External::Memory(MemoryType::new(1, None)),
+            ))
+        });
+        assert_eq!(
+            issues(&binary),
+            [Issue::UnsupportedImport {
+                module: "env".to_owned(),
+                field: "memory".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn checks_exports() {
+        let binary = module(&VERSION_SECTION_PAYLOAD, |b| {
+            function(b, "game_time", &[], Some(WasmValueType::I32))
+        });
+        assert_eq!(
+            issues(&binary),
+            [Issue::ExportSignatureMismatch {
+                field: "game_time",
+                expected: "fn() -> f64".to_owned(),
+                found: "fn() -> i32".to_owned(),
+            }]
+        );
+
+        let mut binary = Vec::new();
+        builder::module()
+            .memory()
+            .build()
+            .build()
+            .serialize(&mut binary)
+            .unwrap();
+        let report = validate(&binary);
+        assert_eq!(
+            report.issues(),
+            &[
+                Issue::MissingExport("memory"),
+                Issue::MissingExport("configure"),
+                Issue::NoTimerExports,
+            ]
+        );
+        assert!(!report.is_valid());
+        assert!(!Issue::NoTimerExports.is_error());
+    }
+}
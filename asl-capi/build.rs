@@ -0,0 +1,23 @@
+extern crate cbindgen;
+
+use std::env;
+use std::path::Path;
+
+/// The runtime's types are parsed directly from its sources, as cbindgen can't
+/// resolve dependencies through newer lock file formats. The header is written
+/// next to the manifest, so C code can include it from a stable path.
+fn main() {
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let crate_dir = Path::new(&crate_dir);
+    let runtime_src = crate_dir.join("../asl-runtime/src/lib.rs");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=../asl-runtime/src");
+    cbindgen::Builder::new()
+        .with_config(cbindgen::Config::from_root_or_default(crate_dir))
+        .with_crate(crate_dir)
+        .with_src(&runtime_src)
+        .generate()
+        .expect("Unable to generate the C header")
+        .write_to_file(crate_dir.join("asl.h"));
+}
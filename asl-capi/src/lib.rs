@@ -1,41 +1,529 @@
 extern crate asl_runtime;
 
-use asl_runtime::{Runtime, TimerAction, TimerState};
-use std::ffi::CStr;
+use asl_runtime::{
+    AttachPolicy, Event, PointerPath, PointerSize, PointerType, PointerValue, ReadStatus, Runtime,
+    RuntimeHandle, Setting, TimerAction, TimerState,
+};
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::{CStr, CString};
 use std::fs;
-use std::os::raw::c_char;
+use std::os::raw::{c_char, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
 
-unsafe fn str(s: *const c_char) -> &'static str {
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(message: &str) {
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = message);
+}
+
+/// Runs the body of an entry point. Panics must not unwind into the host, so
+/// they get turned into an error and the default value gets returned instead.
+/// Release builds abort on panics, so the C API needs to be built with the
+/// `release-capi` profile for this to work.
+fn guard<T>(default: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => match payload.downcast_ref::<String>() {
+                Some(message) => message.clone(),
+                None => String::from("The runtime panicked"),
+            },
+        };
+        set_last_error(&message);
+        default
+    })
+}
+
+unsafe fn str<'a>(s: *const c_char) -> Option<&'a str> {
     if s.is_null() {
-        ""
+        Some("")
     } else {
-        CStr::from_ptr(s as _).to_str().unwrap()
+        match CStr::from_ptr(s as _).to_str() {
+            Ok(s) => Some(s),
+            Err(e) => {
+                set_last_error(&e.to_string());
+                None
+            }
+        }
     }
 }
 
+fn runtime_from_buffer(script: &[u8]) -> *mut Runtime {
+    match Runtime::new(script) {
+        Ok(runtime) => Box::into_raw(Box::new(runtime)),
+        Err(e) => {
+            set_last_error(&e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// The message of the most recent error on this thread. The string stays
+/// valid until the next error occurs on the same thread.
 #[no_mangle]
-pub unsafe extern "C" fn ASLRuntime_from_path(path: *const c_char) -> Option<Box<Runtime>> {
-    let script = fs::read(str(path)).ok()?;
-    Some(Box::new(Runtime::new(&script).ok()?))
+pub extern "C" fn ASL_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| last_error.borrow().as_ptr())
 }
 
 #[no_mangle]
-pub extern "C" fn ASLRuntime_drop(this: Box<Runtime>) {
-    drop(this);
+pub unsafe extern "C" fn ASLRuntime_from_path(path: *const c_char) -> *mut Runtime {
+    guard(ptr::null_mut(), || {
+        let path = match str(path) {
+            Some(path) => path,
+            None => return ptr::null_mut(),
+        };
+        let script = match fs::read(path) {
+            Ok(script) => script,
+            Err(e) => {
+                set_last_error(&e.to_string());
+                return ptr::null_mut();
+            }
+        };
+        runtime_from_buffer(&script)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ASLRuntime_from_buffer(
+    data: *const u8,
+    len: usize,
+) -> *mut Runtime {
+    guard(ptr::null_mut(), || {
+        if data.is_null() {
+            set_last_error("No buffer provided");
+            return ptr::null_mut();
+        }
+        runtime_from_buffer(slice::from_raw_parts(data, len))
+    })
+}
+
+fn action_code(action: &TimerAction) -> i32 {
+    match action {
+        TimerAction::Start => 1,
+        TimerAction::Split => 2,
+        TimerAction::Reset => 3,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ASLRuntime_drop(this: *mut Runtime) {
+    if !this.is_null() {
+        guard((), || drop(Box::from_raw(this)))
+    }
 }
 
 #[no_mangle]
 pub extern "C" fn ASLRuntime_step(this: &mut Runtime) -> i32 {
-    match this.step() {
-        Err(_) => -1,
+    guard(-1, || match this.step() {
+        Err(e) => {
+            set_last_error(&e.to_string());
+            -1
+        }
         Ok(None) => 0,
-        Ok(Some(TimerAction::Start)) => 1,
-        Ok(Some(TimerAction::Split)) => 2,
-        Ok(Some(TimerAction::Reset)) => 3,
-    }
+        Ok(Some(action)) => action_code(&action),
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn ASLRuntime_set_state(this: &mut Runtime, state: TimerState) {
+    guard((), || this.set_state(state))
+}
+
+fn query<T>(result: Result<Option<T>, Box<Error>>, out: &mut T) -> bool {
+    match result {
+        Ok(Some(value)) => {
+            *out = value;
+            true
+        }
+        Ok(None) => false,
+        Err(e) => {
+            set_last_error(&e.to_string());
+            false
+        }
+    }
+}
+
+/// Stores whether the game is loading in `out`. Returns `false` if the script
+/// doesn't track loading, no process is attached or the script failed.
+#[no_mangle]
+pub extern "C" fn ASLRuntime_is_loading(this: &mut Runtime, out: &mut bool) -> bool {
+    guard(false, || query(this.is_loading(), out))
+}
+
+/// Stores the game time in seconds in `out`. Returns `false` if the script
+/// doesn't track game time, no process is attached or the script failed.
+#[no_mangle]
+pub extern "C" fn ASLRuntime_game_time(this: &mut Runtime, out: &mut f64) -> bool {
+    guard(false, || query(this.game_time(), out))
+}
+
+#[no_mangle]
+pub extern "C" fn ASLRuntime_tick_rate(this: &Runtime) -> f64 {
+    this.tick_rate()
+}
+
+/// Overrides the script's tick rate. Passing a value that isn't positive goes
+/// back to the script's tick rate.
+#[no_mangle]
+pub extern "C" fn ASLRuntime_set_tick_rate(this: &mut Runtime, tick_rate: f64) {
+    this.set_tick_rate(Some(tick_rate))
+}
+
+#[no_mangle]
+pub extern "C" fn ASLRuntime_set_attach_policy(this: &mut Runtime, attach_policy: AttachPolicy) {
+    this.set_attach_policy(attach_policy)
+}
+
+#[no_mangle]
+pub extern "C" fn ASLRuntime_attach(this: &mut Runtime) -> bool {
+    guard(false, || this.attach())
+}
+
+#[no_mangle]
+pub extern "C" fn ASLRuntime_detach(this: &mut Runtime) {
+    guard((), || this.detach())
+}
+
+/// Registers a callback receiving the script's messages. The message is not
+/// nul-terminated and is only valid during the call.
+#[no_mangle]
+pub extern "C" fn ASLRuntime_set_log_callback(
+    this: &mut Runtime,
+    callback: extern "C" fn(*mut c_void, *const u8, usize),
+    user_data: *mut c_void,
+) {
+    guard((), || {
+        this.set_log_callback(move |message| callback(user_data, message.as_ptr(), message.len()))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn ASLRuntime_setting_count(this: &Runtime) -> usize {
+    this.settings().len()
+}
+
+#[no_mangle]
+pub extern "C" fn ASLRuntime_setting(this: &Runtime, index: usize) -> Option<&Setting> {
+    this.settings().get(index)
+}
+
+/// Returns whether the setting exists.
+#[no_mangle]
+pub unsafe extern "C" fn ASLRuntime_set_setting(
+    this: &mut Runtime,
+    key: *const c_char,
+    value: bool,
+) -> bool {
+    guard(false, || match str(key) {
+        Some(key) => this.set_setting(key, value),
+        None => false,
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Setting_key_ptr(this: &Setting) -> *const u8 {
+    this.key().as_ptr()
+}
+
+#[no_mangle]
+pub extern "C" fn Setting_key_len(this: &Setting) -> usize {
+    this.key().len()
+}
+
+#[no_mangle]
+pub extern "C" fn Setting_value(this: &Setting) -> bool {
+    this.value()
+}
+
+#[no_mangle]
+pub extern "C" fn Setting_default_value(this: &Setting) -> bool {
+    this.default_value()
+}
+
+#[no_mangle]
+pub extern "C" fn ASLRuntime_is_attached(this: &Runtime) -> bool {
+    this.is_attached()
+}
+
+#[no_mangle]
+pub extern "C" fn ASLRuntime_pointer_path_count(this: &Runtime) -> usize {
+    this.pointer_paths().len()
+}
+
+#[no_mangle]
+pub extern "C" fn ASLRuntime_pointer_path(this: &Runtime, index: usize) -> Option<&PointerPath> {
+    this.pointer_paths().get(index)
+}
+
+#[no_mangle]
+pub extern "C" fn PointerPath_name_ptr(this: &PointerPath) -> *const u8 {
+    this.name().as_ptr()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerPath_name_len(this: &PointerPath) -> usize {
+    this.name().len()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerPath_module_ptr(this: &PointerPath) -> *const u8 {
+    this.module_name().as_ptr()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerPath_module_len(this: &PointerPath) -> usize {
+    this.module_name().len()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerPath_offset_count(this: &PointerPath) -> usize {
+    this.offsets().len()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerPath_offset(this: &PointerPath, index: usize) -> i64 {
+    this.offsets().get(index).cloned().unwrap_or_default()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerPath_is_emulated(this: &PointerPath) -> bool {
+    this.is_emulated()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerPath_is_mono(this: &PointerPath) -> bool {
+    this.is_mono()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerPath_is_big_endian(this: &PointerPath) -> bool {
+    this.is_big_endian()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerPath_pointer_size(this: &PointerPath) -> PointerSize {
+    this.pointer_size()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerPath_type(this: &PointerPath) -> PointerType {
+    this.ty()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerPath_status(this: &PointerPath) -> ReadStatus {
+    this.status()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerPath_current(this: &PointerPath) -> &PointerValue {
+    this.current()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerPath_old(this: &PointerPath) -> &PointerValue {
+    this.old()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerValue_type(this: &PointerValue) -> PointerType {
+    this.ty()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerValue_as_i64(this: &PointerValue) -> i64 {
+    this.as_i64().unwrap_or_default()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerValue_as_f64(this: &PointerValue) -> f64 {
+    this.as_f64().unwrap_or_default()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerValue_str_ptr(this: &PointerValue) -> *const u8 {
+    this.as_str().unwrap_or_default().as_ptr()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerValue_str_len(this: &PointerValue) -> usize {
+    this.as_str().unwrap_or_default().len()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerValue_bytes_ptr(this: &PointerValue) -> *const u8 {
+    this.as_bytes().unwrap_or_default().as_ptr()
+}
+
+#[no_mangle]
+pub extern "C" fn PointerValue_bytes_len(this: &PointerValue) -> usize {
+    this.as_bytes().unwrap_or_default().len()
+}
+
+/// Starts a runtime for the script on a background thread. Its events are
+/// retrieved with `ASLRuntimeHandle_poll`.
+#[no_mangle]
+pub unsafe extern "C" fn ASLRuntimeHandle_from_buffer(
+    data: *const u8,
+    len: usize,
+) -> *mut RuntimeHandle {
+    guard(ptr::null_mut(), || {
+        if data.is_null() {
+            set_last_error("No buffer provided");
+            return ptr::null_mut();
+        }
+        match RuntimeHandle::spawn(slice::from_raw_parts(data, len).to_vec()) {
+            Ok(handle) => Box::into_raw(Box::new(handle)),
+            Err(e) => {
+                set_last_error(&e.to_string());
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Shuts down the background thread and waits for it to finish.
+#[no_mangle]
+pub unsafe extern "C" fn ASLRuntimeHandle_drop(this: *mut RuntimeHandle) {
+    if !this.is_null() {
+        guard((), || drop(Box::from_raw(this)))
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn ASLRuntimeHandle_set_state(this: &RuntimeHandle, state: TimerState) {
     this.set_state(state)
 }
+
+#[no_mangle]
+pub unsafe extern "C" fn ASLRuntimeHandle_set_setting(
+    this: &RuntimeHandle,
+    key: *const c_char,
+    value: bool,
+) -> bool {
+    guard(false, || match str(key) {
+        Some(key) => {
+            this.set_setting(key, value);
+            true
+        }
+        None => false,
+    })
+}
+
+/// Replaces the script. The outcome is reported as an event.
+#[no_mangle]
+pub unsafe extern "C" fn ASLRuntimeHandle_reload(
+    this: &RuntimeHandle,
+    data: *const u8,
+    len: usize,
+) -> bool {
+    guard(false, || {
+        if data.is_null() {
+            set_last_error("No buffer provided");
+            return false;
+        }
+        this.reload(slice::from_raw_parts(data, len).to_vec());
+        true
+    })
+}
+
+/// Returns the next event or null if there is none. The event needs to be
+/// freed with `Event_drop`.
+#[no_mangle]
+pub extern "C" fn ASLRuntimeHandle_poll(this: &RuntimeHandle) -> *mut Event {
+    guard(ptr::null_mut(), || match this.poll() {
+        Some(event) => Box::into_raw(Box::new(event)),
+        None => ptr::null_mut(),
+    })
+}
+
+#[repr(u8)]
+pub enum EventKind {
+    Action = 0,
+    Attached = 1,
+    Detached = 2,
+    Loading = 3,
+    GameTime = 4,
+    Log = 5,
+    Error = 6,
+    Reloaded = 7,
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn Event_drop(this: *mut Event) {
+    if !this.is_null() {
+        drop(Box::from_raw(this));
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn Event_kind(this: &Event) -> EventKind {
+    match this {
+        Event::Action(_) => EventKind::Action,
+        Event::Attached => EventKind::Attached,
+        Event::Detached => EventKind::Detached,
+        Event::Loading(_) => EventKind::Loading,
+        Event::GameTime(_) => EventKind::GameTime,
+        Event::Log(_) => EventKind::Log,
+        Event::Error(_) => EventKind::Error,
+        Event::Reloaded(_) => EventKind::Reloaded,
+    }
+}
+
+/// Uses the same codes as `ASLRuntime_step`.
+#[no_mangle]
+pub extern "C" fn Event_action(this: &Event) -> i32 {
+    match this {
+        Event::Action(action) => action_code(action),
+        _ => 0,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn Event_is_loading(this: &Event) -> bool {
+    match this {
+        Event::Loading(is_loading) => *is_loading,
+        _ => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn Event_game_time(this: &Event) -> f64 {
+    match this {
+        Event::GameTime(game_time) => *game_time,
+        _ => 0.0,
+    }
+}
+
+fn event_message(event: &Event) -> &str {
+    match event {
+        Event::Log(message) | Event::Error(message) => message.as_str(),
+        _ => "",
+    }
+}
+
+/// The message of a log or error event.
+#[no_mangle]
+pub extern "C" fn Event_message_ptr(this: &Event) -> *const u8 {
+    event_message(this).as_ptr()
+}
+
+#[no_mangle]
+pub extern "C" fn Event_message_len(this: &Event) -> usize {
+    event_message(this).len()
+}
+
+/// Whether a reload kept the process and all pointer paths.
+#[no_mangle]
+pub extern "C" fn Event_reload_is_compatible(this: &Event) -> bool {
+    match this {
+        Event::Reloaded(report) => report.is_compatible(),
+        _ => false,
+    }
+}
@@ -6,21 +6,32 @@ extern crate quote;
 use proc_macro::TokenStream;
 use syn::{Data, DeriveInput, Ident, Lit, LitInt, Meta, Type};
 
-#[proc_macro_derive(ASLState, attributes(Process, Pointer))]
+#[proc_macro_derive(ASLState, attributes(Process, Emulator, Pointer))]
 pub fn asl_state(input: TokenStream) -> TokenStream {
     let ast: DeriveInput = syn::parse(input).unwrap();
     let name = ast.ident;
-    let process = ast.attrs
-        .iter()
-        .filter_map(|x| x.interpret_meta())
-        .filter_map(|x| match x {
-            Meta::NameValue(nv) => Some(nv),
-            _ => None,
-        })
-        .filter(|x| x.ident == "Process")
-        .next()
-        .unwrap()
-        .lit;
+    let attrs = &ast.attrs;
+    let attr = |name: &str| {
+        attrs
+            .iter()
+            .filter_map(|x| x.interpret_meta())
+            .filter_map(|x| match x {
+                Meta::NameValue(nv) => Some(nv),
+                _ => None,
+            })
+            .filter(|x| x.ident == name)
+            .next()
+            .map(|x| x.lit)
+    };
+    let target = match (attr("Process"), attr("Emulator")) {
+        (Some(process), None) => quote! {
+            asl::set_process_name(#process);
+        },
+        (None, Some(emulator)) => quote! {
+            asl::set_emulator(#emulator);
+        },
+        _ => panic!("Either the Process or the Emulator attribute is required"),
+    };
 
     let struct_data = match ast.data {
         Data::Struct(s) => s,
@@ -46,21 +57,21 @@ pub fn asl_state(input: TokenStream) -> TokenStream {
             })
             .next()
             .unwrap();
-        let mut splits = pointer_path.split(',').map(|s| s.trim());
-        let module_name = splits.next().unwrap();
-
-        let offsets = splits
-            .map(|o| syn::parse_str::<LitInt>(o))
-            .collect::<Result<Vec<_>, _>>()
-            .unwrap();
-
-        let ty = match &field.ty {
+        // Byte arrays get read as a whole and are copied out of the buffer.
+        let (ty, len) = match &field.ty {
+            Type::Array(a) => (&*a.elem, Some(&a.len)),
+            ty => (ty, None),
+        };
+        let ty = match ty {
             Type::Path(p) => {
                 assert_eq!(p.path.segments.len(), 1, "Only builtin types are supported");
                 &p.path.segments.last().unwrap().value().ident
             }
             _ => panic!("Unsupported type"),
         };
+        if len.is_some() {
+            assert_eq!(ty.to_string(), "u8", "Only arrays of u8 are supported");
+        }
         let span = ty.span();
         let (ty, call) = match ty.to_string().as_str() {
             "u8" => ("U8", "get_u8"),
@@ -75,26 +86,97 @@ pub fn asl_state(input: TokenStream) -> TokenStream {
             "f64" => ("F64", "get_f64"),
             p => panic!("Unsupported type {}", p),
         };
-        let ty = Ident::new(ty, span);
+        let ty = Ident::new(if len.is_some() { "Bytes" } else { ty }, span);
         let call = Ident::new(call, span);
 
-        pointers.push(quote! {
-            asl::push_pointer_path(#module_name, &[#(#offsets),*], asl::PointerKind::#ty);
-        });
+        let name = ident.to_string();
 
-        fields_current.push(quote! {
-            #ident: asl::#call(#field_index, asl::State::Current)
-        });
+        // Pointer paths can end in `be` or `le` to specify their endianness
+        // and in `ptr32` or `ptr64` to specify the width of their pointers.
+        let mut pointer_path = pointer_path.trim();
+        let (mut endianness, mut pointer_size) = ("Little", "Process");
+        while let Some(index) = pointer_path.rfind(char::is_whitespace) {
+            match &pointer_path[index + 1..] {
+                "be" => endianness = "Big",
+                "le" => endianness = "Little",
+                "ptr32" => pointer_size = "Bits32",
+                "ptr64" => pointer_size = "Bits64",
+                _ => break,
+            }
+            pointer_path = pointer_path[..index].trim_right();
+        }
+        let endianness = Ident::new(endianness, span);
+        let pointer_size = Ident::new(pointer_size, span);
+
+        let emulated = pointer_path.split_whitespace().collect::<Vec<_>>();
+        let push = if !pointer_path.contains(',') && emulated.len() == 3 && emulated[0] == "mono" {
+            // Mono pointer paths look like
+            // `mono Assembly-CSharp GameManager.instance.level`.
+            let (assembly, path) = (emulated[1], emulated[2]);
+            quote! {
+                asl::push_mono_pointer_path(#name, #assembly, #path, asl::PointerKind::#ty, asl::Endianness::#endianness, asl::PointerSize::#pointer_size)
+            }
+        } else if !pointer_path.contains(',') && emulated.len() == 2 {
+            // Emulated pointer paths look like `wram 0xC362`.
+            let region = emulated[0];
+            let address = syn::parse_str::<LitInt>(emulated[1]).unwrap();
+            quote! {
+                asl::push_emulator_pointer_path(#name, #region, #address, asl::PointerKind::#ty, asl::Endianness::#endianness, asl::PointerSize::#pointer_size)
+            }
+        } else {
+            let mut splits = pointer_path.split(',').map(|s| s.trim());
+            let module_name = splits.next().unwrap();
+
+            let offsets = splits
+                .map(|o| syn::parse_str::<LitInt>(o))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
 
-        fields_old.push(quote! {
-            #ident: asl::#call(#field_index, asl::State::Old)
-        });
+            quote! {
+                asl::push_pointer_path(#name, #module_name, &[#(#offsets),*], asl::PointerKind::#ty, asl::Endianness::#endianness, asl::PointerSize::#pointer_size)
+            }
+        };
+
+        if let Some(len) = len {
+            pointers.push(quote! {
+                let id = #push;
+                asl::set_pointer_path_size(id, #len);
+            });
+
+            fields_current.push(quote! {
+                #ident: {
+                    let mut buf = [0; #len];
+                    asl::get_bytes(#field_index, asl::State::Current, 0, &mut buf);
+                    buf
+                }
+            });
+
+            fields_old.push(quote! {
+                #ident: {
+                    let mut buf = [0; #len];
+                    asl::get_bytes(#field_index, asl::State::Old, 0, &mut buf);
+                    buf
+                }
+            });
+        } else {
+            pointers.push(quote! {
+                #push;
+            });
+
+            fields_current.push(quote! {
+                #ident: asl::#call(#field_index, asl::State::Current)
+            });
+
+            fields_old.push(quote! {
+                #ident: asl::#call(#field_index, asl::State::Old)
+            });
+        }
     }
 
     let tokens = quote! {
         #[no_mangle]
         pub extern "C" fn configure() {
-            asl::set_process_name(#process);
+            #target
             #(#pointers)*
         }
 
@@ -1,7 +1,7 @@
 extern crate asl_lang;
 
 use asl_lang::parity_wasm::serialize;
-use asl_lang::{Error, Hover, Result, Span};
+use asl_lang::{Diagnostic, Error, Hover, Label, Result, Severity, Span};
 use std::ffi::CStr;
 use std::fmt::Write;
 use std::os::raw::c_char;
@@ -31,9 +31,25 @@ unsafe fn str(s: *const c_char) -> &'static str {
 
 pub type FFIResult<T> = Result<Option<Box<T>>>;
 
+/// The compiled module, unless there were errors, along with all the errors
+/// and warnings.
+pub struct Compilation {
+    module: Option<Vec<u8>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
 #[no_mangle]
-pub unsafe extern "C" fn ASL_compile(text: *const c_char) -> Box<FFIResult<Vec<u8>>> {
-    Box::new(asl_lang::compile(str(text)).map(|m| Some(Box::new(serialize(m).unwrap()))))
+pub unsafe extern "C" fn ASL_compile(text: *const c_char) -> Box<Compilation> {
+    Box::new(match asl_lang::compile(str(text)) {
+        Ok((module, diagnostics)) => Compilation {
+            module: Some(serialize(module).unwrap()),
+            diagnostics,
+        },
+        Err(diagnostics) => Compilation {
+            module: None,
+            diagnostics,
+        },
+    })
 }
 
 #[no_mangle]
@@ -93,6 +109,73 @@ pub extern "C" fn Error_span(this: &Error) -> Option<&Span> {
     this.span.as_ref()
 }
 
+#[no_mangle]
+pub extern "C" fn Compilation_module(this: &Compilation) -> Option<&Vec<u8>> {
+    this.module.as_ref()
+}
+
+#[no_mangle]
+pub extern "C" fn Compilation_diagnostics(this: &Compilation) -> &Vec<Diagnostic> {
+    &this.diagnostics
+}
+
+#[no_mangle]
+pub extern "C" fn Compilation_drop(this: Box<Compilation>) {
+    drop(this)
+}
+
+#[no_mangle]
+pub extern "C" fn Diagnostics_get(this: &Vec<Diagnostic>, index: usize) -> Option<&Diagnostic> {
+    this.get(index)
+}
+
+#[no_mangle]
+pub extern "C" fn Diagnostic_is_error(this: &Diagnostic) -> bool {
+    this.severity == Severity::Error
+}
+
+#[no_mangle]
+pub extern "C" fn Diagnostic_msg_ptr(this: &Diagnostic) -> *const u8 {
+    this.message.as_ptr()
+}
+
+#[no_mangle]
+pub extern "C" fn Diagnostic_msg_len(this: &Diagnostic) -> usize {
+    this.message.len()
+}
+
+#[no_mangle]
+pub extern "C" fn Diagnostic_span(this: &Diagnostic) -> Option<&Span> {
+    this.span.as_ref()
+}
+
+/// The secondary spans of the diagnostic. Some diagnostics don't have a span
+/// of their own and only point at their labels.
+#[no_mangle]
+pub extern "C" fn Diagnostic_labels(this: &Diagnostic) -> &Vec<Label> {
+    &this.labels
+}
+
+#[no_mangle]
+pub extern "C" fn Labels_get(this: &Vec<Label>, index: usize) -> Option<&Label> {
+    this.get(index)
+}
+
+#[no_mangle]
+pub extern "C" fn Label_msg_ptr(this: &Label) -> *const u8 {
+    this.message.as_ptr()
+}
+
+#[no_mangle]
+pub extern "C" fn Label_msg_len(this: &Label) -> usize {
+    this.message.len()
+}
+
+#[no_mangle]
+pub extern "C" fn Label_span(this: &Label) -> &Span {
+    &this.span
+}
+
 #[no_mangle]
 pub extern "C" fn Hover_ty(this: &Hover) -> Box<Vec<u8>> {
     let text = if let Some(params) = &this.params {
@@ -111,6 +194,12 @@ pub extern "C" fn Hover_ty(this: &Hover) -> Box<Vec<u8>> {
     Box::new(text.into_bytes())
 }
 
+/// Returns the doc comment of what is hovered, if it has one.
+#[no_mangle]
+pub extern "C" fn Hover_docs(this: &Hover) -> Option<Box<Vec<u8>>> {
+    this.docs.clone().map(|docs| Box::new(docs.into_bytes()))
+}
+
 #[no_mangle]
 pub extern "C" fn Hover_span(this: &Hover) -> &Span {
     &this.span
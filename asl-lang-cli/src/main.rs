@@ -1,10 +1,51 @@
 extern crate asl_lang;
 
-use std::fs;
+mod report;
+
 use asl_lang::parity_wasm::serialize_to_file;
+use asl_lang::Severity;
+use std::fs;
+use std::process;
+
+const SCRIPT_PATH: &str = "script.asl";
+const OUTPUT_PATH: &str = "out.wasm";
 
 fn main() {
-    let script = fs::read_to_string("script.asl").unwrap();
-    let module = asl_lang::compile(&script).unwrap();
-    serialize_to_file("out.wasm", module).unwrap();
+    let script = match fs::read_to_string(SCRIPT_PATH) {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("error: Couldn't read {}: {}", SCRIPT_PATH, e);
+            process::exit(1);
+        }
+    };
+
+    let result = asl_lang::compile(&script);
+    let diagnostics = match &result {
+        Ok((_, diagnostics)) | Err(diagnostics) => diagnostics,
+    };
+    for diagnostic in diagnostics {
+        eprintln!("{}", report::render(diagnostic, SCRIPT_PATH, &script));
+    }
+
+    let module = match result {
+        Ok((module, _)) => module,
+        Err(diagnostics) => {
+            let errors = diagnostics
+                .iter()
+                .filter(|d| d.severity == Severity::Error)
+                .count();
+            eprintln!(
+                "error: Couldn't compile {} due to {} error{}",
+                SCRIPT_PATH,
+                errors,
+                if errors == 1 { "" } else { "s" }
+            );
+            process::exit(1);
+        }
+    };
+
+    if let Err(e) = serialize_to_file(OUTPUT_PATH, module) {
+        eprintln!("error: Couldn't write {}: {:?}", OUTPUT_PATH, e);
+        process::exit(1);
+    }
 }
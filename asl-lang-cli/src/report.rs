@@ -0,0 +1,120 @@
+use asl_lang::{Diagnostic, Severity, Span};
+use std::cmp::max;
+use std::fmt::Write;
+
+/// A span to underline, along with what to underline it with.
+struct Mark<'a> {
+    span: &'a Span,
+    underline: char,
+    message: &'a str,
+}
+
+/// Renders the diagnostic with the lines of code it points at. The code it is
+/// about is underlined with `^`, other code that plays a part in it with `-`.
+pub fn render(diagnostic: &Diagnostic, path: &str, src: &str) -> String {
+    let mut out = String::new();
+
+    let severity = match diagnostic.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    match diagnostic.code {
+        Some(code) => writeln!(out, "{}[{}]: {}", severity, code, diagnostic.message),
+        None => writeln!(out, "{}: {}", severity, diagnostic.message),
+    }
+    .unwrap();
+
+    let mut marks = Vec::new();
+    if let Some(span) = &diagnostic.span {
+        marks.push(Mark {
+            span,
+            underline: '^',
+            message: "",
+        });
+    }
+    for label in &diagnostic.labels {
+        marks.push(Mark {
+            span: &label.span,
+            underline: '-',
+            message: &label.message,
+        });
+    }
+
+    let mut line_numbers = marks.iter().map(|m| m.span.from.0).collect::<Vec<_>>();
+    line_numbers.sort();
+    line_numbers.dedup();
+    let width = line_numbers.last().map_or(1, |l| l.to_string().len());
+    let gutter = " ".repeat(width);
+
+    // Diagnostics without a span of their own point at their first label.
+    match marks.first() {
+        Some(mark) => writeln!(
+            out,
+            "{}--> {}:{}:{}",
+            gutter, path, mark.span.from.0, mark.span.from.1
+        ),
+        None => writeln!(out, "{}--> {}", gutter, path),
+    }
+    .unwrap();
+
+    if !line_numbers.is_empty() {
+        writeln!(out, "{} |", gutter).unwrap();
+    }
+
+    let lines = src.lines().collect::<Vec<_>>();
+    let mut previous_line_number = None;
+    for &line_number in &line_numbers {
+        if let Some(previous) = previous_line_number {
+            if line_number > previous + 1 {
+                writeln!(out, "...").unwrap();
+            }
+        }
+        previous_line_number = Some(line_number);
+
+        let line = lines.get(line_number - 1).cloned().unwrap_or("");
+        writeln!(out, "{:>width$} | {}", line_number, line, width = width).unwrap();
+
+        for mark in marks.iter().filter(|m| m.span.from.0 == line_number) {
+            writeln!(out, "{} | {}", gutter, underline(line, mark)).unwrap();
+        }
+    }
+
+    if !line_numbers.is_empty() && !diagnostic.notes.is_empty() {
+        writeln!(out, "{} |", gutter).unwrap();
+    }
+    for note in &diagnostic.notes {
+        writeln!(out, "{} = note: {}", gutter, note).unwrap();
+    }
+
+    out
+}
+
+fn underline(line: &str, mark: &Mark) -> String {
+    // The columns count bytes and the spans end exclusively. Spans that go on
+    // to the next lines are underlined until the end of the first line.
+    let start = mark.span.from.1 - 1;
+    let end = if mark.span.to.0 == mark.span.from.0 {
+        mark.span.to.1 - 1
+    } else {
+        line.len()
+    };
+
+    // Tabs are kept, so the underline lines up with the code.
+    let mut underline = line
+        .get(..start)
+        .unwrap_or(line)
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    let len = line
+        .get(start..end)
+        .map_or(end.saturating_sub(start), |s| s.chars().count());
+    for _ in 0..max(len, 1) {
+        underline.push(mark.underline);
+    }
+    if !mark.message.is_empty() {
+        underline.push(' ');
+        underline.push_str(mark.message);
+    }
+    underline
+}
@@ -1,5 +1,7 @@
-use error::{RangeError, RangeResult};
+use debug_info::SrcByteRange;
+use error::{ErrorCode, RangeError, RangeResult};
 use specs::prelude::*;
+use std::fmt;
 use types::Ty;
 
 #[derive(Debug)]
@@ -9,13 +11,172 @@ pub struct Source {
 
 impl Source {
     pub fn state(&self) -> RangeResult<&State> {
-        self.items
+        let state = self
+            .items
             .iter()
             .filter_map(|i| match i {
                 Item::State(s) => Some(s),
                 _ => None,
             }).next()
-            .ok_or_else(|| RangeError::new("You need at least one state block"))
+            .ok_or_else(|| {
+                RangeError::new(ErrorCode::StateBlock, "You need at least one state block")
+            })?;
+
+        if let Target::Process(_) = state.target {
+            if let Some(path) = state.paths.iter().find(|p| p.is_emulated()) {
+                return Err(RangeError::new(ErrorCode::StateBlock, format!(
+                    "The state variable '{}' points into emulated memory, but the state block doesn't declare an emulator",
+                    path.name
+                )));
+            }
+        }
+
+        if let Target::Emulator(_) = state.target {
+            if let Some(path) = state.paths.iter().find(|p| p.is_mono()) {
+                return Err(RangeError::new(
+                    ErrorCode::StateBlock,
+                    format!(
+                        "The state variable '{}' reads from Mono, which emulated systems don't run",
+                        path.name
+                    ),
+                ));
+            }
+        }
+
+        for path in &state.paths {
+            if let PathTy::Scalar(_) = path.ty {
+                continue;
+            }
+            self.size_of(&path.ty)?;
+            if path.big_endian {
+                return Err(RangeError::new(
+                    ErrorCode::StateBlock,
+                    format!(
+                        "The state variable '{}' can't be big endian, only numbers can",
+                        path.name
+                    ),
+                ));
+            }
+        }
+
+        Ok(state)
+    }
+
+    pub fn layout(&self, name: &str) -> RangeResult<&Layout> {
+        self.items
+            .iter()
+            .filter_map(|i| match i {
+                Item::Layout(l) if l.name == name => Some(l),
+                _ => None,
+            }).next()
+            .ok_or_else(|| {
+                RangeError::new(
+                    ErrorCode::Unresolved,
+                    format!("Unresolved layout '{}'", name),
+                )
+            })
+    }
+
+    /// The amount of bytes a value of the type takes up in the game's memory.
+    pub fn size_of(&self, ty: &PathTy) -> RangeResult<u32> {
+        self.size_of_inner(ty, &mut Vec::new())
+    }
+
+    fn size_of_inner<'a>(&'a self, ty: &'a PathTy, layouts: &mut Vec<&'a str>) -> RangeResult<u32> {
+        match ty {
+            PathTy::Scalar(ty) => match ty {
+                Ty::U8 | Ty::I8 => Ok(1),
+                Ty::U16 | Ty::I16 => Ok(2),
+                Ty::U32 | Ty::I32 | Ty::F32 => Ok(4),
+                Ty::U64 | Ty::I64 | Ty::F64 => Ok(8),
+                _ => Err(RangeError::new(
+                    ErrorCode::StateBlock,
+                    format!("The type {} can't be read from the game's memory", ty),
+                )),
+            },
+            PathTy::Array(ty, len) => self
+                .size_of_inner(ty, layouts)?
+                .checked_mul(*len)
+                .ok_or_else(|| RangeError::new(ErrorCode::StateBlock, "The array is too large")),
+            PathTy::Layout(name) => {
+                if layouts.contains(&name.as_str()) {
+                    return Err(RangeError::new(
+                        ErrorCode::StateBlock,
+                        format!("The layout '{}' contains itself", name),
+                    ));
+                }
+                let layout = self.layout(name)?;
+                layouts.push(name);
+                let mut size = 0;
+                for field in &layout.fields {
+                    let field_size = self.size_of_inner(&field.ty, layouts)?;
+                    size = size.max(field.offset.saturating_add(field_size));
+                }
+                layouts.pop();
+                Ok(size)
+            }
+        }
+    }
+
+    /// Resolves which number of a state variable the accessors refer to.
+    pub fn element(&self, path: &PointerPath, accessors: &[Accessor]) -> RangeResult<Element> {
+        let (mut ty, mut offset, mut strides) = (&path.ty, 0, Vec::new());
+        for accessor in accessors {
+            match (accessor, ty) {
+                (Accessor::Index, PathTy::Array(element, _)) => {
+                    strides.push(self.size_of(element)?);
+                    ty = &**element;
+                }
+                (Accessor::Field(name), PathTy::Layout(layout)) => {
+                    let field = self
+                        .layout(layout)?
+                        .fields
+                        .iter()
+                        .find(|f| f.name == *name)
+                        .ok_or_else(|| {
+                            RangeError::new(
+                                ErrorCode::StateAccess,
+                                format!("The layout '{}' has no field '{}'", layout, name),
+                            )
+                        })?;
+                    offset += field.offset;
+                    ty = &field.ty;
+                }
+                (Accessor::Index, _) => {
+                    return Err(RangeError::new(
+                        ErrorCode::StateAccess,
+                        format!(
+                            "Only arrays can be indexed, but '{}' is accessed as one",
+                            path.name
+                        ),
+                    ))
+                }
+                (Accessor::Field(name), _) => {
+                    return Err(RangeError::new(
+                        ErrorCode::StateAccess,
+                        format!(
+                            "Only layouts have fields, but the field '{}' of '{}' is accessed",
+                            name, path.name
+                        ),
+                    ))
+                }
+            }
+        }
+
+        match ty {
+            PathTy::Scalar(ty) => Ok(Element {
+                ty: ty.clone(),
+                offset,
+                strides,
+            }),
+            _ => Err(RangeError::new(
+                ErrorCode::StateAccess,
+                format!(
+                    "The state variable '{}' needs to be accessed down to a number",
+                    path.name
+                ),
+            )),
+        }
     }
 
     pub fn code_items<'s>(&'s self) -> impl Iterator<Item = (Option<ActionKind>, Entity)> + 's {
@@ -30,6 +191,8 @@ impl Source {
 #[derive(Debug)]
 pub enum Item {
     State(State),
+    Layout(Layout),
+    Struct(Struct),
     Action(ActionKind, Entity),
     Function(Entity),
 }
@@ -45,16 +208,153 @@ pub enum ActionKind {
 
 #[derive(Debug)]
 pub struct State {
-    pub process: String,
+    pub target: Target,
     pub paths: Vec<PointerPath>,
 }
 
+/// What the runtime attaches to.
+#[derive(Debug)]
+pub enum Target {
+    Process(String),
+    /// The name of an emulated system, like `gb`.
+    Emulator(String),
+}
+
 #[derive(Debug)]
 pub struct PointerPath {
     pub name: String,
+    pub range: SrcByteRange,
+    pub docs: Option<String>,
+    pub ty: PathTy,
+    pub location: Location,
+    pub big_endian: bool,
+    pub pointer_size: Option<PointerSize>,
+}
+
+/// The type of a value in the game's memory.
+#[derive(Debug)]
+pub enum PathTy {
+    Scalar(Ty),
+    Array(Box<PathTy>, u32),
+    /// The name of a layout.
+    Layout(String),
+}
+
+/// Describes where the fields of a struct in the game's memory are.
+#[derive(Debug)]
+pub struct Layout {
+    pub name: String,
+    pub fields: Vec<LayoutField>,
+}
+
+#[derive(Debug)]
+pub struct LayoutField {
+    pub name: String,
+    pub ty: PathTy,
+    pub offset: u32,
+}
+
+/// A struct that values in the code can have as their type.
+#[derive(Debug)]
+pub struct Struct {
+    pub name: String,
+    pub fields: Vec<StructField>,
+}
+
+#[derive(Debug)]
+pub struct StructField {
+    pub name: String,
+    pub ty: Option<Ty>,
+}
+
+/// A field of a struct or a tuple.
+#[derive(Debug, Clone)]
+pub enum Field {
+    Name(String),
+    Index(usize),
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Field::Name(name) => write!(f, "{}", name),
+            Field::Index(index) => write!(f, "{}", index),
+        }
+    }
+}
+
+/// Accesses an element of an array or a field of a layout.
+#[derive(Debug)]
+pub enum Access {
+    Index(Entity),
+    Field(String),
+}
+
+/// An `Access` without the expression of the index.
+#[derive(Debug, Clone)]
+pub enum Accessor {
+    Index,
+    Field(String),
+}
+
+/// Where a number inside the value of a state variable is. Each index moves
+/// the offset by its stride.
+#[derive(Debug)]
+pub struct Element {
     pub ty: Ty,
-    pub module: String,
-    pub offsets: Vec<i64>,
+    pub offset: u32,
+    pub strides: Vec<u32>,
+}
+
+/// Overrides the width of the pointers along a pointer path, which otherwise
+/// matches the process.
+#[derive(Debug, Copy, Clone)]
+pub enum PointerSize {
+    Bits32,
+    Bits64,
+}
+
+/// Changes how a pointer path gets read.
+#[derive(Debug, Copy, Clone)]
+pub enum PointerPathOption {
+    BigEndian(bool),
+    PointerSize(PointerSize),
+}
+
+#[derive(Debug)]
+pub enum Location {
+    Module {
+        module: String,
+        offsets: Vec<i64>,
+    },
+    /// An address in a memory region of the emulated system.
+    Emulated {
+        region: String,
+        address: i64,
+    },
+    /// A static field of a class of a Unity game running on Mono, followed by
+    /// the fields of the objects along the way.
+    Mono {
+        assembly: String,
+        class: String,
+        fields: Vec<String>,
+    },
+}
+
+impl PointerPath {
+    pub fn is_emulated(&self) -> bool {
+        match self.location {
+            Location::Emulated { .. } => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_mono(&self) -> bool {
+        match self.location {
+            Location::Mono { .. } => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Component)]
@@ -64,12 +364,22 @@ pub struct Children(pub Vec<Entity>);
 #[storage(NullStorage)]
 pub struct Scoped;
 
+/// Marks a block, whose children are its statements in the order they run in.
+#[derive(Component, Default)]
+#[storage(NullStorage)]
+pub struct Block;
+
 impl State {
     pub fn lookup(&self, field_name: &str) -> RangeResult<&PointerPath> {
         self.paths
             .iter()
             .find(|p| p.name == *field_name)
-            .ok_or_else(|| RangeError::new(format!("Unresolved state variable '{}'", field_name)))
+            .ok_or_else(|| {
+                RangeError::new(
+                    ErrorCode::Unresolved,
+                    format!("Unresolved state variable '{}'", field_name),
+                )
+            })
     }
 
     pub fn lookup_index(&self, field_name: &str) -> usize {
@@ -1,15 +1,70 @@
-use ast::{ActionKind, Source, State};
+use asl_abi;
+use ast::{
+    Accessor, ActionKind, Element, Field, Location, PathTy, PointerPath, PointerSize, Source,
+    Target,
+};
 use function_indexing::FunctionIndex;
 use function_signatures::FunctionSignatureRegisters;
 use name_resolution::Vars;
 use parity_wasm::{
     builder::{ImportBuilder, ModuleBuilder, SignatureBuilder},
-    elements::{BlockType, Instruction, Instructions, Local, Module, ValueType},
+    elements::{
+        BlockType, CustomSection, Instruction, Instructions, Local, Module, Section, ValueType,
+    },
 };
 use reg_alloc::{FunctionRegisters, Registers};
 use reg_extend::NeedsExtending;
 use specs::prelude::*;
-use types::Ty;
+use std::collections::HashMap;
+use types::{Tuple, Ty};
+
+/// The functions of the runtime compiled scripts import. Their position is
+/// their function index.
+const IMPORTS: &[&str] = &[
+    "set_process_name",
+    "push_pointer_path",
+    "push_offset",
+    "get_u8",
+    "get_u16",
+    "get_u32",
+    "get_u64",
+    "get_i8",
+    "get_i16",
+    "get_i32",
+    "get_i64",
+    "get_f32",
+    "get_f64",
+    "set_pointer_path_name",
+    "read_memory",
+    "set_emulator",
+    "push_emulator_pointer_path",
+    "set_pointer_path_size",
+    "get_bytes",
+    "get_module_address",
+    "get_module_size",
+    "get_module_export",
+    "get_memory_region",
+    "push_mono_pointer_path",
+];
+
+fn import_index(name: &str) -> u32 {
+    IMPORTS.iter().position(|&import| import == name).unwrap() as u32
+}
+
+/// The `configure` function comes right after the imports and is followed by
+/// the functions of the script.
+pub fn configure_fn_index() -> u32 {
+    IMPORTS.len() as u32
+}
+
+fn value_type(ty: &asl_abi::ValueType) -> ValueType {
+    match ty {
+        asl_abi::ValueType::I32 => ValueType::I32,
+        asl_abi::ValueType::I64 => ValueType::I64,
+        asl_abi::ValueType::F32 => ValueType::F32,
+        asl_abi::ValueType::F64 => ValueType::F64,
+    }
+}
 
 pub enum Op {
     Entity(Entity),
@@ -21,6 +76,8 @@ pub enum Op {
     RShift,
     Not,
     Neg,
+    /// Combines two booleans that are both already evaluated. `&&` and `||`
+    /// short circuit instead.
     BoolOr,
     BoolAnd,
     BitOr,
@@ -42,14 +99,37 @@ pub enum Op {
     Cast(Entity),
     Br(u32),
     BrIf(u32),
+    Break(usize),
+    Continue(usize),
+    Return,
+    Unreachable,
     ConstInt(i64),
     ConstFloat(f64),
     ConstBool(bool),
     Drop,
     LoadVar(usize),
     StoreVar(usize),
+    LoadField(usize, Field),
     StateVar(bool, String),
+    StateVarElement(bool, String, Vec<Accessor>),
+    StateVarIndex(String, Vec<Accessor>, usize),
+    ReadStateVarElement(String, Vec<Accessor>),
     Call(usize),
+    ReadOffsetAddress(Entity, usize),
+    StoreOffset,
+    ReadMemory(Entity, String, usize),
+    ModuleAddress(String),
+    ModuleSize(String),
+    ModuleExport(String, String),
+    MemoryRegion(MemoryRegionField),
+}
+
+/// The field of the memory region returned by `get_memory_region`.
+#[derive(Copy, Clone)]
+pub enum MemoryRegionField {
+    Base,
+    Size,
+    Protection,
 }
 
 #[derive(Component)]
@@ -57,6 +137,21 @@ pub struct CodeGenDesc(pub Vec<Op>);
 
 pub struct CodeGen<'s>(pub &'s Source, pub Option<Module>);
 
+/// Describes where the code can find the strings it references in the data
+/// section and where its scratch memory is. The scratch memory holds the value
+/// of a one-off memory read or a memory region. Each read also has its own
+/// area for its list of offsets, as its offsets may contain reads themselves.
+/// Functions and blocks can only result in a single value, so values of more
+/// than one register leave them through globals instead. Comparing such values
+/// also temporarily stores them in the globals. The globals are laid out like
+/// the registers of a function.
+struct MemoryLayout {
+    strings: HashMap<String, u32>,
+    scratch: u32,
+    offset_areas: HashMap<Entity, u32>,
+    value_globals: FunctionRegisters,
+}
+
 impl<'a, 's> System<'a> for CodeGen<'s> {
     type SystemData = (
         ReadStorage<'a, CodeGenDesc>,
@@ -83,48 +178,11 @@ impl<'a, 's> System<'a> for CodeGen<'s> {
         ): Self::SystemData,
     ) {
         let state = self.0.state().unwrap();
-
-        macro_rules! sig {
-            ($ty:ident) => {
-                SignatureBuilder::new()
-                    .params()
-                    .i32()
-                    .i32()
-                    .build()
-                    .return_type()
-                    .$ty()
-                    .build_sig()
-            };
+        let (target_fn, target) = match &state.target {
+            Target::Process(process) => (import_index("set_process_name"), process),
+            Target::Emulator(emulator) => (import_index("set_emulator"), emulator),
         };
 
-        let sigs = vec![
-            SignatureBuilder::new()
-                .params()
-                .i32()
-                .i32()
-                .build()
-                .build_sig(),
-            SignatureBuilder::new()
-                .params()
-                .i32()
-                .i32()
-                .i32()
-                .build()
-                .return_type()
-                .i32()
-                .build_sig(),
-            SignatureBuilder::new()
-                .params()
-                .i32()
-                .i64()
-                .build()
-                .build_sig(),
-            sig!(i32),
-            sig!(i64),
-            sig!(f32),
-            sig!(f64),
-        ];
-
         let mut builder = ModuleBuilder::new()
             .memory()
             .with_min(1)
@@ -134,87 +192,123 @@ impl<'a, 's> System<'a> for CodeGen<'s> {
             .internal()
             .memory(0)
             .build()
-            .with_signatures(sigs)
-            .import()
-            .module("env")
-            .field("set_process_name")
-            .external()
-            .func(0)
-            .build()
-            .import()
-            .module("env")
-            .field("push_pointer_path")
-            .external()
-            .func(1)
-            .build()
-            .import()
-            .module("env")
-            .field("push_offset")
-            .external()
-            .func(2)
-            .build()
             .data()
             .offset(Instruction::I32Const(0))
-            .value(state.process.as_bytes().to_vec())
+            .value(target.as_bytes().to_vec())
             .build();
 
-        macro_rules! import {
-            ($name:expr, $idx:expr) => {
-                builder.push_import(
-                    ImportBuilder::new()
-                        .module("env")
-                        .field($name)
-                        .external()
-                        .func($idx)
-                        .build(),
-                );
-            };
-        };
-
-        import!("get_u8", 3);
-        import!("get_u16", 3);
-        import!("get_u32", 3);
-        import!("get_u64", 4);
-        import!("get_i8", 3);
-        import!("get_i16", 3);
-        import!("get_i32", 3);
-        import!("get_i64", 4);
-        import!("get_f32", 5);
-        import!("get_f64", 6);
+        for &name in IMPORTS {
+            let import = asl_abi::import(name).unwrap();
+            let signature = SignatureBuilder::new()
+                .with_params(import.params.iter().map(value_type).collect())
+                .with_return_type(import.result.as_ref().map(value_type))
+                .build_sig();
+            // Identical signatures get merged, so the builder decides which
+            // type the import refers to.
+            let type_ref = builder.push_signature(signature);
+            builder.push_import(
+                ImportBuilder::new()
+                    .module(asl_abi::IMPORT_MODULE)
+                    .field(name)
+                    .external()
+                    .func(type_ref)
+                    .build(),
+            );
+        }
 
         let mut builder = builder
             .export()
             .field("configure")
             .internal()
-            .func(13)
+            .func(configure_fn_index())
             .build();
 
         let mut configure_fn = vec![
             Instruction::I32Const(0),
-            Instruction::I32Const(state.process.len() as i32),
-            Instruction::Call(0),
+            Instruction::I32Const(target.len() as i32),
+            Instruction::Call(target_fn),
         ];
 
-        let mut data_section_offset = state.process.len();
+        let mut data_section_offset = target.len();
         for (id, path) in state.paths.iter().enumerate() {
-            configure_fn.push(Instruction::I32Const(data_section_offset as i32));
-            configure_fn.push(Instruction::I32Const(path.module.len() as i32));
-            configure_fn.push(Instruction::I32Const(0)); // TODO Type
-            configure_fn.push(Instruction::Call(1));
-            configure_fn.push(Instruction::Drop);
+            let pointer_type = pointer_type(&path.ty) | pointer_flags(path);
+            let base = match &path.location {
+                Location::Module { module, offsets } => {
+                    configure_fn.push(Instruction::I32Const(data_section_offset as i32));
+                    configure_fn.push(Instruction::I32Const(module.len() as i32));
+                    configure_fn.push(Instruction::I32Const(pointer_type));
+                    configure_fn.push(Instruction::Call(import_index("push_pointer_path")));
+                    configure_fn.push(Instruction::Drop);
 
-            for offset in &path.offsets {
-                configure_fn.push(Instruction::I32Const(id as i32));
-                configure_fn.push(Instruction::I64Const(*offset));
-                configure_fn.push(Instruction::Call(2));
+                    for offset in offsets {
+                        configure_fn.push(Instruction::I32Const(id as i32));
+                        configure_fn.push(Instruction::I64Const(*offset));
+                        configure_fn.push(Instruction::Call(import_index("push_offset")));
+                    }
+
+                    module.clone()
+                }
+                Location::Emulated { region, address } => {
+                    configure_fn.push(Instruction::I32Const(data_section_offset as i32));
+                    configure_fn.push(Instruction::I32Const(region.len() as i32));
+                    configure_fn.push(Instruction::I64Const(*address));
+                    configure_fn.push(Instruction::I32Const(pointer_type));
+                    configure_fn.push(Instruction::Call(import_index(
+                        "push_emulator_pointer_path",
+                    )));
+                    configure_fn.push(Instruction::Drop);
+
+                    region.clone()
+                }
+                Location::Mono {
+                    assembly,
+                    class,
+                    fields,
+                } => {
+                    // The field chain is stored right after the assembly.
+                    let chain = format!("{}.{}", class, fields.join("."));
+                    configure_fn.push(Instruction::I32Const(data_section_offset as i32));
+                    configure_fn.push(Instruction::I32Const(assembly.len() as i32));
+                    configure_fn.push(Instruction::I32Const(
+                        (data_section_offset + assembly.len()) as i32,
+                    ));
+                    configure_fn.push(Instruction::I32Const(chain.len() as i32));
+                    configure_fn.push(Instruction::I32Const(pointer_type));
+                    configure_fn.push(Instruction::Call(import_index("push_mono_pointer_path")));
+                    configure_fn.push(Instruction::Drop);
+
+                    assembly.clone() + &chain
+                }
+            };
+
+            match path.ty {
+                PathTy::Scalar(_) => {}
+                _ => {
+                    let size = self.0.size_of(&path.ty).unwrap();
+                    configure_fn.push(Instruction::I32Const(id as i32));
+                    configure_fn.push(Instruction::I32Const(size as i32));
+                    configure_fn.push(Instruction::Call(import_index("set_pointer_path_size")));
+                }
             }
 
             builder = builder
                 .data()
                 .offset(Instruction::I32Const(data_section_offset as i32))
-                .value(path.module.as_bytes().to_vec())
+                .value(base.as_bytes().to_vec())
                 .build();
-            data_section_offset += path.module.len();
+            data_section_offset += base.len();
+
+            configure_fn.push(Instruction::I32Const(id as i32));
+            configure_fn.push(Instruction::I32Const(data_section_offset as i32));
+            configure_fn.push(Instruction::I32Const(path.name.len() as i32));
+            configure_fn.push(Instruction::Call(import_index("set_pointer_path_name")));
+
+            builder = builder
+                .data()
+                .offset(Instruction::I32Const(data_section_offset as i32))
+                .value(path.name.as_bytes().to_vec())
+                .build();
+            data_section_offset += path.name.len();
         }
 
         configure_fn.push(Instruction::End);
@@ -228,12 +322,120 @@ impl<'a, 's> System<'a> for CodeGen<'s> {
             .build()
             .build();
 
+        let (mut strings, mut offset_areas) = (HashMap::new(), Vec::new());
+        for CodeGenDesc(ops) in (&codegen_descs).join() {
+            for op in ops {
+                let referenced = match op {
+                    Op::ReadMemory(area, module, offset_count) => {
+                        offset_areas.push((*area, *offset_count));
+                        vec![module]
+                    }
+                    Op::ModuleAddress(module) | Op::ModuleSize(module) => vec![module],
+                    Op::ModuleExport(module, symbol) => vec![module, symbol],
+                    _ => continue,
+                };
+                for string in referenced {
+                    if !strings.contains_key(string) {
+                        strings.insert(string.clone(), data_section_offset as u32);
+                        builder = builder
+                            .data()
+                            .offset(Instruction::I32Const(data_section_offset as i32))
+                            .value(string.as_bytes().to_vec())
+                            .build();
+                        data_section_offset += string.len();
+                    }
+                }
+            }
+        }
+
+        let scratch = ((data_section_offset + 7) & !7) as u32;
+        // Memory regions are the largest thing stored in the scratch memory.
+        let mut area_address = scratch + 24;
+        let offset_areas = offset_areas
+            .into_iter()
+            .map(|(area, offset_count)| {
+                let address = area_address;
+                area_address += 8 * offset_count as u32;
+                (area, address)
+            }).collect();
+
+        let mut value_globals = FunctionRegisters::default();
+        {
+            let mut reserve = |ty: &Ty| {
+                let mut registers = FunctionRegisters::default();
+                for value_type in ty.value_types() {
+                    match value_type {
+                        ValueType::I32 => registers.i32s += 1,
+                        ValueType::I64 => registers.i64s += 1,
+                        ValueType::F32 => registers.f32s += 1,
+                        ValueType::F64 => registers.f64s += 1,
+                    }
+                }
+                value_globals.i32s = value_globals.i32s.max(registers.i32s);
+                value_globals.i64s = value_globals.i64s.max(registers.i64s);
+                value_globals.f32s = value_globals.f32s.max(registers.f32s);
+                value_globals.f64s = value_globals.f64s.max(registers.f64s);
+            };
+
+            for (fn_kind, fn_entity) in self.0.code_items() {
+                let ty = types.get(fn_entity).unwrap();
+                if fn_kind.is_none() && ty.value_types().len() > 1 {
+                    reserve(ty);
+                }
+            }
+
+            for (CodeGenDesc(ops), ty) in (&codegen_descs, &types).join() {
+                if ty.value_types().len() <= 1 {
+                    continue;
+                }
+                for op in ops {
+                    match op {
+                        Op::If | Op::Else | Op::End | Op::Block | Op::Break(_) | Op::Return => {
+                            reserve(ty)
+                        }
+                        Op::Eq | Op::Ne => reserve(&pair(ty)),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        for (count, value_type) in &[
+            (value_globals.i32s, ValueType::I32),
+            (value_globals.i64s, ValueType::I64),
+            (value_globals.f32s, ValueType::F32),
+            (value_globals.f64s, ValueType::F64),
+        ] {
+            let init = match value_type {
+                ValueType::I32 => Instruction::I32Const(0),
+                ValueType::I64 => Instruction::I64Const(0),
+                ValueType::F32 => Instruction::F32Const(0),
+                ValueType::F64 => Instruction::F64Const(0),
+            };
+            for _ in 0..*count {
+                builder = builder
+                    .global()
+                    .with_type(*value_type)
+                    .mutable()
+                    .init_expr(init.clone())
+                    .build();
+            }
+        }
+
+        let layout = MemoryLayout {
+            strings,
+            scratch,
+            offset_areas,
+            value_globals,
+        };
+
         for (fn_kind, fn_entity) in self.0.code_items() {
             let FunctionIndex(fn_index) = function_indices.get(fn_entity).unwrap();
             builder = build_action(
                 *fn_index,
                 fn_kind,
-                state,
+                self.0,
+                &layout,
                 builder,
                 &codegen_descs,
                 &types,
@@ -247,14 +449,56 @@ impl<'a, 's> System<'a> for CodeGen<'s> {
             );
         }
 
-        self.1 = Some(builder.build());
+        let mut module = builder.build();
+        let mut version = CustomSection::default();
+        *version.name_mut() = asl_abi::VERSION_SECTION.to_owned();
+        *version.payload_mut() = asl_abi::VERSION_SECTION_PAYLOAD.to_vec();
+        module.sections_mut().push(Section::Custom(version));
+
+        self.1 = Some(module);
+    }
+}
+
+fn pointer_type(ty: &PathTy) -> i32 {
+    let ty = match ty {
+        PathTy::Scalar(ty) => ty,
+        // Arrays and layouts are read as a whole into a buffer of bytes.
+        _ => return 11,
+    };
+    match ty {
+        Ty::U8 => 0,
+        Ty::U16 => 1,
+        Ty::U32 => 2,
+        Ty::U64 => 3,
+        Ty::I8 => 4,
+        Ty::I16 => 5,
+        Ty::I32 => 6,
+        Ty::I64 => 7,
+        Ty::F32 => 8,
+        Ty::F64 => 9,
+        _ => panic!("Unsupported state variable type"),
+    }
+}
+
+/// The flags stored in the upper bits of the pointer type.
+fn pointer_flags(path: &PointerPath) -> i32 {
+    let mut flags = 0;
+    if path.big_endian {
+        flags |= asl_abi::BIG_ENDIAN_FLAG;
+    }
+    match path.pointer_size {
+        Some(PointerSize::Bits32) => flags |= asl_abi::POINTER_SIZE_32_FLAG,
+        Some(PointerSize::Bits64) => flags |= asl_abi::POINTER_SIZE_64_FLAG,
+        None => {}
     }
+    flags as i32
 }
 
 fn build_action(
     fn_idx: u32,
     fn_kind: Option<ActionKind>,
-    state: &State,
+    source: &Source,
+    layout: &MemoryLayout,
     builder: ModuleBuilder,
     codegen_descs: &ReadStorage<CodeGenDesc>,
     types: &ReadStorage<Ty>,
@@ -266,24 +510,33 @@ fn build_action(
     function_signatures: &ReadStorage<FunctionSignatureRegisters>,
     entity: Entity,
 ) -> ModuleBuilder {
+    let ty = types.get(entity).unwrap();
     let (ret_type, name, params) = match fn_kind {
         Some(ActionKind::Start) => (Some(ValueType::I32), Some("should_start"), Vec::new()),
         Some(ActionKind::Split) => (Some(ValueType::I32), Some("should_split"), Vec::new()),
         Some(ActionKind::Reset) => (Some(ValueType::I32), Some("should_reset"), Vec::new()),
         Some(ActionKind::IsLoading) => (Some(ValueType::I32), Some("is_loading"), Vec::new()),
         Some(ActionKind::GameTime) => (Some(ValueType::F64), Some("game_time"), Vec::new()),
-        None => (
-            unimplemented!(),
-            // types.get(entity).unwrap().reg_type(),
-            None,
-            function_signatures.get(entity).unwrap().0.clone(),
-        ),
+        None => {
+            let value_types = ty.value_types();
+            (
+                if value_types.len() == 1 {
+                    Some(value_types[0])
+                } else {
+                    None
+                },
+                None,
+                function_signatures.get(entity).unwrap().0.clone(),
+            )
+        }
     };
 
     let mut instructions = Vec::new();
     code_gen(
         &mut instructions,
-        state,
+        &mut Vec::new(),
+        source,
+        layout,
         codegen_descs,
         types,
         vars,
@@ -292,6 +545,9 @@ fn build_action(
         function_indices,
         entity,
     );
+    if fn_kind.is_none() && ty.value_types().len() > 1 {
+        store_value_globals(&mut instructions, ty, layout);
+    }
     instructions.push(Instruction::End);
 
     let mut builder = builder
@@ -313,6 +569,57 @@ fn build_action(
     builder
 }
 
+/// The globals a value of more than one register gets stored in.
+fn value_globals(ty: &Ty, value_globals: &FunctionRegisters) -> Vec<u32> {
+    let mut indices = FunctionRegisters {
+        i32s: 0,
+        i64s: value_globals.i32s,
+        f32s: value_globals.i32s + value_globals.i64s,
+        f64s: value_globals.i32s + value_globals.i64s + value_globals.f32s,
+    };
+    let mut globals = Vec::new();
+    for value_type in ty.value_types() {
+        let index = match value_type {
+            ValueType::I32 => &mut indices.i32s,
+            ValueType::I64 => &mut indices.i64s,
+            ValueType::F32 => &mut indices.f32s,
+            ValueType::F64 => &mut indices.f64s,
+        };
+        globals.push(*index);
+        *index += 1;
+    }
+    globals
+}
+
+fn store_value_globals(instructions: &mut Vec<Instruction>, ty: &Ty, layout: &MemoryLayout) {
+    for global in value_globals(ty, &layout.value_globals).into_iter().rev() {
+        instructions.push(Instruction::SetGlobal(global));
+    }
+}
+
+fn load_value_globals(instructions: &mut Vec<Instruction>, ty: &Ty, layout: &MemoryLayout) {
+    for global in value_globals(ty, &layout.value_globals) {
+        instructions.push(Instruction::GetGlobal(global));
+    }
+}
+
+/// The block type of a block resulting in a value of the type and whether the
+/// value needs to be passed through the value globals instead.
+fn block_type(ty: &Ty) -> (BlockType, bool) {
+    if ty.value_types().len() > 1 {
+        (BlockType::NoResult, true)
+    } else if let Some(val_ty) = ty.value_type() {
+        (BlockType::Value(val_ty), false)
+    } else {
+        (BlockType::NoResult, false)
+    }
+}
+
+/// Both sides of a comparison of values of the type.
+fn pair(ty: &Ty) -> Ty {
+    Ty::Tuple(Tuple::new(vec![Some(ty.clone()), Some(ty.clone())]))
+}
+
 fn build_locals(entity: Entity, function_registers: &ReadStorage<FunctionRegisters>) -> Vec<Local> {
     let regs = function_registers.get(entity).unwrap();
     let mut locals = Vec::new();
@@ -331,9 +638,15 @@ fn build_locals(entity: Entity, function_registers: &ReadStorage<FunctionRegiste
     locals
 }
 
+/// The blocks that are currently open, along with the entity that opened them
+/// and whether they pass their value through the value globals.
+type Frames = Vec<(Entity, bool)>;
+
 fn code_gen(
     instructions: &mut Vec<Instruction>,
-    state: &State,
+    frames: &mut Frames,
+    source: &Source,
+    layout: &MemoryLayout,
     codegen_descs: &ReadStorage<CodeGenDesc>,
     types: &ReadStorage<Ty>,
     vars: &ReadStorage<Vars>,
@@ -348,7 +661,9 @@ fn code_gen(
         match op {
             Op::Entity(child) => code_gen(
                 instructions,
-                state,
+                frames,
+                source,
+                layout,
                 codegen_descs,
                 types,
                 vars,
@@ -470,6 +785,11 @@ fn code_gen(
                 };
                 instructions.push(ins);
             }
+            Op::Eq if ty.value_types().len() > 1 => lower_value_eq(instructions, ty, layout),
+            Op::Ne if ty.value_types().len() > 1 => {
+                lower_value_eq(instructions, ty, layout);
+                instructions.push(Instruction::I32Eqz);
+            }
             Op::Eq => {
                 let ins = match ty.value_type() {
                     Some(ValueType::I32) => Instruction::I32Eq,
@@ -553,7 +873,7 @@ fn code_gen(
             }
             Op::ConstBool(val) => instructions.push(Instruction::I32Const(*val as i32)),
             Op::Drop => {
-                if *ty != Ty::Unit {
+                for _ in ty.value_types() {
                     instructions.push(Instruction::Drop);
                 }
             }
@@ -577,53 +897,115 @@ fn code_gen(
                     }
                 }
             }
+            Op::LoadField(i, field) => {
+                let var = vars.get(entity).unwrap().0[*i];
+                let range = types.get(var).unwrap().field_registers(field);
+                if let Some(Registers(registers)) = registers_storage.get(var) {
+                    for register in &registers[range] {
+                        if let Some((_, idx)) = register {
+                            instructions.push(Instruction::GetLocal(*idx));
+                        }
+                    }
+                }
+            }
             Op::StateVar(is_current, name) => {
-                let index = state.lookup_index(name);
+                let index = source.state().unwrap().lookup_index(name);
                 instructions.push(Instruction::I32Const(index as i32));
                 instructions.push(Instruction::I32Const(*is_current as i32));
-                let ins = match ty {
-                    Ty::U8 => 3,
-                    Ty::U16 => 4,
-                    Ty::U32 => 5,
-                    Ty::U64 => 6,
-                    Ty::I8 => 7,
-                    Ty::I16 => 8,
-                    Ty::I32 => 9,
-                    Ty::I64 => 10,
-                    Ty::F32 => 11,
-                    Ty::F64 => 12,
+                let getter = match ty {
+                    Ty::U8 => "get_u8",
+                    Ty::U16 => "get_u16",
+                    Ty::U32 => "get_u32",
+                    Ty::U64 => "get_u64",
+                    Ty::I8 => "get_i8",
+                    Ty::I16 => "get_i16",
+                    Ty::I32 => "get_i32",
+                    Ty::I64 => "get_i64",
+                    Ty::F32 => "get_f32",
+                    Ty::F64 => "get_f64",
                     _ => panic!("Unsupported state variable type"),
                 };
-                instructions.push(Instruction::Call(ins));
+                instructions.push(Instruction::Call(import_index(getter)));
+            }
+            Op::StateVarElement(is_current, name, accessors) => {
+                let element = state_var_element(source, name, accessors);
+                let index = source.state().unwrap().lookup_index(name);
+                instructions.push(Instruction::I32Const(index as i32));
+                instructions.push(Instruction::I32Const(*is_current as i32));
+                instructions.push(Instruction::I32Const(element.offset as i32));
+            }
+            Op::StateVarIndex(name, accessors, index) => {
+                let element = state_var_element(source, name, accessors);
+                instructions.push(Instruction::I32Const(element.strides[*index] as i32));
+                instructions.push(Instruction::I32Mul);
+                instructions.push(Instruction::I32Add);
+            }
+            Op::ReadStateVarElement(name, accessors) => {
+                let element = state_var_element(source, name, accessors);
+                let (size, load) = load_instruction(&element.ty);
+                instructions.push(Instruction::I32Const(layout.scratch as i32));
+                instructions.push(Instruction::I32Const(size));
+                instructions.push(Instruction::Call(import_index("get_bytes")));
+                instructions.push(Instruction::I32Const(layout.scratch as i32));
+                instructions.push(load);
             }
             Op::If => {
-                let block_ty = if let Some(val_ty) = ty.value_type() {
-                    BlockType::Value(val_ty)
-                } else {
-                    BlockType::NoResult
-                };
+                let (block_ty, uses_globals) = block_type(ty);
+                frames.push((entity, uses_globals));
                 instructions.push(Instruction::If(block_ty));
             }
-            Op::Else => instructions.push(Instruction::Else),
-            Op::End => instructions.push(Instruction::End),
-            Op::Block => {
-                let block_ty = if let Some(val_ty) = ty.value_type() {
-                    BlockType::Value(val_ty)
+            Op::Else => {
+                if frames.last().unwrap().1 {
+                    store_value_globals(instructions, ty, layout);
+                }
+                instructions.push(Instruction::Else);
+            }
+            Op::End => {
+                let (_, uses_globals) = frames.pop().unwrap();
+                if uses_globals {
+                    store_value_globals(instructions, ty, layout);
+                    instructions.push(Instruction::End);
+                    load_value_globals(instructions, ty, layout);
                 } else {
-                    BlockType::NoResult
-                };
+                    instructions.push(Instruction::End);
+                }
+            }
+            Op::Block => {
+                let (block_ty, uses_globals) = block_type(ty);
+                frames.push((entity, uses_globals));
                 instructions.push(Instruction::Block(block_ty));
             }
             Op::Loop => {
-                let block_ty = if let Some(val_ty) = ty.value_type() {
-                    BlockType::Value(val_ty)
-                } else {
-                    BlockType::NoResult
-                };
-                instructions.push(Instruction::Loop(block_ty));
+                // Loops are only ever left by branching out of the block
+                // around them, so they never result in a value themselves.
+                frames.push((entity, false));
+                instructions.push(Instruction::Loop(BlockType::NoResult));
             }
             Op::Br(count) => instructions.push(Instruction::Br(*count)),
             Op::BrIf(count) => instructions.push(Instruction::BrIf(*count)),
+            Op::Break(var_id) => {
+                // Breaking leaves the outermost block of the loop.
+                let target = vars.get(entity).unwrap().0[*var_id];
+                let index = frames.iter().position(|&(e, _)| e == target).unwrap();
+                if frames[index].1 {
+                    store_value_globals(instructions, ty, layout);
+                }
+                instructions.push(Instruction::Br((frames.len() - 1 - index) as u32));
+            }
+            Op::Continue(var_id) => {
+                // Continuing leaves the innermost block of the loop, which
+                // either is the loop itself or finishes the iteration.
+                let target = vars.get(entity).unwrap().0[*var_id];
+                let index = frames.iter().rposition(|&(e, _)| e == target).unwrap();
+                instructions.push(Instruction::Br((frames.len() - 1 - index) as u32));
+            }
+            Op::Return => {
+                if ty.value_types().len() > 1 {
+                    store_value_globals(instructions, ty, layout);
+                }
+                instructions.push(Instruction::Return);
+            }
+            Op::Unreachable => instructions.push(Instruction::Unreachable),
             Op::Cast(expr) => {
                 let from_ty = types.get(*expr).unwrap();
                 lower_cast(
@@ -648,11 +1030,89 @@ fn code_gen(
                 let fn_entity = vars.get(entity).unwrap().0[*fn_var_id];
                 let FunctionIndex(fn_idx) = function_indices.get(fn_entity).unwrap();
                 instructions.push(Instruction::Call(*fn_idx));
+                let fn_ty = types.get(fn_entity).unwrap();
+                if fn_ty.value_types().len() > 1 {
+                    load_value_globals(instructions, fn_ty, layout);
+                }
+            }
+            Op::ReadOffsetAddress(area, index) => {
+                let address = layout.offset_areas[area] + 8 * *index as u32;
+                instructions.push(Instruction::I32Const(address as i32));
+            }
+            Op::StoreOffset => instructions.push(Instruction::I64Store(3, 0)),
+            Op::ReadMemory(area, module, offset_count) => {
+                let module_ptr = layout.strings[module];
+                let (size, load) = load_instruction(ty);
+                instructions.push(Instruction::I32Const(module_ptr as i32));
+                instructions.push(Instruction::I32Const(module.len() as i32));
+                instructions.push(Instruction::I32Const(layout.offset_areas[area] as i32));
+                instructions.push(Instruction::I32Const(*offset_count as i32));
+                instructions.push(Instruction::I32Const(layout.scratch as i32));
+                instructions.push(Instruction::I32Const(size));
+                instructions.push(Instruction::Call(import_index("read_memory")));
+                // The runtime zeroes the value if reading fails, so we don't
+                // need to look at whether it succeeded.
+                instructions.push(Instruction::Drop);
+                instructions.push(Instruction::I32Const(layout.scratch as i32));
+                instructions.push(load);
+            }
+            Op::ModuleAddress(module) => {
+                instructions.push(Instruction::I32Const(layout.strings[module] as i32));
+                instructions.push(Instruction::I32Const(module.len() as i32));
+                instructions.push(Instruction::Call(import_index("get_module_address")));
+            }
+            Op::ModuleSize(module) => {
+                instructions.push(Instruction::I32Const(layout.strings[module] as i32));
+                instructions.push(Instruction::I32Const(module.len() as i32));
+                instructions.push(Instruction::Call(import_index("get_module_size")));
+            }
+            Op::ModuleExport(module, symbol) => {
+                instructions.push(Instruction::I32Const(layout.strings[module] as i32));
+                instructions.push(Instruction::I32Const(module.len() as i32));
+                instructions.push(Instruction::I32Const(layout.strings[symbol] as i32));
+                instructions.push(Instruction::I32Const(symbol.len() as i32));
+                instructions.push(Instruction::Call(import_index("get_module_export")));
+            }
+            Op::MemoryRegion(field) => {
+                instructions.push(Instruction::I32Const(layout.scratch as i32));
+                instructions.push(Instruction::Call(import_index("get_memory_region")));
+                // The runtime zeroes the region if there is none, so we don't
+                // need to look at whether there is one.
+                instructions.push(Instruction::Drop);
+                instructions.push(Instruction::I32Const(layout.scratch as i32));
+                instructions.push(match field {
+                    MemoryRegionField::Base => Instruction::I64Load(3, 0),
+                    MemoryRegionField::Size => Instruction::I64Load(3, 8),
+                    MemoryRegionField::Protection => Instruction::I32Load(2, 16),
+                });
             }
         }
     }
 }
 
+fn state_var_element(source: &Source, name: &str, accessors: &[Accessor]) -> Element {
+    let path = source.state().unwrap().lookup(name).unwrap();
+    source.element(path, accessors).unwrap()
+}
+
+/// The size of a value of the type and the instruction that loads it from
+/// memory.
+fn load_instruction(ty: &Ty) -> (i32, Instruction) {
+    match ty {
+        Ty::U8 => (1, Instruction::I32Load8U(0, 0)),
+        Ty::U16 => (2, Instruction::I32Load16U(1, 0)),
+        Ty::U32 => (4, Instruction::I32Load(2, 0)),
+        Ty::U64 => (8, Instruction::I64Load(3, 0)),
+        Ty::I8 => (1, Instruction::I32Load8S(0, 0)),
+        Ty::I16 => (2, Instruction::I32Load16S(1, 0)),
+        Ty::I32 => (4, Instruction::I32Load(2, 0)),
+        Ty::I64 => (8, Instruction::I64Load(3, 0)),
+        Ty::F32 => (4, Instruction::F32Load(2, 0)),
+        Ty::F64 => (8, Instruction::F64Load(3, 0)),
+        _ => panic!("Unsupported type to read"),
+    }
+}
+
 fn lower_cast(
     instructions: &mut Vec<Instruction>,
     from_ty: &Ty,
@@ -817,6 +1277,32 @@ fn lower_cast(
     }
 }
 
+/// Compares two values of more than one register that are on the stack by
+/// comparing each of their registers.
+fn lower_value_eq(instructions: &mut Vec<Instruction>, ty: &Ty, layout: &MemoryLayout) {
+    let globals = value_globals(&pair(ty), &layout.value_globals);
+    for global in globals.iter().rev() {
+        instructions.push(Instruction::SetGlobal(*global));
+    }
+    let scalars = ty.scalars();
+    let (left, right) = globals.split_at(scalars.len());
+    for (i, scalar) in scalars.iter().enumerate() {
+        instructions.push(Instruction::GetGlobal(left[i]));
+        lower_extend(instructions, scalar);
+        instructions.push(Instruction::GetGlobal(right[i]));
+        lower_extend(instructions, scalar);
+        instructions.push(match scalar.value_type().unwrap() {
+            ValueType::I32 => Instruction::I32Eq,
+            ValueType::I64 => Instruction::I64Eq,
+            ValueType::F32 => Instruction::F32Eq,
+            ValueType::F64 => Instruction::F64Eq,
+        });
+        if i > 0 {
+            instructions.push(Instruction::I32And);
+        }
+    }
+}
+
 fn lower_extend(instructions: &mut Vec<Instruction>, ty: &Ty) {
     match ty {
         Ty::U8 => lower_zext(instructions, 0xFF),
@@ -1,10 +1,11 @@
+use ast::Source;
 use name_resolution::DeclaredBy;
 use name_resolution::FunctionDecl;
 use name_resolution::Vars;
 use specs::prelude::*;
-use types::Ty;
+use types::{Inference, Ty, TypeChecking};
 
-#[derive(Copy, Clone, Debug, Component)]
+#[derive(Copy, Clone, Debug, PartialEq, Component)]
 #[storage(DenseVecStorage)]
 pub struct SrcByteRange(pub usize, pub usize);
 
@@ -20,6 +21,10 @@ impl SrcByteRange {
 #[derive(Component)]
 pub struct ReferencesVar(pub usize);
 
+/// The doc comment of a function.
+#[derive(Component)]
+pub struct Docs(pub String);
+
 fn line_column(src: &str, byte_pos: usize) -> (usize, usize) {
     let (mut total_bytes, mut line, mut column) = (0, 1, 1);
     for row in src.split_terminator('\n') {
@@ -56,6 +61,7 @@ pub struct Hover {
     pub entity: Entity,
     pub params: Option<Vec<Ty>>,
     pub ty: Ty,
+    pub docs: Option<String>,
     pub span: Span,
 }
 
@@ -64,12 +70,14 @@ pub struct HoverSystem<'s> {
     column: usize,
     result: Option<Hover>,
     src: &'s str,
+    source: &'s Source,
 }
 
 impl<'s> HoverSystem<'s> {
-    pub fn new(src: &'s str, line: usize, column: usize) -> Self {
+    pub fn new(src: &'s str, source: &'s Source, line: usize, column: usize) -> Self {
         Self {
             src,
+            source,
             line,
             column,
             result: None,
@@ -100,9 +108,25 @@ impl<'a, 's> System<'a> for HoverSystem<'s> {
         ReadStorage<'a, SrcByteRange>,
         ReadStorage<'a, Ty>,
         ReadStorage<'a, FunctionDecl>,
+        ReadStorage<'a, Docs>,
+        ReadStorage<'a, ReferencesVar>,
+        ReadStorage<'a, Vars>,
+        ReadStorage<'a, TypeChecking>,
     );
 
-    fn run(&mut self, (entities, ranges, types, function_decls): Self::SystemData) {
+    fn run(
+        &mut self,
+        (
+            entities,
+            ranges,
+            types,
+            function_decls,
+            docs,
+            references_vars,
+            vars,
+            type_checking,
+        ): Self::SystemData,
+    ) {
         let byte_pos = self.byte_pos();
         let (mut min_ty, mut min_from, mut min_len) = (None, 0, usize::max_value());
         for (entity, SrcByteRange(from, to), ty) in (&*entities, &ranges, &types).join() {
@@ -127,20 +151,59 @@ impl<'a, 's> System<'a> for HoverSystem<'s> {
                 entity,
                 params,
                 ty: ty.clone(),
+                docs: self.docs(entity, &docs, &references_vars, &vars, &type_checking),
                 span: SrcByteRange(min_from, min_from + min_len).to_span(self.src),
             });
         }
     }
 }
 
+impl<'s> HoverSystem<'s> {
+    /// Looks up the doc comment of the function or state variable that is
+    /// either declared or referred to.
+    fn docs(
+        &self,
+        entity: Entity,
+        docs: &ReadStorage<Docs>,
+        references_vars: &ReadStorage<ReferencesVar>,
+        vars: &ReadStorage<Vars>,
+        type_checking: &ReadStorage<TypeChecking>,
+    ) -> Option<String> {
+        if let Some(Docs(docs)) = docs.get(entity) {
+            return Some(docs.clone());
+        }
+        if let (Some(ReferencesVar(var_index)), Some(Vars(vars))) =
+            (references_vars.get(entity), vars.get(entity))
+        {
+            if let Some(Docs(docs)) = docs.get(vars[*var_index]) {
+                return Some(docs.clone());
+            }
+        }
+        let TypeChecking(inference_list) = type_checking.get(entity)?;
+        for inference in inference_list {
+            if let Inference::StateVarSameAsMe(field_name, _) = inference {
+                let path = self.source.state().ok()?.lookup(field_name).ok()?;
+                return path.docs.clone();
+            }
+        }
+        None
+    }
+}
+
 pub struct GoToDefinition {
     hover: Hover,
     result: Option<(Entity, SrcByteRange)>,
 }
 
 impl GoToDefinition {
-    pub fn run(src: &str, world: &World, line: usize, column: usize) -> Option<(Entity, Span)> {
-        let hover = HoverSystem::new(src, line, column).run(world)?;
+    pub fn run(
+        src: &str,
+        source: &Source,
+        world: &World,
+        line: usize,
+        column: usize,
+    ) -> Option<(Entity, Span)> {
+        let hover = HoverSystem::new(src, source, line, column).run(world)?;
         let mut system = Self {
             hover,
             result: None,
@@ -181,8 +244,14 @@ pub struct FindAllVariableReferences<'s> {
 }
 
 impl<'s> FindAllVariableReferences<'s> {
-    pub fn run(src: &'s str, world: &World, line: usize, column: usize) -> Option<Vec<Span>> {
-        let hover = HoverSystem::new(src, line, column).run(world)?;
+    pub fn run(
+        src: &'s str,
+        source: &Source,
+        world: &World,
+        line: usize,
+        column: usize,
+    ) -> Option<Vec<Span>> {
+        let hover = HoverSystem::new(src, source, line, column).run(world)?;
         let mut system = Self {
             src,
             hover_entity: hover.entity,
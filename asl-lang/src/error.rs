@@ -1,37 +1,175 @@
 use debug_info::{Span, SrcByteRange};
 use specs::prelude::*;
+use std::fmt;
+
+/// Identifies the kind of an error. The codes never change, so they can be
+/// searched for and documented.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ErrorCode {
+    /// The code doesn't follow the grammar of the language.
+    Syntax,
+    /// The state block or one of its state variables is invalid.
+    StateBlock,
+    /// A state variable is accessed in a way its type doesn't allow.
+    StateAccess,
+    /// A name doesn't refer to anything that is declared.
+    Unresolved,
+    /// A jump has no loop to jump out of.
+    Jump,
+    /// A struct declaration or a struct literal is invalid.
+    Struct,
+    /// Two types that need to be the same aren't.
+    TypeConflict,
+    /// A field is accessed on a type that doesn't have it.
+    UnknownField,
+    /// A function is called with the wrong amount of arguments.
+    ArgumentCount,
+}
+
+impl ErrorCode {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorCode::Syntax => "E0001",
+            ErrorCode::StateBlock => "E0002",
+            ErrorCode::StateAccess => "E0003",
+            ErrorCode::Unresolved => "E0004",
+            ErrorCode::Jump => "E0005",
+            ErrorCode::Struct => "E0006",
+            ErrorCode::TypeConflict => "E0007",
+            ErrorCode::UnknownField => "E0008",
+            ErrorCode::ArgumentCount => "E0009",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
 
 #[derive(Debug)]
 pub struct RangeError {
+    pub code: ErrorCode,
     pub message: String,
     pub range: Option<SrcByteRange>,
+    pub labels: Vec<(SrcByteRange, String)>,
+    pub notes: Vec<String>,
 }
 
 pub type RangeResult<T> = ::std::result::Result<T, RangeError>;
 
+/// Points at some other code that plays a part in an error.
+#[derive(Debug)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
 #[derive(Debug)]
 pub struct Error {
+    pub code: ErrorCode,
     pub message: String,
     pub span: Option<Span>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
 }
 
 impl RangeError {
-    pub fn new(message: impl Into<String>) -> Self {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
         Self {
+            code,
             message: message.into(),
             range: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
         }
     }
+    pub fn with_label(mut self, range: Option<SrcByteRange>, message: impl Into<String>) -> Self {
+        if let Some(range) = range {
+            self.labels.push((range, message.into()));
+        }
+        self
+    }
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
     pub fn spanned(self, src: &str) -> Error {
         Error {
+            code: self.code,
             message: self.message,
             span: self.range.map(|r| r.to_span(src)),
+            labels: self
+                .labels
+                .into_iter()
+                .map(|(range, message)| Label {
+                    span: range.to_span(src),
+                    message,
+                }).collect(),
+            notes: self.notes,
         }
     }
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
 
+#[derive(Debug)]
+pub struct RangeWarning {
+    pub message: String,
+    pub range: Option<SrcByteRange>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Severity {
+    Error,
+    /// Points out code that is most likely a mistake, but still compiles.
+    Warning,
+}
+
+/// An error or a warning reported while compiling. Only errors have a code.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<ErrorCode>,
+    pub message: String,
+    pub span: Option<Span>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl From<Error> for Diagnostic {
+    fn from(error: Error) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: Some(error.code),
+            message: error.message,
+            span: error.span,
+            labels: error.labels,
+            notes: error.notes,
+        }
+    }
+}
+
+impl RangeWarning {
+    pub fn new(message: impl Into<String>, range: Option<SrcByteRange>) -> Self {
+        Self {
+            message: message.into(),
+            range,
+        }
+    }
+    pub fn spanned(self, src: &str) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            code: None,
+            message: self.message,
+            span: self.range.map(|r| r.to_span(src)),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+}
+
 pub trait ResultExt {
     fn with_entity_range(self, entity: Entity, ranges: &ReadStorage<SrcByteRange>) -> Self;
 }
@@ -1,4 +1,5 @@
 use ast::Source;
+use code_gen;
 use specs::prelude::*;
 
 #[derive(Component)]
@@ -11,7 +12,7 @@ impl<'a, 's> System<'a> for FunctionIndexing<'s> {
     type SystemData = (WriteStorage<'a, FunctionIndex>,);
 
     fn run(&mut self, (mut function_indices,): Self::SystemData) {
-        let mut index = 14;
+        let mut index = code_gen::configure_fn_index() + 1;
         for (_, entity) in self.0.code_items() {
             function_indices
                 .insert(entity, FunctionIndex(index))
@@ -1,5 +1,5 @@
 use debug_info::SrcByteRange;
-use error::{RangeError, RangeResult, ResultExt};
+use error::{ErrorCode, RangeError};
 use name_resolution::FunctionDecl;
 use name_resolution::Vars;
 use parity_wasm::elements::ValueType;
@@ -57,16 +57,16 @@ impl<'a> System<'a> for AllocFunctionSignatureRegisters {
 }
 
 pub struct VerifyFunctionCallSignatures {
-    result: RangeResult<()>,
+    errors: Vec<RangeError>,
 }
 
 impl VerifyFunctionCallSignatures {
     pub fn new() -> Self {
-        Self { result: Ok(()) }
+        Self { errors: Vec::new() }
     }
-    pub fn run(mut self, world: &World) -> RangeResult<()> {
+    pub fn run(mut self, world: &World) -> Vec<RangeError> {
         self.run_now(&world.res);
-        self.result
+        self.errors
     }
 }
 
@@ -86,11 +86,18 @@ impl<'a> System<'a> for VerifyFunctionCallSignatures {
             let expected = params.len();
             let has = function_call.arguments;
             if expected != has {
-                self.result = Err(RangeError::new(format!(
-                    "Provided {} arguments but the function expects {} arguments.",
-                    has, expected
-                ))).with_entity_range(entity, &ranges);
-                return;
+                let mut error = RangeError::new(
+                    ErrorCode::ArgumentCount,
+                    format!(
+                        "Provided {} arguments but the function expects {} arguments.",
+                        has, expected
+                    ),
+                ).with_label(
+                    ranges.get(resolved_function).cloned(),
+                    "the function is declared here",
+                );
+                error.range = ranges.get(entity).cloned();
+                self.errors.push(error);
             }
         }
     }
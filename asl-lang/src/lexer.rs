@@ -0,0 +1,745 @@
+use debug_info::SrcByteRange;
+use error::{ErrorCode, RangeError};
+use std::collections::HashMap;
+use std::fmt;
+use types::Ty;
+
+/// A number literal along with the type its suffix asks for, like `5u8`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Number<T> {
+    pub value: T,
+    pub ty: Option<Ty>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tok {
+    Ident(String),
+    Label(String),
+    Int(Number<i64>),
+    Float(Number<f64>),
+    Str(String),
+
+    As,
+    Be,
+    Bool,
+    Break,
+    Continue,
+    Current,
+    Else,
+    Emulator,
+    F32,
+    F64,
+    False,
+    Fn,
+    For,
+    GameTime,
+    I8,
+    I16,
+    I32,
+    I64,
+    If,
+    In,
+    IsLoading,
+    Layout,
+    Le,
+    Let,
+    Loop,
+    Match,
+    MemoryRegionBase,
+    MemoryRegionProtection,
+    MemoryRegionSize,
+    ModuleAddress,
+    ModuleExport,
+    ModuleSize,
+    Mono,
+    Old,
+    Ptr32,
+    Ptr64,
+    Read,
+    Reset,
+    Return,
+    Split,
+    Start,
+    State,
+    Struct,
+    True,
+    U8,
+    U16,
+    U32,
+    U64,
+    Underscore,
+    Unit,
+    While,
+
+    And,
+    AndAnd,
+    AndEq,
+    Arrow,
+    Caret,
+    CaretEq,
+    Colon,
+    ColonColon,
+    Comma,
+    Dot,
+    DotDot,
+    Eq,
+    EqEq,
+    FatArrow,
+    Gt,
+    GtEq,
+    LBrace,
+    LBracket,
+    LParen,
+    Lt,
+    LtEq,
+    Minus,
+    MinusEq,
+    Not,
+    NotEq,
+    Or,
+    OrEq,
+    OrOr,
+    Plus,
+    PlusEq,
+    RBrace,
+    RBracket,
+    RParen,
+    Semi,
+    Shl,
+    ShlEq,
+    Shr,
+    ShrEq,
+    Slash,
+    SlashEq,
+    Star,
+    StarEq,
+}
+
+const KEYWORDS: &[(&str, Tok)] = &[
+    ("as", Tok::As),
+    ("be", Tok::Be),
+    ("bool", Tok::Bool),
+    ("break", Tok::Break),
+    ("continue", Tok::Continue),
+    ("current", Tok::Current),
+    ("else", Tok::Else),
+    ("emulator", Tok::Emulator),
+    ("f32", Tok::F32),
+    ("f64", Tok::F64),
+    ("false", Tok::False),
+    ("fn", Tok::Fn),
+    ("for", Tok::For),
+    ("gameTime", Tok::GameTime),
+    ("i8", Tok::I8),
+    ("i16", Tok::I16),
+    ("i32", Tok::I32),
+    ("i64", Tok::I64),
+    ("if", Tok::If),
+    ("in", Tok::In),
+    ("isLoading", Tok::IsLoading),
+    ("layout", Tok::Layout),
+    ("le", Tok::Le),
+    ("let", Tok::Let),
+    ("loop", Tok::Loop),
+    ("match", Tok::Match),
+    ("memory_region_base", Tok::MemoryRegionBase),
+    ("memory_region_protection", Tok::MemoryRegionProtection),
+    ("memory_region_size", Tok::MemoryRegionSize),
+    ("module_address", Tok::ModuleAddress),
+    ("module_export", Tok::ModuleExport),
+    ("module_size", Tok::ModuleSize),
+    ("mono", Tok::Mono),
+    ("old", Tok::Old),
+    ("ptr32", Tok::Ptr32),
+    ("ptr64", Tok::Ptr64),
+    ("read", Tok::Read),
+    ("reset", Tok::Reset),
+    ("return", Tok::Return),
+    ("split", Tok::Split),
+    ("start", Tok::Start),
+    ("state", Tok::State),
+    ("struct", Tok::Struct),
+    ("true", Tok::True),
+    ("u8", Tok::U8),
+    ("u16", Tok::U16),
+    ("u32", Tok::U32),
+    ("u64", Tok::U64),
+    ("_", Tok::Underscore),
+    ("unit", Tok::Unit),
+    ("while", Tok::While),
+];
+
+/// Longer symbols come first, so they win over the shorter symbols they start
+/// with.
+const SYMBOLS: &[(&str, Tok)] = &[
+    ("<<=", Tok::ShlEq),
+    (">>=", Tok::ShrEq),
+    ("&&", Tok::AndAnd),
+    ("&=", Tok::AndEq),
+    ("->", Tok::Arrow),
+    ("^=", Tok::CaretEq),
+    ("::", Tok::ColonColon),
+    ("..", Tok::DotDot),
+    ("==", Tok::EqEq),
+    ("=>", Tok::FatArrow),
+    (">=", Tok::GtEq),
+    ("<=", Tok::LtEq),
+    ("-=", Tok::MinusEq),
+    ("!=", Tok::NotEq),
+    ("|=", Tok::OrEq),
+    ("||", Tok::OrOr),
+    ("+=", Tok::PlusEq),
+    ("<<", Tok::Shl),
+    (">>", Tok::Shr),
+    ("/=", Tok::SlashEq),
+    ("*=", Tok::StarEq),
+    ("&", Tok::And),
+    ("^", Tok::Caret),
+    (":", Tok::Colon),
+    (",", Tok::Comma),
+    (".", Tok::Dot),
+    ("=", Tok::Eq),
+    (">", Tok::Gt),
+    ("{", Tok::LBrace),
+    ("[", Tok::LBracket),
+    ("(", Tok::LParen),
+    ("<", Tok::Lt),
+    ("-", Tok::Minus),
+    ("!", Tok::Not),
+    ("|", Tok::Or),
+    ("+", Tok::Plus),
+    ("}", Tok::RBrace),
+    ("]", Tok::RBracket),
+    (")", Tok::RParen),
+    (";", Tok::Semi),
+    ("/", Tok::Slash),
+    ("*", Tok::Star),
+];
+
+impl Tok {
+    /// Whether the token can be the end of a value. A minus following such a
+    /// token subtracts from the value rather than being the sign of a number.
+    fn ends_value(&self) -> bool {
+        match self {
+            Tok::Ident(_)
+            | Tok::Int(_)
+            | Tok::Float(_)
+            | Tok::Str(_)
+            | Tok::True
+            | Tok::False
+            | Tok::RParen
+            | Tok::RBracket
+            | Tok::RBrace => true,
+            // The type of a cast.
+            tok => suffix_ty(&tok.to_string()).is_some() || *tok == Tok::Bool,
+        }
+    }
+}
+
+impl fmt::Display for Tok {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Tok::Ident(name) | Tok::Label(name) => write!(f, "{}", name),
+            Tok::Int(Number { value, ty }) => {
+                write!(f, "{}", value)?;
+                ty.iter().try_for_each(|ty| write!(f, "{}", ty))
+            }
+            Tok::Float(Number { value, ty }) => {
+                write!(f, "{:?}", value)?;
+                ty.iter().try_for_each(|ty| write!(f, "{}", ty))
+            }
+            Tok::Str(value) => write!(f, "{:?}", value),
+            tok => {
+                let (text, _) = KEYWORDS
+                    .iter()
+                    .chain(SYMBOLS)
+                    .find(|(_, t)| t == tok)
+                    .unwrap();
+                f.write_str(text)
+            }
+        }
+    }
+}
+
+/// The doc comments of the source, by the position of the code they document.
+pub struct DocComments(HashMap<usize, String>);
+
+impl DocComments {
+    /// Looks up the doc comment that directly precedes the code at the
+    /// position.
+    pub fn before(&self, pos: usize) -> Option<String> {
+        self.0.get(&pos).cloned()
+    }
+}
+
+pub type Spanned = (usize, Tok, usize);
+
+/// The tokens of the source. The invalid tokens are reported as errors, but
+/// are either skipped or replaced by a valid token, so the parser can still
+/// look for more errors.
+pub struct Lexed {
+    pub tokens: Vec<Spanned>,
+    pub docs: DocComments,
+    pub errors: Vec<RangeError>,
+}
+
+pub fn lex(src: &str) -> Lexed {
+    let mut lexer = Lexer {
+        src,
+        pos: 0,
+        tokens: Vec::new(),
+        docs: HashMap::new(),
+        doc: None,
+        errors: Vec::new(),
+    };
+    lexer.run();
+
+    Lexed {
+        tokens: lexer.tokens,
+        docs: DocComments(lexer.docs),
+        errors: lexer.errors,
+    }
+}
+
+struct Lexer<'s> {
+    src: &'s str,
+    pos: usize,
+    tokens: Vec<Spanned>,
+    docs: HashMap<usize, String>,
+    /// The doc comment for the next token.
+    doc: Option<String>,
+    errors: Vec<RangeError>,
+}
+
+impl<'s> Lexer<'s> {
+    fn run(&mut self) {
+        while let Some(c) = self.peek() {
+            let start = self.pos;
+            let rest = self.rest();
+            if c.is_whitespace() {
+                self.pos += c.len_utf8();
+            } else if rest.starts_with("//") {
+                self.line_comment();
+            } else if rest.starts_with("/*") {
+                self.block_comment();
+            } else if c == '"' {
+                let value = self.string();
+                self.push(start, Tok::Str(value));
+            } else if rest.starts_with("r\"") || rest.starts_with("r#") {
+                let value = self.raw_string();
+                self.push(start, Tok::Str(value));
+            } else if c == '\'' {
+                self.label();
+            } else if c.is_ascii_digit() && self.last() == Some(&Tok::Dot) {
+                self.tuple_index();
+            } else if c.is_ascii_digit() || (c == '-' && self.is_signed_number()) {
+                let number = self.number();
+                self.push(start, number);
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                self.word();
+            } else if let Some((text, tok)) = SYMBOLS.iter().find(|(s, _)| rest.starts_with(s)) {
+                self.pos += text.len();
+                self.push(start, tok.clone());
+            } else {
+                self.pos += c.len_utf8();
+                self.error(start, format!("Unexpected character '{}'", c));
+            }
+        }
+    }
+
+    fn rest(&self) -> &'s str {
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn last(&self) -> Option<&Tok> {
+        self.tokens.last().map(|(_, tok, _)| tok)
+    }
+
+    fn eat_while(&mut self, f: impl Fn(char) -> bool) -> &'s str {
+        let rest = self.rest();
+        let len = rest.find(|c| !f(c)).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn push(&mut self, start: usize, tok: Tok) {
+        if let Some(doc) = self.doc.take() {
+            self.docs.insert(start, doc);
+        }
+        self.tokens.push((start, tok, self.pos));
+    }
+
+    /// Reports an error for everything from the start up to the current
+    /// position.
+    fn error(&mut self, start: usize, message: impl Into<String>) {
+        let mut error = RangeError::new(ErrorCode::Syntax, message);
+        error.range = Some(SrcByteRange(start, self.pos));
+        self.errors.push(error);
+    }
+
+    fn line_comment(&mut self) {
+        let line = self.eat_while(|c| c != '\n');
+        if line.starts_with("///") && !line.starts_with("////") {
+            let mut line = &line[3..];
+            if line.ends_with('\r') {
+                line = &line[..line.len() - 1];
+            }
+            if line.starts_with(' ') {
+                line = &line[1..];
+            }
+            // Consecutive doc comments document the same code.
+            let doc = self.doc.get_or_insert_with(String::new);
+            if !doc.is_empty() {
+                doc.push('\n');
+            }
+            doc.push_str(line);
+        }
+    }
+
+    fn block_comment(&mut self) {
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            let rest = self.rest();
+            if rest.starts_with("/*") {
+                depth += 1;
+                self.pos += 2;
+            } else if rest.starts_with("*/") {
+                depth -= 1;
+                self.pos += 2;
+                if depth == 0 {
+                    return;
+                }
+            } else {
+                self.pos += c.len_utf8();
+            }
+        }
+        self.pos = start + 2;
+        self.error(start, "Unterminated block comment");
+        self.pos = self.src.len();
+    }
+
+    /// Strings can't span multiple lines, so a missing quote doesn't swallow
+    /// the rest of the source.
+    fn string(&mut self) -> String {
+        let start = self.pos;
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                None | Some('\n') => {
+                    self.error(start, "Unterminated string literal");
+                    break;
+                }
+                Some('"') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some('\\') => {
+                    if let Some(c) = self.escape() {
+                        value.push(c);
+                    }
+                }
+                Some(c) => {
+                    self.pos += c.len_utf8();
+                    value.push(c);
+                }
+            }
+        }
+        value
+    }
+
+    fn escape(&mut self) -> Option<char> {
+        let start = self.pos;
+        self.pos += 1;
+        let c = match self.peek() {
+            Some(c) if c != '\n' => c,
+            _ => return None,
+        };
+        self.pos += c.len_utf8();
+
+        let escaped = match c {
+            'n' => Some('\n'),
+            'r' => Some('\r'),
+            't' => Some('\t'),
+            '0' => Some('\0'),
+            '\\' | '"' | '\'' => Some(c),
+            'x' => {
+                let digits = self.rest().get(..2).unwrap_or("");
+                let value = Some(digits)
+                    .filter(|d| d.chars().all(|c| c.is_ascii_hexdigit()))
+                    .and_then(|d| u8::from_str_radix(d, 16).ok())
+                    .filter(|&v| v <= 0x7F);
+                if value.is_some() {
+                    self.pos += 2;
+                }
+                value.map(char::from)
+            }
+            'u' if self.peek() == Some('{') => {
+                self.pos += 1;
+                let digits = self.eat_while(|c| c.is_ascii_hexdigit());
+                if self.peek() == Some('}') {
+                    self.pos += 1;
+                    u32::from_str_radix(digits, 16)
+                        .ok()
+                        .and_then(::std::char::from_u32)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        if escaped.is_none() {
+            let escape = &self.src[start..self.pos];
+            self.error(start, format!("Invalid escape sequence '{}'", escape));
+        }
+        escaped
+    }
+
+    /// Raw strings like `r"C:\Path"` or `r#"a "quoted" word"#` don't have any
+    /// escapes.
+    fn raw_string(&mut self) -> String {
+        let start = self.pos;
+        self.pos += 1;
+        let hashes = self.eat_while(|c| c == '#').len();
+        if self.peek() != Some('"') {
+            self.error(start, "Expected '\"' to start the raw string");
+            return String::new();
+        }
+        self.pos += 1;
+
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        let rest = self.rest();
+        let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+        match line.find(&terminator) {
+            Some(end) => {
+                self.pos += end + terminator.len();
+                line[..end].to_owned()
+            }
+            None => {
+                self.pos += line.len();
+                self.error(start, "Unterminated raw string literal");
+                line.to_owned()
+            }
+        }
+    }
+
+    fn label(&mut self) {
+        let start = self.pos;
+        self.pos += 1;
+        if self.peek().map_or(false, |c| c.is_ascii_alphabetic()) {
+            self.eat_while(is_word);
+            let label = self.src[start..self.pos].to_owned();
+            self.push(start, Tok::Label(label));
+        } else {
+            self.error(start, "Expected the name of a label after '");
+        }
+    }
+
+    fn word(&mut self) {
+        let start = self.pos;
+        let word = self.eat_while(is_word);
+        let tok = match KEYWORDS.iter().find(|(keyword, _)| *keyword == word) {
+            Some((_, tok)) => tok.clone(),
+            None => {
+                if word.starts_with('_') {
+                    self.error(start, "Names can't start with an underscore");
+                }
+                Tok::Ident(word.to_owned())
+            }
+        };
+        self.push(start, tok);
+    }
+
+    /// Whether a minus is the sign of the number that directly follows it.
+    fn is_signed_number(&self) -> bool {
+        self.rest()[1..].starts_with(|c: char| c.is_ascii_digit())
+            && !self.last().map_or(false, Tok::ends_value)
+    }
+
+    /// The fields of tuples are accessed like `pair.0.1`, which must not be
+    /// mistaken for a float.
+    fn tuple_index(&mut self) {
+        let start = self.pos;
+        let index = self.eat_while(|c| c.is_ascii_digit());
+        let value = match index.parse() {
+            Ok(value) => value,
+            Err(_) => {
+                self.error(start, "The tuple index is too large");
+                0
+            }
+        };
+        self.push(start, Tok::Int(Number { value, ty: None }));
+    }
+
+    fn number(&mut self) -> Tok {
+        let start = self.pos;
+        let negative = self.rest().starts_with('-');
+        if negative {
+            self.pos += 1;
+        }
+
+        let rest = self.rest();
+        let (radix, base) = if rest.starts_with("0x") {
+            (16, "hexadecimal")
+        } else if rest.starts_with("0o") {
+            (8, "octal")
+        } else if rest.starts_with("0b") {
+            (2, "binary")
+        } else {
+            (10, "decimal")
+        };
+        if radix != 10 {
+            self.pos += 2;
+        }
+
+        let digits_start = self.pos;
+        self.eat_while(|c| c.is_digit(radix) || c == '_');
+        let mut is_float = false;
+        if radix == 10 {
+            let rest = self.rest();
+            if rest.starts_with('.') && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+                is_float = true;
+                self.pos += 1;
+                self.eat_while(|c| c.is_ascii_digit() || c == '_');
+            }
+            let exponent = exponent_len(self.rest());
+            if exponent != 0 {
+                is_float = true;
+                self.pos += exponent;
+            }
+        }
+        let digits = self.src[digits_start..self.pos].replace('_', "");
+        let literal_end = self.pos;
+        let suffix = self.eat_while(is_word);
+
+        if digits.is_empty() {
+            self.error(start, format!("Missing digits in a {} literal", base));
+            return Tok::Int(Number { value: 0, ty: None });
+        }
+
+        let ty = if suffix.is_empty() {
+            None
+        } else if let Some(ty) = suffix_ty(suffix) {
+            Some(ty)
+        } else {
+            let message = if radix != 10 && suffix.starts_with(|c: char| c.is_ascii_hexdigit()) {
+                format!("Invalid digit in a {} literal", base)
+            } else {
+                format!("Invalid suffix '{}' for a number literal", suffix)
+            };
+            self.error(start, message);
+            None
+        };
+        let is_float_ty = ty
+            .as_ref()
+            .map_or(false, |ty| *ty == Ty::F32 || *ty == Ty::F64);
+
+        if is_float || is_float_ty {
+            if radix != 10 {
+                self.error(start, format!("A {} literal can't be a float", base));
+            } else if ty.is_some() && !is_float_ty {
+                self.error(
+                    start,
+                    format!("A float literal can't have the suffix '{}'", suffix),
+                );
+            }
+            let value = digits.parse::<f64>().unwrap_or(0.0);
+            let value = if negative { -value } else { value };
+            let ty = if is_float_ty { ty } else { None };
+            return Tok::Float(Number { value, ty });
+        }
+
+        let magnitude = match u64::from_str_radix(&digits, radix) {
+            Ok(magnitude) => magnitude,
+            Err(_) => {
+                self.error(start, "The integer literal is too large");
+                0
+            }
+        };
+        let (max, ty_name) = match &ty {
+            Some(ty) => (int_max(ty, negative), ty.to_string()),
+            None if negative => (int_max(&Ty::I64, true), String::from("i64")),
+            None => (int_max(&Ty::U64, false), String::from("u64")),
+        };
+        if magnitude > max {
+            let literal = self.src[start..literal_end].to_owned();
+            self.error(
+                start,
+                format!("The literal {} doesn't fit into {}", literal, ty_name),
+            );
+        }
+        let value = if negative {
+            (magnitude as i64).wrapping_neg()
+        } else {
+            magnitude as i64
+        };
+        Tok::Int(Number { value, ty })
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// The length of the exponent of a float, like `e-3`, at the start of the
+/// text.
+fn exponent_len(text: &str) -> usize {
+    if !text.starts_with('e') && !text.starts_with('E') {
+        return 0;
+    }
+    let sign = if text[1..].starts_with('+') || text[1..].starts_with('-') {
+        1
+    } else {
+        0
+    };
+    let digits = &text[1 + sign..];
+    if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+        return 0;
+    }
+    1 + sign
+        + digits
+            .find(|c: char| !c.is_ascii_digit() && c != '_')
+            .unwrap_or(digits.len())
+}
+
+fn suffix_ty(suffix: &str) -> Option<Ty> {
+    Some(match suffix {
+        "u8" => Ty::U8,
+        "u16" => Ty::U16,
+        "u32" => Ty::U32,
+        "u64" => Ty::U64,
+        "i8" => Ty::I8,
+        "i16" => Ty::I16,
+        "i32" => Ty::I32,
+        "i64" => Ty::I64,
+        "f32" => Ty::F32,
+        "f64" => Ty::F64,
+        _ => return None,
+    })
+}
+
+/// The largest magnitude a literal of the integer type can have.
+fn int_max(ty: &Ty, negative: bool) -> u64 {
+    let (bits, signed) = match ty {
+        Ty::U8 => (8, false),
+        Ty::U16 => (16, false),
+        Ty::U32 => (32, false),
+        Ty::U64 => (64, false),
+        Ty::I8 => (8, true),
+        Ty::I16 => (16, true),
+        Ty::I32 => (32, true),
+        _ => (64, true),
+    };
+    match (signed, negative) {
+        (false, false) => !0 >> (64 - bits),
+        (false, true) => 0,
+        (true, false) => (1 << (bits - 1)) - 1,
+        (true, true) => 1 << (bits - 1),
+    }
+}
@@ -1,5 +1,6 @@
 #![allow(unknown_lints)]
 
+extern crate asl_abi;
 #[macro_use]
 extern crate lalrpop_util;
 extern crate specs;
@@ -7,6 +8,8 @@ extern crate specs;
 extern crate specs_derive;
 
 pub extern crate parity_wasm;
+#[cfg(test)]
+extern crate wasmi;
 
 mod ast;
 mod code_gen;
@@ -14,12 +17,15 @@ mod debug_info;
 mod error;
 mod function_indexing;
 mod function_signatures;
+mod lexer;
 mod name_resolution;
 mod ops;
 mod reg_alloc;
 mod reg_extend;
 mod specify_general_types;
+mod struct_resolution;
 mod types;
+mod usage;
 
 lalrpop_mod!(
     #[allow(clippy)]
@@ -28,17 +34,21 @@ lalrpop_mod!(
 
 use debug_info::SrcByteRange;
 pub use debug_info::{Hover, Span};
-pub use error::{Error, Result};
+use error::RangeError;
+pub use error::{Diagnostic, Error, ErrorCode, Label, Result, Severity};
 use lalrpop_util::ParseError;
 pub use parity_wasm::elements::Module;
 use specs::prelude::*;
+use std::fmt;
 pub use types::Ty;
 
 fn create_world() -> World {
     let mut world = World::new();
     world.register::<ast::Children>();
+    world.register::<ast::Block>();
     world.register::<ast::Scoped>();
     world.register::<code_gen::CodeGenDesc>();
+    world.register::<debug_info::Docs>();
     world.register::<debug_info::ReferencesVar>();
     world.register::<debug_info::SrcByteRange>();
     world.register::<function_indexing::FunctionIndex>();
@@ -47,6 +57,8 @@ fn create_world() -> World {
     world.register::<name_resolution::DeclaredBy>();
     world.register::<name_resolution::FunctionDecl>();
     world.register::<name_resolution::FunctionParamAsVar>();
+    world.register::<name_resolution::Jump>();
+    world.register::<name_resolution::LoopLabel>();
     world.register::<name_resolution::VarDecl>();
     world.register::<name_resolution::VarNames>();
     world.register::<name_resolution::Vars>();
@@ -54,73 +66,222 @@ fn create_world() -> World {
     world.register::<reg_alloc::Registers>();
     world.register::<reg_extend::InferExtending>();
     world.register::<reg_extend::NeedsExtending>();
+    world.register::<struct_resolution::StructLit>();
     world.register::<types::Ty>();
+    world.register::<types::TypeAnchor>();
     world.register::<types::TypeChecking>();
 
     world
 }
 
-fn parse(world: &mut World, src: &str) -> Result<ast::Source> {
-    match grammar::SourceParser::new().parse(world, src) {
-        Ok(s) => Ok(s),
-        Err(ParseError::InvalidToken { location }) => Err(Error {
-            message: String::from("Invalid token"),
-            span: Some(Span::from_byte_position(src, location)),
-        }),
-        Err(ParseError::UnrecognizedToken {
+/// Parses the source, recovering from as many syntax errors as possible. There
+/// is only a source if the parser made it to the end.
+fn parse(world: &mut World, src: &str) -> (Option<ast::Source>, Vec<Error>) {
+    let lexed = lexer::lex(src);
+    let mut recovered = Vec::new();
+    let result =
+        grammar::SourceParser::new().parse(world, &mut recovered, &lexed.docs, lexed.tokens);
+    let mut errors = lexed
+        .errors
+        .into_iter()
+        .map(|e| e.spanned(src))
+        .chain(recovered.into_iter().map(|e| parse_error(src, e.error)))
+        .collect::<Vec<_>>();
+
+    let source = match result {
+        Ok(source) => Some(source),
+        Err(e) => {
+            errors.push(parse_error(src, e));
+            None
+        }
+    };
+    errors.sort_by_key(|e| e.span.as_ref().map(|s| s.from));
+    (source, errors)
+}
+
+fn parse_error<T: fmt::Display>(src: &str, error: ParseError<usize, T, &str>) -> Error {
+    let (message, span) = match error {
+        ParseError::InvalidToken { location } => (
+            String::from("Invalid token"),
+            Span::from_byte_position(src, location),
+        ),
+        ParseError::UnrecognizedToken {
             token: Some((l, token, r)),
             expected,
-        }) => Err(Error {
-            message: format!(
+        } => (
+            format!(
                 "Unexpected token \"{}\", expected one of: {}",
                 token,
                 expected.join(", ")
             ),
-            span: Some(SrcByteRange(l, r).to_span(src)),
-        }),
-        Err(ParseError::UnrecognizedToken {
+            SrcByteRange(l, r).to_span(src),
+        ),
+        ParseError::UnrecognizedToken {
             token: None,
             expected,
-        }) => Err(Error {
-            message: format!(
+        } => (
+            format!(
                 "Unexpected end of file, expected one of: {}",
                 expected.join(", ")
             ),
-            span: Some(Span::from_byte_position(src, src.len())),
-        }),
-        Err(ParseError::ExtraToken {
+            Span::from_byte_position(src, src.len()),
+        ),
+        ParseError::ExtraToken {
             token: (l, token, r),
-        }) => Err(Error {
-            message: format!("Encountered an extra token \"{}\"", token),
-            span: Some(SrcByteRange(l, r).to_span(src)),
-        }),
+        } => (
+            format!("Encountered an extra token \"{}\"", token),
+            SrcByteRange(l, r).to_span(src),
+        ),
         _ => unimplemented!(),
+    };
+
+    Error {
+        code: ErrorCode::Syntax,
+        message,
+        span: Some(span),
+        labels: Vec::new(),
+        notes: Vec::new(),
     }
 }
 
-fn base_passes(world: &mut World, src: &str, source: &ast::Source) -> Result<()> {
-    name_resolution::NameResolution::new(source)
-        .run(&world)
-        .map_err(|e| e.spanned(src))?;
+/// The editor features keep working on code the parser recovered from.
+fn parse_leniently(world: &mut World, src: &str) -> Result<ast::Source> {
+    match parse(world, src) {
+        (Some(source), _) => Ok(source),
+        (None, mut errors) => Err(errors.pop().unwrap()),
+    }
+}
 
-    function_signatures::VerifyFunctionCallSignatures::new()
-        .run(&world)
-        .map_err(|e| e.spanned(src))?;
+fn check(src: &str, errors: Vec<RangeError>) -> ::std::result::Result<(), Vec<Error>> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.into_iter().map(|e| e.spanned(src)).collect())
+    }
+}
 
-    types::TypeSystem::new(source)
+/// The warnings, along with the functions and variable declarations that are
+/// never used.
+type Resolution = (Vec<Diagnostic>, Vec<Entity>, Vec<Entity>);
+
+fn resolution_passes(
+    world: &mut World,
+    src: &str,
+    source: &ast::Source,
+) -> ::std::result::Result<Resolution, Vec<Error>> {
+    struct_resolution::StructResolution::new(source)
         .run(&world)
-        .map_err(|e| e.spanned(src))?;
+        .map_err(|e| vec![e.spanned(src)])?;
+
+    check(
+        src,
+        name_resolution::NameResolution::new(source).run(&world),
+    )?;
+
+    let (warnings, unused_functions, unused_decls) = usage::UsageAnalysis::new(source).run(&world);
+
+    Ok((
+        warnings.into_iter().map(|w| w.spanned(src)).collect(),
+        unused_functions,
+        unused_decls,
+    ))
+}
+
+fn type_passes(
+    world: &mut World,
+    src: &str,
+    source: &ast::Source,
+) -> ::std::result::Result<(), Vec<Error>> {
+    check(
+        src,
+        function_signatures::VerifyFunctionCallSignatures::new().run(&world),
+    )?;
+
+    check(src, types::TypeSystem::new(source).run(&world))?;
 
     types::CheckForUnassignedTypes.run_now(&world.res);
 
     Ok(())
 }
 
-pub fn compile(src: &str) -> Result<Module> {
+fn base_passes(world: &mut World, src: &str, source: &ast::Source) -> Result<()> {
+    let first = |mut errors: Vec<Error>| errors.remove(0);
+    resolution_passes(world, src, source).map_err(first)?;
+    type_passes(world, src, source).map_err(first)
+}
+
+/// Removes the functions along with all of their code, so they don't get type
+/// checked or compiled.
+fn remove_functions(world: &mut World, source: &mut ast::Source, functions: &[Entity]) {
+    source.items.retain(|item| match item {
+        ast::Item::Function(entity) => !functions.contains(entity),
+        _ => true,
+    });
+
+    let mut code = functions.to_vec();
+    {
+        let children = world.read_storage::<ast::Children>();
+        let mut index = 0;
+        while index < code.len() {
+            if let Some(ast::Children(c)) = children.get(code[index]) {
+                code.extend(c);
+            }
+            index += 1;
+        }
+    }
+    world.delete_entities(&code).unwrap();
+}
+
+/// Removes the type annotations of the variables, so they get inferred from
+/// their values instead. Variables that are never used can't cause any type
+/// errors this way.
+fn remove_annotations(world: &World, decls: &[Entity]) {
+    let vars = world.read_storage::<name_resolution::Vars>();
+    let var_decls = world.read_storage::<name_resolution::VarDecl>();
+    let mut types = world.write_storage::<types::Ty>();
+    let mut anchors = world.write_storage::<types::TypeAnchor>();
+    for &decl in decls {
+        match anchors.get(decl) {
+            Some(anchor) if anchor.kind == types::AnchorKind::Annotation => {}
+            _ => continue,
+        }
+        anchors.remove(decl);
+        types.remove(decl);
+        if let (Some(name_resolution::Vars(vars)), Some(name_resolution::VarDecl(id))) =
+            (vars.get(decl), var_decls.get(decl))
+        {
+            types.remove(vars[*id]);
+        }
+    }
+}
+
+fn into_diagnostics(errors: Vec<Error>) -> Vec<Diagnostic> {
+    errors.into_iter().map(Diagnostic::from).collect()
+}
+
+/// Compiles the source into a module. All the errors and warnings that were
+/// found get reported, either alongside the module or instead of it. Errors
+/// are found one stage at a time though: syntax errors, name resolution errors
+/// and type errors each stop the compilation before the next stage.
+pub fn compile(src: &str) -> ::std::result::Result<(Module, Vec<Diagnostic>), Vec<Diagnostic>> {
     let mut world = create_world();
-    let source = parse(&mut world, src)?;
 
-    base_passes(&mut world, src, &source)?;
+    // The code skipped while recovering from syntax errors would only cause
+    // misleading errors later on, so we stop at the syntax errors.
+    let (source, errors) = parse(&mut world, src);
+    if !errors.is_empty() {
+        return Err(into_diagnostics(errors));
+    }
+    let mut source = source.unwrap();
+
+    let (mut diagnostics, unused_functions, unused_decls) =
+        resolution_passes(&mut world, src, &source).map_err(into_diagnostics)?;
+    remove_functions(&mut world, &mut source, &unused_functions);
+    remove_annotations(&world, &unused_decls);
+    if let Err(errors) = type_passes(&mut world, src, &source) {
+        diagnostics.extend(into_diagnostics(errors));
+        return Err(diagnostics);
+    }
 
     reg_extend::RegisterExtensionInference.run_now(&world.res);
     specify_general_types::SpecifyGeneralTypes.run_now(&world.res);
@@ -130,32 +291,32 @@ pub fn compile(src: &str) -> Result<Module> {
     let mut code_gen = code_gen::CodeGen(&source, None);
     code_gen.run_now(&world.res);
 
-    Ok(code_gen.1.unwrap())
+    Ok((code_gen.1.unwrap(), diagnostics))
 }
 
 pub fn hover(src: &str, line: usize, column: usize) -> Result<Option<Hover>> {
     let mut world = create_world();
-    let source = parse(&mut world, src)?;
+    let source = parse_leniently(&mut world, src)?;
 
     base_passes(&mut world, src, &source)?;
-    Ok(debug_info::HoverSystem::new(src, line, column).run(&world))
+    Ok(debug_info::HoverSystem::new(src, &source, line, column).run(&world))
 }
 
 pub fn go_to_definition(src: &str, line: usize, column: usize) -> Result<Option<Span>> {
     let mut world = create_world();
-    let source = parse(&mut world, src)?;
+    let source = parse_leniently(&mut world, src)?;
 
     base_passes(&mut world, src, &source)?;
-    Ok(debug_info::GoToDefinition::run(src, &world, line, column).map(|(_, s)| s))
+    Ok(debug_info::GoToDefinition::run(src, &source, &world, line, column).map(|(_, s)| s))
 }
 
 pub fn find_all_references(src: &str, line: usize, column: usize) -> Result<Option<Vec<Span>>> {
     let mut world = create_world();
-    let source = parse(&mut world, src)?;
+    let source = parse_leniently(&mut world, src)?;
 
     base_passes(&mut world, src, &source)?;
     Ok(debug_info::FindAllVariableReferences::run(
-        src, &world, line, column,
+        src, &source, &world, line, column,
     ))
 }
 
@@ -1,6 +1,7 @@
 use ast::{Children, Scoped, Source};
 use debug_info::SrcByteRange;
-use error::{RangeError, RangeResult, ResultExt};
+use error::{ErrorCode, RangeError, RangeResult, ResultExt};
+use function_signatures::FunctionCall;
 use specs::prelude::*;
 use types::Ty;
 
@@ -28,21 +29,34 @@ pub struct FunctionParamAsVar {
 #[storage(DenseVecStorage)]
 pub struct FunctionDecl(pub usize, pub Vec<Entity>);
 
+/// Marks a loop that can be jumped out of, along with its label.
+#[derive(Component)]
+pub struct LoopLabel(pub Option<String>);
+
+/// Jumps out of a loop or the function. The loop or the function it jumps out
+/// of gets appended to the variables.
+#[derive(Component)]
+pub enum Jump {
+    Break(Option<String>),
+    Continue(Option<String>),
+    Return,
+}
+
 pub struct NameResolution<'s> {
     src: &'s Source,
-    result: RangeResult<()>,
+    errors: Vec<RangeError>,
 }
 
 impl<'s> NameResolution<'s> {
     pub fn new(src: &'s Source) -> Self {
         Self {
             src,
-            result: Ok(()),
+            errors: Vec::new(),
         }
     }
-    pub fn run(mut self, world: &World) -> RangeResult<()> {
+    pub fn run(mut self, world: &World) -> Vec<RangeError> {
         self.run_now(&world.res);
-        self.result
+        self.errors
     }
 }
 
@@ -58,7 +72,10 @@ impl<'a, 's> System<'a> for NameResolution<'s> {
         ReadStorage<'a, SrcByteRange>,
         ReadStorage<'a, FunctionDecl>,
         ReadStorage<'a, FunctionParamAsVar>,
+        ReadStorage<'a, FunctionCall>,
         WriteStorage<'a, DeclaredBy>,
+        ReadStorage<'a, LoopLabel>,
+        ReadStorage<'a, Jump>,
     );
 
     fn run(
@@ -74,7 +91,10 @@ impl<'a, 's> System<'a> for NameResolution<'s> {
             ranges,
             function_decls,
             function_params,
+            function_calls,
             mut declared_bys,
+            loop_labels,
+            jumps,
         ): Self::SystemData,
     ) {
         let mut scopes = Scopes::default();
@@ -85,8 +105,10 @@ impl<'a, 's> System<'a> for NameResolution<'s> {
         }
 
         for (_, fn_entity) in self.src.code_items() {
-            if let Err(e) = resolve_names(
+            scopes.function = Some(fn_entity);
+            resolve_names(
                 &mut scopes,
+                &mut self.errors,
                 &entities,
                 fn_entity,
                 &children,
@@ -96,19 +118,20 @@ impl<'a, 's> System<'a> for NameResolution<'s> {
                 &mut vars,
                 &mut types,
                 &function_params,
+                &function_calls,
                 &function_decls,
                 &ranges,
                 &mut declared_bys,
-            ) {
-                self.result = Err(e);
-                return;
-            }
+                &loop_labels,
+                &jumps,
+            );
         }
     }
 }
 
 fn resolve_names<'a>(
     scopes: &mut Scopes<'a>,
+    errors: &mut Vec<RangeError>,
     entities: &Entities,
     entity: Entity,
     children: &ReadStorage<Children>,
@@ -118,15 +141,21 @@ fn resolve_names<'a>(
     vars: &mut WriteStorage<Vars>,
     types: &mut WriteStorage<Ty>,
     function_params: &ReadStorage<FunctionParamAsVar>,
+    function_calls: &ReadStorage<FunctionCall>,
     function_decls: &ReadStorage<FunctionDecl>,
     ranges: &ReadStorage<SrcByteRange>,
     declared_bys: &mut WriteStorage<DeclaredBy>,
-) -> RangeResult<()> {
+    loop_labels: &'a ReadStorage<LoopLabel>,
+    jumps: &'a ReadStorage<Jump>,
+) {
     let introduces_scope = scoped.get(entity).is_some();
     if introduces_scope {
         scopes.push_scope();
     }
 
+    let unresolved_arguments = scopes.unresolved_arguments.len();
+    let mut is_unresolved_call = false;
+
     if let Some(VarNames(list_of_names)) = var_names.get(entity) {
         if let Some(VarDecl(id)) = var_decls.get(entity) {
             let var_entity = scopes.declare_var(&list_of_names[*id], entities);
@@ -136,11 +165,35 @@ fn resolve_names<'a>(
             }
         }
 
+        let is_call = function_calls.get(entity).is_some();
+        // The arguments of a call look up the function again. If the call
+        // already reported it as not in scope, they don't need to.
+        let is_unresolved_argument = scopes.unresolved_arguments.contains(&entity);
         let resolved_vars = list_of_names
             .iter()
-            .map(|var_name| scopes.lookup(var_name))
-            .collect::<RangeResult<_>>()
-            .with_entity_range(entity, ranges)?;
+            .map(|var_name| {
+                // A stand-in keeps the indices of the other variables intact,
+                // so we can keep looking for errors.
+                if is_unresolved_argument {
+                    return entities.create();
+                }
+                let var = if is_call {
+                    scopes.lookup_function(var_name)
+                } else {
+                    scopes.lookup(var_name)
+                };
+                var.with_entity_range(entity, ranges).unwrap_or_else(|e| {
+                    errors.push(e);
+                    is_unresolved_call = is_call;
+                    entities.create()
+                })
+            }).collect::<Vec<_>>();
+
+        if is_unresolved_call {
+            if let Some(Children(arguments)) = children.get(entity) {
+                scopes.unresolved_arguments.extend(arguments);
+            }
+        }
 
         #[allow(never_loop)]
         loop {
@@ -157,16 +210,48 @@ fn resolve_names<'a>(
     if let Some(param) = function_params.get(entity) {
         let Vars(vars) = vars.get_mut(entity).unwrap();
         let function_entity = vars[param.function_var];
-        let FunctionDecl(_, params) = function_decls.get(function_entity).unwrap();
-        if let Some(param_var) = params.get(param.param_idx) {
-            vars.push(*param_var);
+        if let Some(FunctionDecl(_, params)) = function_decls.get(function_entity) {
+            if let Some(param_var) = params.get(param.param_idx) {
+                vars.push(*param_var);
+            }
         }
     }
 
+    if let Some(jump) = jumps.get(entity) {
+        let target = match jump {
+            Jump::Break(label) | Jump::Continue(label) => scopes.lookup_loop(label.as_ref()),
+            Jump::Return => Ok(scopes.function.unwrap()),
+        }.with_entity_range(entity, ranges);
+
+        match target {
+            Ok(target) => {
+                #[allow(never_loop)]
+                loop {
+                    // TODO NLL
+                    if let Some(Vars(existing_vars)) = vars.get_mut(entity) {
+                        existing_vars.push(target);
+                        break;
+                    }
+                    vars.insert(entity, Vars(vec![target])).unwrap();
+                    break;
+                }
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+
+    let loop_label = loop_labels.get(entity);
+    if let Some(LoopLabel(label)) = loop_label {
+        scopes
+            .loops
+            .push((label.as_ref().map(|l| l.as_str()), entity));
+    }
+
     if let Some(my_children) = children.get(entity) {
         for child in &my_children.0 {
             resolve_names(
                 scopes,
+                errors,
                 entities,
                 *child,
                 children,
@@ -176,24 +261,37 @@ fn resolve_names<'a>(
                 vars,
                 types,
                 function_params,
+                function_calls,
                 function_decls,
                 ranges,
                 declared_bys,
-            )?;
+                loop_labels,
+                jumps,
+            );
         }
     }
 
+    if loop_label.is_some() {
+        scopes.loops.pop();
+    }
+
+    if is_unresolved_call {
+        scopes.unresolved_arguments.truncate(unresolved_arguments);
+    }
+
     if introduces_scope {
         scopes.pop_scope();
     }
-
-    Ok(())
 }
 
 #[derive(Default)]
 struct Scopes<'a> {
     frames: Vec<usize>,
     vars: Vec<(&'a str, Entity)>,
+    loops: Vec<(Option<&'a str>, Entity)>,
+    function: Option<Entity>,
+    /// The arguments of calls to functions that aren't in scope.
+    unresolved_arguments: Vec<Entity>,
 }
 
 impl<'a> Scopes<'a> {
@@ -213,7 +311,44 @@ impl<'a> Scopes<'a> {
             .filter(|(existing_name, _)| *existing_name == name)
             .map(|(_, entity)| *entity)
             .next()
-            .ok_or_else(|| RangeError::new(format!("Variable '{}' is not in scope", name)))
+            .ok_or_else(|| {
+                RangeError::new(
+                    ErrorCode::Unresolved,
+                    format!("Variable '{}' is not in scope", name),
+                )
+            })
+    }
+
+    fn lookup_function(&self, name: &str) -> RangeResult<Entity> {
+        self.lookup(name).map_err(|_| {
+            RangeError::new(
+                ErrorCode::Unresolved,
+                format!("Function '{}' is not in scope", name),
+            )
+        })
+    }
+
+    fn lookup_loop(&self, label: Option<&String>) -> RangeResult<Entity> {
+        let loops = self.loops.iter().rev();
+        match label {
+            Some(label) => loops
+                .filter(|(existing_label, _)| *existing_label == Some(label.as_str()))
+                .map(|(_, entity)| *entity)
+                .next()
+                .ok_or_else(|| {
+                    RangeError::new(
+                        ErrorCode::Jump,
+                        format!("There is no loop labelled {}", label),
+                    )
+                }),
+            None => loops
+                .map(|(_, entity)| *entity)
+                .next()
+                .ok_or_else(|| {
+                    RangeError::new(ErrorCode::Jump, "There is no loop to jump out of")
+                        .with_note("Only loop, while and for loops can be jumped out of")
+                }),
+        }
     }
 
     fn declare_var(&mut self, name: &'a str, entities: &Entities) -> Entity {
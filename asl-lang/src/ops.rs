@@ -1,12 +1,13 @@
 use ast::Scoped;
-use ast::{self, ActionKind, Children};
-use code_gen::{CodeGenDesc, Op};
-use debug_info::ReferencesVar;
+use ast::{self, Access, Accessor, ActionKind, Children, Field};
+use code_gen::{CodeGenDesc, MemoryRegionField, Op};
+use debug_info::{Docs, ReferencesVar};
 use debug_info::SrcByteRange;
-use name_resolution::{FunctionDecl, VarDecl, VarNames};
+use name_resolution::{FunctionDecl, Jump, VarDecl, VarNames, Vars};
 use reg_extend::{ExtendConnection, InferExtending, NeedsExtending};
 use specs::prelude::*;
-use types::{Inference, Ty, TypeChecking};
+use struct_resolution::StructLit;
+use types::{AnchorKind, Inference, Tuple, Ty, TypeAnchor, TypeChecking};
 
 pub fn bin_op_extend(left: Entity, right: Entity, op: Op) -> CodeGenDesc {
     CodeGenDesc(vec![
@@ -45,6 +46,65 @@ pub fn build_action(
     ast::Item::Action(kind, entity)
 }
 
+pub fn build_block(world: &mut World, statements: Vec<Entity>, range: SrcByteRange) -> Entity {
+    // A block that ends in a jump never finishes.
+    let ty = match world.read_storage::<Ty>().get(*statements.last().unwrap()) {
+        Some(Ty::Never) => Ty::Never,
+        _ => Ty::Unit,
+    };
+
+    world
+        .create_entity()
+        .with(CodeGenDesc(statements.iter().cloned().map(Op::Entity).collect()))
+        .with(Children(statements))
+        .with(Scoped)
+        .with(ast::Block)
+        .with(ty)
+        .with(range)
+        .build()
+}
+
+pub fn build_jump(
+    world: &mut World,
+    jump: Jump,
+    value: Option<Entity>,
+    range: SrcByteRange,
+) -> Entity {
+    let (op, is_continue) = match jump {
+        Jump::Break(_) => (Op::Break(0), false),
+        Jump::Continue(_) => (Op::Continue(0), true),
+        Jump::Return => (Op::Return, false),
+    };
+
+    // The value has the type of the loop or the function the jump leaves,
+    // while the jump itself has the never type.
+    let mut value_builder = world.create_entity().with(jump).with(range);
+    value_builder = if let Some(value) = value {
+        value_builder
+            .with(Children(vec![value]))
+            .with(TypeChecking(vec![
+                Inference::SameAsMe(value),
+                Inference::VarSameAsMe(0),
+            ])).with(CodeGenDesc(vec![Op::Entity(value), Op::Extend(value), op]))
+    } else if is_continue {
+        value_builder.with(Ty::Unit).with(CodeGenDesc(vec![op]))
+    } else {
+        value_builder
+            .with(TypeChecking(vec![Inference::VarSameAsMe(0)]))
+            .with(Ty::Unit)
+            .with(CodeGenDesc(vec![op]))
+    };
+    let value = value_builder.build();
+
+    world
+        .create_entity()
+        .with(Children(vec![value]))
+        .with(CodeGenDesc(vec![Op::Entity(value)]))
+        .with(Ty::Never)
+        .with(range)
+        .build()
+}
+
 pub fn build_compare(
     world: &mut World,
     l: Entity,
@@ -128,8 +188,9 @@ pub fn build_fn(
     world: &mut World,
     fn_name: String,
     params: Vec<(String, Option<Ty>, SrcByteRange)>,
-    ty: Option<Ty>,
+    ty: Option<(Ty, SrcByteRange)>,
     block: Entity,
+    docs: Option<String>,
     (l, r): (usize, usize),
 ) -> ast::Item {
     let mut param_entities = Vec::new();
@@ -144,7 +205,10 @@ pub fn build_fn(
             .with(range);
 
         if let Some(ty) = ty {
-            builder = builder.with(ty);
+            builder = builder.with(ty).with(TypeAnchor {
+                kind: AnchorKind::Parameter,
+                range,
+            });
         }
 
         param_entities.push(builder.build());
@@ -168,9 +232,261 @@ pub fn build_fn(
             ExtendConnection::StoreVar(0),
         ]));
 
-    if let Some(ty) = ty {
-        entity = entity.with(ty);
+    if let Some((ty, range)) = ty {
+        entity = entity.with(ty).with(TypeAnchor {
+            kind: AnchorKind::Annotation,
+            range,
+        });
+    }
+
+    if let Some(docs) = docs {
+        entity = entity.with(Docs(docs));
     }
 
     ast::Item::Function(entity.build())
 }
+
+pub fn build_read(
+    world: &mut World,
+    ty: Ty,
+    module: String,
+    offsets: Vec<Entity>,
+    range: SrcByteRange,
+) -> Entity {
+    let offset_area = world.create_entity().build();
+
+    let mut children = Vec::new();
+    let mut ops = Vec::new();
+
+    for (index, offset) in offsets.into_iter().enumerate() {
+        let entity = world
+            .create_entity()
+            .with(Children(vec![offset]))
+            .with(TypeChecking(vec![Inference::SameAsMe(offset)]))
+            .with(Ty::I64)
+            .with(CodeGenDesc(vec![
+                Op::ReadOffsetAddress(offset_area, index),
+                Op::Entity(offset),
+                Op::StoreOffset,
+            ])).build();
+
+        children.push(entity);
+        ops.push(Op::Entity(entity));
+    }
+
+    ops.push(Op::ReadMemory(offset_area, module, children.len()));
+
+    world
+        .create_entity()
+        .with(Children(children))
+        .with(CodeGenDesc(ops))
+        .with(ty)
+        .with(range)
+        .build()
+}
+
+pub fn build_state_var_element(
+    world: &mut World,
+    is_current: bool,
+    field: String,
+    accesses: Vec<Access>,
+    range: SrcByteRange,
+) -> Entity {
+    let accessors = accesses
+        .iter()
+        .map(|a| match a {
+            Access::Index(_) => Accessor::Index,
+            Access::Field(name) => Accessor::Field(name.clone()),
+        }).collect::<Vec<_>>();
+
+    let mut children = Vec::new();
+    let mut ops = vec![Op::StateVarElement(
+        is_current,
+        field.clone(),
+        accessors.clone(),
+    )];
+
+    for access in accesses {
+        if let Access::Index(index) = access {
+            let entity = world
+                .create_entity()
+                .with(Children(vec![index]))
+                .with(TypeChecking(vec![Inference::SameAsMe(index)]))
+                .with(Ty::I32)
+                .with(CodeGenDesc(vec![Op::Entity(index)]))
+                .build();
+
+            ops.push(Op::Entity(entity));
+            ops.push(Op::StateVarIndex(
+                field.clone(),
+                accessors.clone(),
+                children.len(),
+            ));
+            children.push(entity);
+        }
+    }
+
+    ops.push(Op::ReadStateVarElement(field.clone(), accessors.clone()));
+
+    world
+        .create_entity()
+        .with(Children(children))
+        .with(TypeChecking(vec![Inference::StateVarSameAsMe(
+            field, accessors,
+        )]))
+        .with(CodeGenDesc(ops))
+        .with(range)
+        .build()
+}
+
+pub fn build_memory_region(
+    world: &mut World,
+    address: Entity,
+    field: MemoryRegionField,
+    range: SrcByteRange,
+) -> Entity {
+    let entity = world
+        .create_entity()
+        .with(Children(vec![address]))
+        .with(TypeChecking(vec![Inference::SameAsMe(address)]))
+        .with(Ty::I64)
+        .with(CodeGenDesc(vec![Op::Entity(address)]))
+        .build();
+
+    let ty = match field {
+        MemoryRegionField::Base | MemoryRegionField::Size => Ty::I64,
+        MemoryRegionField::Protection => Ty::U32,
+    };
+
+    world
+        .create_entity()
+        .with(Children(vec![entity]))
+        .with(CodeGenDesc(vec![
+            Op::Entity(entity),
+            Op::MemoryRegion(field),
+        ]))
+        .with(ty)
+        .with(range)
+        .build()
+}
+
+pub fn build_field_access(
+    world: &mut World,
+    expr: Entity,
+    field: Field,
+    range: SrcByteRange,
+) -> Entity {
+    // The struct or tuple gets stored in a variable, so the registers of the field can
+    // be loaded on their own.
+    let cached_expr = world.create_entity().build();
+
+    let store_expr = world
+        .create_entity()
+        .with(Children(vec![expr]))
+        .with(Vars(vec![cached_expr]))
+        .with(TypeChecking(vec![
+            Inference::SameAsMe(expr),
+            Inference::VarSameAsMe(0),
+        ])).with(CodeGenDesc(vec![Op::Entity(expr), Op::StoreVar(0)]))
+        .build();
+
+    world
+        .create_entity()
+        .with(Children(vec![store_expr]))
+        .with(Vars(vec![cached_expr]))
+        .with(TypeChecking(vec![Inference::FieldSameAsMe(
+            store_expr,
+            field.clone(),
+        )])).with(CodeGenDesc(vec![
+            Op::Entity(store_expr),
+            Op::LoadField(0, field),
+        ]))
+        // The field may hold a value that wasn't extended yet.
+        .with(NeedsExtending)
+        .with(range)
+        .build()
+}
+
+pub fn build_tuple_decl(
+    world: &mut World,
+    names: Vec<Option<(String, SrcByteRange)>>,
+    ty: Option<(Ty, SrcByteRange)>,
+    expr: Entity,
+    range: SrcByteRange,
+) -> Entity {
+    let cached_expr = world.create_entity().build();
+
+    // Without a type the pattern at least tells how many fields the tuple has.
+    let (ty, anchor) = match ty {
+        Some((ty, range)) => (
+            ty,
+            Some(TypeAnchor {
+                kind: AnchorKind::Annotation,
+                range,
+            }),
+        ),
+        None => (Ty::Tuple(Tuple::new_count(names.len())), None),
+    };
+    let mut store_expr = world
+        .create_entity()
+        .with(Children(vec![expr]))
+        .with(Vars(vec![cached_expr]))
+        .with(TypeChecking(vec![
+            Inference::SameAsMe(expr),
+            Inference::VarSameAsMe(0),
+        ])).with(CodeGenDesc(vec![Op::Entity(expr), Op::StoreVar(0)]))
+        .with(ty)
+        .with(range);
+    if let Some(anchor) = anchor {
+        store_expr = store_expr.with(anchor);
+    }
+    let store_expr = store_expr.build();
+
+    let mut children = vec![store_expr];
+    for (index, name) in names.into_iter().enumerate() {
+        if let Some((name, range)) = name {
+            let field = Field::Index(index);
+            children.push(
+                world
+                    .create_entity()
+                    .with(Vars(vec![cached_expr]))
+                    .with(VarNames(vec![name]))
+                    .with(VarDecl(0))
+                    .with(ReferencesVar(1))
+                    .with(TypeChecking(vec![
+                        Inference::FieldSameAsMe(store_expr, field.clone()),
+                        Inference::VarSameAsMe(1),
+                    ])).with(CodeGenDesc(vec![Op::LoadField(0, field), Op::StoreVar(1)]))
+                    // The field may hold a value that wasn't extended yet.
+                    .with(NeedsExtending)
+                    .with(InferExtending(vec![ExtendConnection::StoreVar(1)]))
+                    .with(range)
+                    .build(),
+            );
+        }
+    }
+
+    world
+        .create_entity()
+        .with(CodeGenDesc(children.iter().cloned().map(Op::Entity).collect()))
+        .with(Children(children))
+        .with(Ty::Unit)
+        .build()
+}
+
+pub fn build_struct_lit(
+    world: &mut World,
+    fields: Vec<(String, Entity)>,
+    range: SrcByteRange,
+) -> Entity {
+    // Which struct this is only gets known once the structs are resolved, so
+    // the types and code of the literal get filled in by the struct resolution.
+    let (names, values) = fields.into_iter().unzip();
+
+    world
+        .create_entity()
+        .with(StructLit(names))
+        .with(Children(values))
+        .with(range)
+        .build()
+}
@@ -1,9 +1,10 @@
-use ast::{Children, Source};
+use ast::{Children, Field, Source};
 use function_signatures::FunctionSignatureRegisters;
 use name_resolution::Vars;
 use parity_wasm::elements::ValueType;
 use specs::prelude::*;
-use types::{Tuple, Ty};
+use std::ops::Range;
+use types::{Struct, Tuple, Ty};
 
 #[derive(Component)]
 #[storage(DenseVecStorage)]
@@ -153,8 +154,12 @@ impl Ty {
             Ty::I64 | Ty::U64 => Some(ValueType::I64),
             Ty::F32 => Some(ValueType::F32),
             Ty::F64 => Some(ValueType::F64),
-            Ty::Unit => None,
-            Ty::Tuple(Tuple(types)) => {
+            Ty::Unit | Ty::Never => None,
+            Ty::Tuple(Tuple(types))
+            | Ty::Struct(Struct {
+                fields: Tuple(types),
+                ..
+            }) => {
                 for ty in types.read().unwrap().iter() {
                     ty.as_ref().unwrap().populate_registers(registers);
                 }
@@ -169,6 +174,42 @@ impl Ty {
         self.populate_registers(&mut registers);
         Registers(registers)
     }
+    /// The types of the registers that are actually used by a value of the
+    /// type.
+    pub fn value_types(&self) -> Vec<ValueType> {
+        self.create_registers_description()
+            .0
+            .into_iter()
+            .flatten()
+            .map(|(ty, _)| ty)
+            .collect()
+    }
+    /// The scalar values making up a value of the type, in the order of the
+    /// registers they are stored in.
+    pub fn scalars(&self) -> Vec<Ty> {
+        match self {
+            Ty::Tuple(Tuple(types))
+            | Ty::Struct(Struct {
+                fields: Tuple(types),
+                ..
+            }) => types
+                .read()
+                .unwrap()
+                .iter()
+                .flat_map(|ty| ty.as_ref().unwrap().scalars())
+                .collect(),
+            Ty::Unit | Ty::Never => Vec::new(),
+            ty => vec![ty.clone()],
+        }
+    }
+    /// The range of the registers of a value of the type that hold the field.
+    pub fn field_registers(&self, field: &Field) -> Range<usize> {
+        let (Tuple(fields), index) = self.field(field).unwrap();
+        let fields = fields.read().unwrap();
+        let count = |ty: &Option<Ty>| ty.as_ref().unwrap().create_registers_description().0.len();
+        let start = fields[..index].iter().map(count).sum::<usize>();
+        start..start + count(&fields[index])
+    }
     pub fn value_type(&self) -> Option<ValueType> {
         match self {
             Ty::Bool | Ty::U8 | Ty::U16 | Ty::U32 | Ty::I8 | Ty::I16 | Ty::I32 => {
@@ -177,7 +218,7 @@ impl Ty {
             Ty::I64 | Ty::U64 => Some(ValueType::I64),
             Ty::F32 => Some(ValueType::F32),
             Ty::F64 => Some(ValueType::F64),
-            Ty::Unit => None,
+            Ty::Unit | Ty::Never => None,
             _ => unreachable!("This general type shouldn't make it to the reg alloc"),
         }
     }
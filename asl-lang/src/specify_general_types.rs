@@ -1,5 +1,5 @@
 use specs::prelude::*;
-use types::Ty;
+use types::{Struct, Ty};
 
 pub struct SpecifyGeneralTypes;
 
@@ -22,7 +22,7 @@ fn process_ty(ty: &mut Ty) {
         Ty::Int | Ty::Number => *ty = Ty::I32,
         Ty::Float => *ty = Ty::F64,
         Ty::Bits => unreachable!("The Bits type shouldn't get past the type inference"),
-        Ty::Tuple(tuple) => {
+        Ty::Tuple(tuple) | Ty::Struct(Struct { fields: tuple, .. }) => {
             for ty in tuple.0.write().unwrap().iter_mut().flatten() {
                 process_ty(ty);
             }
@@ -0,0 +1,234 @@
+use ast::{self, Children, Item, Source};
+use code_gen::{CodeGenDesc, Op};
+use debug_info::SrcByteRange;
+use error::{ErrorCode, RangeError, RangeResult, ResultExt};
+use specs::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use types::{Inference, Struct, Tuple, Ty, TypeChecking};
+
+/// The names of the fields of a struct literal. The children are the values
+/// of the fields in the same order.
+#[derive(Component)]
+pub struct StructLit(pub Vec<String>);
+
+/// Replaces the names of structs in the types with the structs they refer to
+/// and figures out which struct each struct literal constructs.
+pub struct StructResolution<'s> {
+    src: &'s Source,
+    result: RangeResult<()>,
+}
+
+impl<'s> StructResolution<'s> {
+    pub fn new(src: &'s Source) -> Self {
+        Self {
+            src,
+            result: Ok(()),
+        }
+    }
+    pub fn run(mut self, world: &World) -> RangeResult<()> {
+        self.run_now(&world.res);
+        self.result
+    }
+}
+
+type SystemData<'a> = (
+    Entities<'a>,
+    ReadStorage<'a, StructLit>,
+    ReadStorage<'a, Children>,
+    WriteStorage<'a, Ty>,
+    WriteStorage<'a, TypeChecking>,
+    WriteStorage<'a, CodeGenDesc>,
+    ReadStorage<'a, SrcByteRange>,
+);
+
+impl<'a, 's> System<'a> for StructResolution<'s> {
+    type SystemData = SystemData<'a>;
+
+    fn run(&mut self, sys_data: SystemData) {
+        self.result = try_run(&self.src, sys_data);
+    }
+}
+
+fn try_run(
+    src: &Source,
+    (entities, struct_lits, children, mut types, mut type_checking, mut code_gen_descs, ranges): SystemData,
+) -> RangeResult<()> {
+    let decls = src
+        .items
+        .iter()
+        .filter_map(|i| match i {
+            Item::Struct(s) => Some(s),
+            _ => None,
+        }).collect::<Vec<_>>();
+
+    let mut structs = HashMap::new();
+    for decl in &decls {
+        let mut field_names = Vec::new();
+        for field in &decl.fields {
+            if field_names.contains(&field.name) {
+                return Err(RangeError::new(
+                    ErrorCode::Struct,
+                    format!(
+                        "The struct '{}' has the field '{}' more than once",
+                        decl.name, field.name
+                    ),
+                ));
+            }
+            field_names.push(field.name.clone());
+        }
+        let s = Struct {
+            name: decl.name.clone(),
+            field_names: Arc::new(field_names),
+            fields: Tuple::new_count(decl.fields.len()),
+        };
+        if structs.insert(decl.name.as_str(), s).is_some() {
+            return Err(RangeError::new(
+                ErrorCode::Struct,
+                format!("The struct '{}' is declared more than once", decl.name),
+            ));
+        }
+    }
+
+    for decl in &decls {
+        let mut fields = structs[decl.name.as_str()].fields.0.write().unwrap();
+        for (field, resolved) in decl.fields.iter().zip(fields.iter_mut()) {
+            let mut ty = field.ty.clone().ok_or_else(|| {
+                RangeError::new(
+                    ErrorCode::Struct,
+                    format!(
+                        "The field '{}' of the struct '{}' needs a type",
+                        field.name, decl.name
+                    ),
+                )
+            })?;
+            resolve_ty(&mut ty, &structs)?;
+            *resolved = Some(ty);
+        }
+    }
+
+    for decl in &decls {
+        check_recursion(
+            &Ty::Struct(structs[decl.name.as_str()].clone()),
+            &mut Vec::new(),
+        )?;
+    }
+
+    for (entity, ty) in (&*entities, &mut types).join() {
+        resolve_ty(ty, &structs).with_entity_range(entity, &ranges)?;
+    }
+
+    for (entity, StructLit(names), Children(values)) in (&*entities, &struct_lits, &children).join()
+    {
+        let s = find_struct(&decls, &structs, names).with_entity_range(entity, &ranges)?;
+
+        // The fields are evaluated in the order they are declared in, so they
+        // end up in the right registers.
+        let mut fields = names
+            .iter()
+            .zip(values)
+            .map(|(name, value)| (s.field_index(name).unwrap(), *value))
+            .collect::<Vec<_>>();
+        fields.sort_by_key(|&(index, _)| index);
+
+        let _ = type_checking.insert(
+            entity,
+            TypeChecking(
+                fields
+                    .iter()
+                    .map(|&(index, value)| Inference::SameAsField(index, value))
+                    .collect(),
+            ),
+        );
+        let _ = code_gen_descs.insert(
+            entity,
+            CodeGenDesc(fields.iter().map(|&(_, value)| Op::Entity(value)).collect()),
+        );
+        let _ = types.insert(entity, Ty::Struct(s));
+    }
+
+    Ok(())
+}
+
+fn resolve_ty(ty: &mut Ty, structs: &HashMap<&str, Struct>) -> RangeResult<()> {
+    match ty {
+        Ty::Struct(s) => {
+            let resolved = structs.get(s.name.as_str()).cloned().ok_or_else(|| {
+                RangeError::new(
+                    ErrorCode::Unresolved,
+                    format!("Unresolved struct '{}'", s.name),
+                )
+            })?;
+            *s = resolved;
+        }
+        Ty::Tuple(Tuple(tys)) => {
+            for ty in tys.write().unwrap().iter_mut().flatten() {
+                resolve_ty(ty, structs)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn check_recursion(ty: &Ty, outer: &mut Vec<String>) -> RangeResult<()> {
+    let fields = match ty {
+        Ty::Tuple(Tuple(fields)) => fields,
+        Ty::Struct(s) => {
+            if outer.contains(&s.name) {
+                return Err(RangeError::new(
+                    ErrorCode::Struct,
+                    format!("The struct '{}' contains itself", s.name),
+                ));
+            }
+            outer.push(s.name.clone());
+            for field in s.fields.0.read().unwrap().iter().flatten() {
+                check_recursion(field, outer)?;
+            }
+            outer.pop();
+            return Ok(());
+        }
+        _ => return Ok(()),
+    };
+    for field in fields.read().unwrap().iter().flatten() {
+        check_recursion(field, outer)?;
+    }
+    Ok(())
+}
+
+/// Struct literals don't mention the struct, so the struct is the one that has
+/// exactly the fields of the literal.
+fn find_struct(
+    decls: &[&ast::Struct],
+    structs: &HashMap<&str, Struct>,
+    names: &[String],
+) -> RangeResult<Struct> {
+    for (i, name) in names.iter().enumerate() {
+        if names[..i].contains(name) {
+            return Err(RangeError::new(
+                ErrorCode::Struct,
+                format!("The field '{}' is specified more than once", name),
+            ));
+        }
+    }
+
+    let mut candidates = decls.iter().filter(|decl| {
+        decl.fields.len() == names.len()
+            && decl.fields.iter().all(|field| names.contains(&field.name))
+    });
+
+    match (candidates.next(), candidates.next()) {
+        (Some(decl), None) => Ok(structs[decl.name.as_str()].clone()),
+        (Some(a), Some(b)) => Err(RangeError::new(
+            ErrorCode::Struct,
+            format!(
+                "The struct literal could be a '{}' or a '{}', as they have the same fields",
+                a.name, b.name
+            ),
+        )),
+        (None, _) => Err(RangeError::new(
+            ErrorCode::Struct,
+            "There is no struct with exactly these fields",
+        )),
+    }
+}
@@ -1,6 +1,33 @@
 use debug_info::{Hover, Span};
+use lexer::{lex, Tok};
+use parity_wasm;
 use types::Ty;
-use {compile, hover};
+use wasmi::{
+    self, FuncInstance, FuncRef, ImportsBuilder, ModuleImportResolver, ModuleInstance,
+    NopExternals, RuntimeValue, Signature,
+};
+use {compile, hover, ErrorCode, Severity};
+
+/// Resolves the imports with functions that can't actually be called, so the
+/// code can run as long as it doesn't interact with the runtime.
+struct NoRuntime;
+
+impl ModuleImportResolver for NoRuntime {
+    fn resolve_func(&self, _: &str, signature: &Signature) -> Result<FuncRef, wasmi::Error> {
+        Ok(FuncInstance::alloc_host(signature.clone(), 0))
+    }
+}
+
+fn run(code: &str, export: &str) -> Option<RuntimeValue> {
+    let binary = parity_wasm::serialize(compile(code).unwrap().0).unwrap();
+    let module = wasmi::Module::from_buffer(binary).unwrap();
+    let imports = ImportsBuilder::new().with_resolver("env", &NoRuntime);
+    ModuleInstance::new(&module, &imports)
+        .unwrap()
+        .assert_no_start()
+        .invoke_export(export, &[], &mut NopExternals)
+        .unwrap()
+}
 
 #[test]
 fn test_hover() {
@@ -26,6 +53,7 @@ start {
         entity: result.entity,
         params: None,
         ty: Ty::U8,
+        docs: None,
         span: Span {
             from: (7, 17),
             to: (8, 8),
@@ -35,6 +63,38 @@ start {
     assert_eq!(result, expected);
 }
 
+#[test]
+fn comments() {
+    let code = r#"state("game.exe") {
+    /// The current level.
+    level: u8 = "game.exe", 0x10; // Not "//" a string
+}
+
+/* A block comment /* with a nested one */ that goes on */
+split {
+    // Split on every new level.
+    is_new(current.level, old.level)
+}
+
+/// Whether the level changed.
+/// Only checks the number.
+fn is_new(a: u8, b: u8) -> bool {
+    a != b
+}"#;
+    compile(code).unwrap();
+
+    let function = hover(code, 9, 5).unwrap().unwrap();
+    assert_eq!(
+        function.docs.as_ref().map(String::as_str),
+        Some("Whether the level changed.\nOnly checks the number.")
+    );
+    let state_var = hover(code, 9, 12).unwrap().unwrap();
+    assert_eq!(
+        state_var.docs.as_ref().map(String::as_str),
+        Some("The current level.")
+    );
+}
+
 #[test]
 fn normal_compile() {
     compile(
@@ -68,6 +128,64 @@ split {
     ).unwrap();
 }
 
+#[test]
+fn literals() {
+    let result = run(
+        r#"state("game.exe") {}
+
+split {
+    let thousand = 1_000;
+    let small = 5u8;
+    let bits = 0b1010 + 0o17 + 0xFF;
+    let big = 1.5e3;
+    let single = 1.0f32;
+
+    thousand-1 == 999 && small == 5 && bits == 280 && big == 1500.0 && single == 1f32 && -2 < 0
+}"#,
+        "should_split",
+    );
+    assert_eq!(result, Some(RuntimeValue::I32(1)));
+
+    let strings = lex(r##""a\tb\u{e9}\x41" r"C:\Path" r#"a "quoted" word"#"##)
+        .tokens
+        .into_iter()
+        .map(|(_, tok, _)| tok)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        strings,
+        [
+            Tok::Str(String::from("a\tb\u{e9}A")),
+            Tok::Str(String::from(r"C:\Path")),
+            Tok::Str(String::from(r#"a "quoted" word"#)),
+        ]
+    );
+
+    let invalid = errors(
+        r#"state("game\q.exe") {}
+
+split {
+    let a = 300u8;
+    let b = 0b102;
+    let c = 1.5u8;
+    let d = 5x;
+    let _e = a;
+    true #
+}"#,
+    );
+    assert_eq!(
+        invalid,
+        [
+            "Invalid escape sequence '\\q'",
+            "The literal 300 doesn't fit into u8",
+            "Invalid digit in a binary literal",
+            "A float literal can't have the suffix 'u8'",
+            "Invalid suffix 'x' for a number literal",
+            "Names can't start with an underscore",
+            "Unexpected character '#'",
+        ]
+    );
+}
+
 #[test]
 fn function_call() {
     compile(
@@ -156,3 +274,639 @@ fn foo(a, b, c) {
 }"#,
     ).unwrap_err();
 }
+
+#[test]
+fn one_off_reads() {
+    compile(
+        r#"state("game.exe") {
+}
+
+split {
+    let index: i64 = 3;
+    let version = read::<u8>("game.exe", 0x10);
+    let base = read::<u32>("", 0x1000) as i64;
+    let value = read::<u32>("game.exe", 0x20, base, 4 * index,);
+    version == 2 && value == 5
+}"#,
+    ).unwrap();
+}
+
+#[test]
+fn emulated_pointer_paths() {
+    compile(
+        r#"state(emulator: "gb") {
+    level: u8 = wram 0xC362;
+    hp: u16 = wram 0xC3A0;
+}
+
+split {
+    current.level > old.level && current.hp > 0
+}"#,
+    ).unwrap();
+}
+
+#[test]
+fn big_endian_pointer_paths() {
+    compile(
+        r#"state("Dolphin.exe") {
+    level: u32 = "Dolphin.exe", 0x10, 0x20 be;
+    igt: f64 = "Dolphin.exe", 0x30 le;
+    frames: u32 = "Dolphin.exe", 0x40, 0x8 be ptr32;
+}
+
+split {
+    current.level > old.level
+}"#,
+    ).unwrap();
+}
+
+#[test]
+fn emulated_pointer_paths_need_emulator() {
+    compile(
+        r#"state("bgb.exe") {
+    level: u8 = wram 0xC362;
+}
+
+split {
+    current.level > old.level
+}"#,
+    ).unwrap_err();
+}
+
+#[test]
+fn arrays_and_layouts() {
+    compile(
+        r#"layout Player {
+    hp: u16 = 0x4;
+    position: [f32; 3] = 0x10;
+}
+
+state("game.exe") {
+    items: [u8; 16] = "game.exe", 0x100;
+    player: Player = "game.exe", 0x200, 0x8;
+    players: [Player; 4] = "game.exe", 0x300;
+}
+
+split {
+    let slot = 3;
+    current.items[slot] > old.items[slot]
+        && current.player.hp > 0
+        && current.players[1].position[2] > old.player.position[2]
+}"#,
+    ).unwrap();
+}
+
+#[test]
+fn arrays_need_to_be_indexed() {
+    compile(
+        r#"state("game.exe") {
+    items: [u8; 16] = "game.exe", 0x100;
+}
+
+split {
+    current.items > old.items
+}"#,
+    ).unwrap_err();
+}
+
+#[test]
+fn module_builtins() {
+    compile(
+        r#"state("game.exe") {}
+
+start {
+    let domain = module_export("mono.dll", "mono_get_root_domain");
+    let end = module_address("game.exe") + module_size("game.exe");
+    let writable = memory_region_protection(end) & 0x4 != 0;
+    domain != 0 && writable && memory_region_base(end) + memory_region_size(end) > end
+}"#,
+    ).unwrap();
+}
+
+#[test]
+fn mono_pointer_paths() {
+    compile(
+        r#"state("Game.exe") {
+    level: i32 = mono "Assembly-CSharp" GameManager.instance.level;
+    igt: f64 = mono "Assembly-CSharp" GameManager.instance.timer.elapsed;
+}
+
+split {
+    current.level > old.level
+}"#,
+    ).unwrap();
+}
+
+#[test]
+fn mono_pointer_paths_need_process() {
+    compile(
+        r#"state(emulator: "gb") {
+    level: i32 = mono "Assembly-CSharp" GameManager.instance.level;
+}
+
+split {
+    current.level > old.level
+}"#,
+    ).unwrap_err();
+}
+
+#[test]
+fn structs() {
+    let result = run(
+        r#"struct Position {
+    x: f32,
+    y: f32,
+}
+
+struct Player {
+    position: Position,
+    level: u8,
+}
+
+state("game.exe") {}
+
+split {
+    let position = { x: 3.0, y: -4.0 };
+    let player = spawn(position, 2);
+    let origin = { y: 0.0, x: 0.0 };
+    distance(player.position, origin) == 25.0 && player.position.y < origin.y && player.level > 1
+}
+
+fn spawn(position: Position, level: u8) -> Player {
+    { position, level }
+}
+
+fn distance(a: Position, b: Position) -> f32 {
+    let x = a.x - b.x;
+    let y = a.y - b.y;
+    x * x + y * y
+}"#,
+        "should_split",
+    );
+    assert_eq!(result, Some(RuntimeValue::I32(1)));
+}
+
+#[test]
+fn struct_errors() {
+    compile(
+        r#"struct Position {
+    x: f32,
+    y: f32,
+}
+
+state("game.exe") {}
+
+split {
+    let position = { x: 1.0, z: 2.0 };
+    true
+}"#,
+    ).unwrap_err();
+
+    compile(
+        r#"struct Position {
+    x: f32,
+    y: f32,
+}
+
+state("game.exe") {}
+
+split {
+    let position = { x: 1.0, y: 2.0 };
+    position.z > 0.0
+}"#,
+    ).unwrap_err();
+
+    compile(
+        r#"struct Node {
+    next: Node,
+}
+
+state("game.exe") {}
+
+split {
+    true
+}"#,
+    ).unwrap_err();
+}
+
+#[test]
+fn tuples() {
+    let result = run(
+        r#"state("game.exe") {}
+
+split {
+    let pair = swap((1, 2.5));
+    let (a, b) = pair;
+    let nested = ((b, 3), pair);
+    let (low, high): (u8, _) = if a > 1.0 { (1, 2) } else { (2, 1) };
+    let (_, last, _) = (1, 2, 3);
+
+    a == 2.5 && b == 1 && nested.0.1 == 3 && swap((3, 0.5)).1 == 3
+        && nested.1 == (2.5, 1) && pair != (2.5, 2) && low == 1 && high == 2 && last == 2
+}
+
+fn swap(pair: (i32, f64)) -> (f64, i32) {
+    (pair.1, pair.0)
+}"#,
+        "should_split",
+    );
+    assert_eq!(result, Some(RuntimeValue::I32(1)));
+}
+
+#[test]
+fn tuple_errors() {
+    compile(
+        r#"state("game.exe") {}
+
+split {
+    let (a, b, c) = (1, 2);
+    true
+}"#,
+    ).unwrap_err();
+
+    compile(
+        r#"state("game.exe") {}
+
+split {
+    let pair = (1, 2);
+    pair.2 == 0
+}"#,
+    ).unwrap_err();
+
+    compile(
+        r#"state("game.exe") {}
+
+split {
+    (1, 2) == (1, 2, 3)
+}"#,
+    ).unwrap_err();
+}
+
+#[test]
+fn short_circuiting() {
+    // Calling into the runtime traps in the tests, so the reads must never
+    // happen either.
+    let result = run(
+        r#"state("game.exe") {}
+
+split {
+    let hits = 0;
+    let a = false && { hits = hits + 1; true };
+    let b = true || { hits = hits + 1; false };
+    let c = true && { hits = hits + 1; true };
+    let d = false || { hits = hits + 1; true };
+    let e = false && read::<u8>("game.exe", 0x10) == 1;
+    let f = true || read::<u8>("game.exe", 0x10) == 1;
+
+    !a && b && c && d && !e && f && hits == 2
+}"#,
+        "should_split",
+    );
+    assert_eq!(result, Some(RuntimeValue::I32(1)));
+}
+
+#[test]
+fn control_flow() {
+    let result = run(
+        r#"state("game.exe") {}
+
+split {
+    let sum = 0;
+    for i in 0..10 {
+        if i == 3 { continue; };
+        if i == 6 { break; };
+        sum += i;
+    };
+
+    let steps = 0;
+    let found = loop {
+        steps += 1;
+        if steps * steps > 50 { break steps; };
+    };
+
+    let pairs = 0;
+    'outer: for a in 0..5 {
+        for b in 0..5 {
+            if b > a { continue 'outer; };
+            if a == 4 { break 'outer; };
+            pairs += 1;
+        };
+    };
+
+    let countdown = 5;
+    while true {
+        countdown = countdown - 1;
+        if countdown == 2 { break; };
+    };
+
+    let pair = loop { break (1, 2.5) };
+
+    sum == 12 && found == 8 && pairs == 10 && countdown == 2 && pair.1 == 2.5
+        && first_above(10) == 11 && sign(-3) == -1 && sign(3) == 1
+}
+
+fn first_above(limit: i32) -> i32 {
+    let i = 0;
+    loop {
+        i += 1;
+        if i > limit { return i; };
+    }
+}
+
+fn sign(x: i32) -> i32 {
+    let result = if x < 0 { return -1; } else { 1 };
+    result
+}"#,
+        "should_split",
+    );
+    assert_eq!(result, Some(RuntimeValue::I32(1)));
+}
+
+#[test]
+fn control_flow_errors() {
+    compile(
+        r#"state("game.exe") {}
+
+split {
+    break;
+    true
+}"#,
+    ).unwrap_err();
+
+    compile(
+        r#"state("game.exe") {}
+
+split {
+    'outer: while true {
+        break 'inner;
+    };
+    true
+}"#,
+    ).unwrap_err();
+
+    compile(
+        r#"state("game.exe") {}
+
+split {
+    while true {
+        break 5;
+    };
+    true
+}"#,
+    ).unwrap_err();
+
+    compile(
+        r#"state("game.exe") {}
+
+split {
+    return 5;
+}"#,
+    ).unwrap_err();
+}
+
+#[test]
+fn warnings() {
+    let (_, warnings) = compile(
+        r#"state("game.exe") {
+    level: u8 = "game.exe", 0x10;
+    lives: u8 = "game.exe", 0x20;
+}
+
+split {
+    let unused = 5;
+    let ignored: u8 = 2.5;
+    let level = current.level;
+    if level > 3 && is_valid(level, 2) {
+        return true;
+        level == 4
+    } else {
+        false
+    }
+}
+
+fn is_valid(value: u8, bonus: u8) -> bool {
+    value < 10
+}
+
+fn broken(x: i32) -> i32 {
+    x && true
+}"#,
+    ).unwrap();
+
+    let messages = warnings
+        .iter()
+        .map(|w| w.message.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        [
+            "The state variable 'lives' is never used",
+            "The variable 'unused' is never used",
+            "The variable 'ignored' is never used",
+            "This code is never reached",
+            "The variable 'bonus' is never used",
+            "The function 'broken' is never used",
+        ]
+    );
+}
+
+fn errors(code: &str) -> Vec<String> {
+    compile(code)
+        .unwrap_err()
+        .into_iter()
+        .filter(|d| d.severity == Severity::Error)
+        .map(|d| d.message)
+        .collect()
+}
+
+#[test]
+fn multiple_errors() {
+    let syntax_errors = errors(
+        r#"state("game.exe") {}
+
+start {
+    let x = ;
+    true
+}
+
+split {
+    let y = 5 +;
+    true
+}"#,
+    );
+    assert_eq!(syntax_errors.len(), 2);
+
+    let name_errors = errors(
+        r#"state("game.exe") {}
+
+split {
+    a == 1 && b == 2
+}"#,
+    );
+    assert_eq!(
+        name_errors,
+        [
+            "Variable 'a' is not in scope",
+            "Variable 'b' is not in scope",
+        ]
+    );
+
+    let call_errors = errors(
+        r#"state("game.exe") {}
+
+split {
+    bar(1, 2, 3) && bar(baz(4), 5)
+}"#,
+    );
+    assert_eq!(
+        call_errors,
+        [
+            "Function 'bar' is not in scope",
+            "Function 'bar' is not in scope",
+            "Function 'baz' is not in scope",
+        ]
+    );
+
+    let type_errors = errors(
+        r#"state("game.exe") {}
+
+split {
+    let a: u8 = true;
+    let b: bool = 5;
+    a == 1 && b
+}"#,
+    );
+    assert_eq!(type_errors.len(), 2);
+}
+
+#[test]
+fn error_details() {
+    let errors = compile(
+        r#"state("game.exe") {}
+
+split {
+    let level: u8 = true;
+    level == 1
+}"#,
+    ).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].code, Some(ErrorCode::TypeConflict));
+    assert_eq!(
+        errors[0].message,
+        "Expected u8 because of the type annotation, found bool because of the literal"
+    );
+    let labels = errors[0]
+        .labels
+        .iter()
+        .map(|l| (l.message.as_str(), l.span.from, l.span.to))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        labels,
+        [
+            ("u8 because of this type annotation", (4, 16), (4, 18)),
+            ("bool because of this literal", (4, 21), (4, 25)),
+        ]
+    );
+
+    let errors = compile(
+        r#"state("game.exe") {
+    speed: f32 = "game.exe", 0x10;
+}
+
+split {
+    let level: u8 = current.speed;
+    level == 1
+}"#,
+    ).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].message,
+        "Expected u8 because of the type annotation, found f32 because of the state variable declaration"
+    );
+    assert_eq!(
+        errors[0].labels[1].span,
+        Span {
+            from: (2, 5),
+            to: (2, 10),
+        }
+    );
+
+    let errors = compile(
+        r#"state("game.exe") {}
+
+split {
+    add(1) == 2
+}
+
+fn add(a: i32, b: i32) -> i32 {
+    a + b
+}"#,
+    ).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].code, Some(ErrorCode::ArgumentCount));
+    assert_eq!(errors[0].labels[0].message, "the function is declared here");
+    assert_eq!(
+        errors[0].labels[0].span,
+        Span {
+            from: (7, 4),
+            to: (7, 7),
+        }
+    );
+}
+
+#[test]
+fn imports_match_abi() {
+    use asl_abi;
+    use parity_wasm::elements::{External, Section, Type, ValueType};
+
+    let (module, _) = compile(
+        r#"state("game.exe") {
+    level: u8 = "game.exe", 0x10;
+    igt: f64 = "game.exe", 0x20, 0x8;
+}
+
+split {
+    read::<u32>("game.exe", 0x30) == 5 && current.level > old.level
+}"#,
+    ).unwrap();
+
+    let types = module.type_section().unwrap().types();
+    for entry in module.import_section().unwrap().entries() {
+        assert_eq!(entry.module(), asl_abi::IMPORT_MODULE);
+        let import = asl_abi::import(entry.field()).unwrap();
+        assert!(import.since <= asl_abi::VERSION);
+
+        let type_ref = match *entry.external() {
+            External::Function(type_ref) => type_ref,
+            _ => panic!("{} isn't imported as a function", entry.field()),
+        };
+        let func_type = match &types[type_ref as usize] {
+            Type::Function(func_type) => func_type,
+        };
+        let convert = |ty: &asl_abi::ValueType| match ty {
+            asl_abi::ValueType::I32 => ValueType::I32,
+            asl_abi::ValueType::I64 => ValueType::I64,
+            asl_abi::ValueType::F32 => ValueType::F32,
+            asl_abi::ValueType::F64 => ValueType::F64,
+        };
+        let params = import.params.iter().map(convert).collect::<Vec<_>>();
+        assert_eq!(func_type.params(), &params[..], "{}", entry.field());
+        assert_eq!(
+            func_type.return_type(),
+            import.result.as_ref().map(convert),
+            "{}",
+            entry.field()
+        );
+    }
+
+    let version = module
+        .sections()
+        .iter()
+        .filter_map(|section| match section {
+            Section::Custom(custom) if custom.name() == asl_abi::VERSION_SECTION => {
+                asl_abi::parse_version(custom.payload())
+            }
+            _ => None,
+        }).next();
+    assert_eq!(version, Some(asl_abi::VERSION));
+}
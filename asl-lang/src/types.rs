@@ -1,10 +1,12 @@
-use ast::{self, Source};
+use ast::{self, Accessor, Field, Source};
 use code_gen::CodeGenDesc;
 use debug_info::SrcByteRange;
-use error::{RangeError, RangeResult, ResultExt};
+use error::{ErrorCode, RangeError, RangeResult, ResultExt};
 use name_resolution::Vars;
 use specs::prelude::*;
 use specs::storage::GenericReadStorage;
+use std::collections::HashSet;
+use std::fmt;
 use std::sync::Arc;
 use std::sync::RwLock;
 
@@ -27,6 +29,10 @@ pub enum Ty {
     Number,
     Bits, // TODO Consider general union types
     Tuple(Tuple),
+    Struct(Struct),
+    /// The type of jumps, which never result in a value, so they fit in
+    /// anywhere.
+    Never,
 }
 
 #[derive(Debug, Clone)]
@@ -47,32 +53,93 @@ impl PartialEq for Tuple {
     }
 }
 
+/// A struct declared in the source. All values of the same struct share the
+/// types of the fields. Until the struct resolution ran, it's only a name
+/// without any fields.
+#[derive(Debug, Clone)]
+pub struct Struct {
+    pub name: String,
+    pub field_names: Arc<Vec<String>>,
+    pub fields: Tuple,
+}
+
+impl Struct {
+    pub fn unresolved(name: String) -> Self {
+        Struct {
+            name,
+            field_names: Arc::new(Vec::new()),
+            fields: Tuple::new(Vec::new()),
+        }
+    }
+
+    pub fn field_index(&self, name: &str) -> Option<usize> {
+        self.field_names.iter().position(|n| n == name)
+    }
+}
+
+impl PartialEq for Struct {
+    fn eq(&self, other: &Struct) -> bool {
+        self.name == other.name && self.fields == other.fields
+    }
+}
+
 #[derive(Component)]
 pub struct TypeChecking(pub Vec<Inference>);
 
+/// The code a type originally comes from. It gets passed along with the type
+/// during the inference, so type conflicts can point at their actual causes
+/// rather than where the conflicting types happen to meet.
+#[derive(Debug, Copy, Clone, PartialEq, Component)]
+pub struct TypeAnchor {
+    pub kind: AnchorKind,
+    pub range: SrcByteRange,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AnchorKind {
+    Literal,
+    Annotation,
+    StateVar,
+    Cast,
+    Parameter,
+}
+
+impl fmt::Display for AnchorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            AnchorKind::Literal => "literal",
+            AnchorKind::Annotation => "type annotation",
+            AnchorKind::StateVar => "state variable declaration",
+            AnchorKind::Cast => "cast",
+            AnchorKind::Parameter => "parameter",
+        })
+    }
+}
+
 pub enum Inference {
     SameAsMe(Entity),
     VarSameAsMe(usize),
-    StateVarSameAsMe(String),
+    StateVarSameAsMe(String, Vec<Accessor>),
     TypeHint(Entity),
     SameAsField(usize, Entity),
+    FieldSameAsMe(Entity, Field),
 }
 
 pub struct TypeSystem<'s> {
     src: &'s Source,
-    result: RangeResult<()>,
+    errors: Vec<RangeError>,
 }
 
 impl<'s> TypeSystem<'s> {
     pub fn new(src: &'s Source) -> Self {
         Self {
             src,
-            result: Ok(()),
+            errors: Vec::new(),
         }
     }
-    pub fn run(mut self, world: &World) -> RangeResult<()> {
+    pub fn run(mut self, world: &World) -> Vec<RangeError> {
         self.run_now(&world.res);
-        self.result
+        self.errors
     }
 }
 
@@ -82,50 +149,64 @@ type SystemData<'a> = (
     WriteStorage<'a, Ty>,
     ReadStorage<'a, Vars>,
     ReadStorage<'a, SrcByteRange>,
+    WriteStorage<'a, TypeAnchor>,
 );
 
 impl<'a, 's> System<'a> for TypeSystem<'s> {
     type SystemData = SystemData<'a>;
 
     fn run(&mut self, sys_data: SystemData) {
-        self.result = try_run(&self.src, sys_data);
+        self.errors = infer_types(&self.src, sys_data);
     }
 }
 
-fn try_run(
+fn infer_types(
     src: &Source,
-    (entities, type_checking, mut types, vars, ranges): SystemData,
-) -> RangeResult<()> {
-    let state = src.state()?;
+    (entities, type_checking, mut types, vars, ranges, mut anchors): SystemData,
+) -> Vec<RangeError> {
+    let state = match src.state() {
+        Ok(state) => state,
+        Err(e) => return vec![e],
+    };
+
+    // Entities that ran into an error aren't inferred any further, so each
+    // error only gets reported once.
+    let mut errors = Vec::new();
+    let mut failed = HashSet::new();
 
     loop {
         let mut is_dirty = false;
 
         run_once(
+            src,
             &state,
             &mut is_dirty,
+            &mut errors,
+            &mut failed,
             &entities,
             &type_checking,
             &mut types,
             &vars,
             &ranges,
-        )?;
+            &mut anchors,
+        );
 
         if !is_dirty {
-            let is_now_dirty = apply_hints(&entities, &type_checking, &mut types);
+            let is_now_dirty = apply_hints(&entities, &type_checking, &mut types, &mut anchors);
             if !is_now_dirty {
                 break;
             }
         }
     }
 
-    Ok(())
+    errors
 }
 
 fn apply_hints(
     entities: &Entities,
     type_checking: &ReadStorage<TypeChecking>,
     types: &mut WriteStorage<Ty>,
+    anchors: &mut WriteStorage<TypeAnchor>,
 ) -> bool {
     let mut is_dirty = false;
 
@@ -143,6 +224,8 @@ fn apply_hints(
                     };
                     if set {
                         let _ = types.insert(*other, my_ty.clone());
+                        let my_anchor = anchors.get(me).cloned();
+                        set_anchor(anchors, *other, my_anchor);
                     }
                 }
             }
@@ -153,68 +236,44 @@ fn apply_hints(
 }
 
 fn run_once(
+    src: &Source,
     state: &ast::State,
     is_dirty: &mut bool,
+    errors: &mut Vec<RangeError>,
+    failed: &mut HashSet<Entity>,
     entities: &Entities,
     type_checking: &ReadStorage<TypeChecking>,
     types: &mut WriteStorage<Ty>,
     vars: &ReadStorage<Vars>,
     ranges: &ReadStorage<SrcByteRange>,
-) -> RangeResult<()> {
+    anchors: &mut WriteStorage<TypeAnchor>,
+) {
     for (me, TypeChecking(inference_list)) in (&**entities, type_checking).join() {
+        if failed.contains(&me) {
+            continue;
+        }
+
         let mut inner_is_dirty = false;
         let mut my_ty = types.get(me).cloned();
+        let mut my_anchor = anchors.get(me).cloned();
 
-        for inference in inference_list {
-            match inference {
-                Inference::SameAsMe(other) => {
-                    let other_ty = types.get(*other);
-                    if let Some(ty) = spread(my_ty.as_ref(), other_ty, &mut inner_is_dirty, &types)
-                        .with_entity_range(me, ranges)?
-                    {
-                        my_ty = Some(ty.clone());
-                    }
-                }
-                Inference::VarSameAsMe(var_id) => {
-                    let var = vars.get(me).unwrap().0[*var_id];
-                    let other_ty = types.get(var);
-                    if let Some(ty) = spread(my_ty.as_ref(), other_ty, &mut inner_is_dirty, &types)
-                        .with_entity_range(me, ranges)?
-                    {
-                        my_ty = Some(ty.clone());
-                    }
-                }
-                Inference::StateVarSameAsMe(field_name) => {
-                    let path = state.lookup(field_name).with_entity_range(me, ranges)?;
-                    if let Some(ty) =
-                        spread(my_ty.as_ref(), Some(&path.ty), &mut inner_is_dirty, &types)
-                            .with_entity_range(me, ranges)?
-                    {
-                        my_ty = Some(ty.clone());
-                    }
-                }
-                Inference::SameAsField(field_idx, other) => {
-                    // TODO Maybe do this outside of the loop so we don't need
-                    // to lock the fields all the time.
-                    // TODO Probably should infer unknown type to tuple too.
-                    // Would error out on "Not a tuple".
-                    let mut my_fields = match &mut my_ty {
-                        Some(Ty::Tuple(Tuple(fields))) => fields.write().unwrap(),
-                        _ => panic!("Not a tuple"),
-                    };
-                    let other_ty = types.get(*other);
-                    let my_ty = &mut my_fields[*field_idx];
-                    if let Some(ty) = spread(my_ty.as_ref(), other_ty, &mut inner_is_dirty, &types)
-                        .with_entity_range(me, ranges)?
-                    {
-                        *my_ty = Some(ty.clone());
-                    }
-                }
-                Inference::TypeHint(_) => {
-                    // Type Hinting has very low priority, so we only do it once
-                    // we normally would finish the type inference.
-                }
-            }
+        if let Err(e) = infer(
+            src,
+            state,
+            me,
+            inference_list,
+            &mut my_ty,
+            &mut my_anchor,
+            &mut inner_is_dirty,
+            types,
+            vars,
+            ranges,
+            anchors,
+        ).with_entity_range(me, ranges)
+        {
+            failed.insert(me);
+            errors.push(e);
+            continue;
         }
 
         if inner_is_dirty {
@@ -223,22 +282,36 @@ fn run_once(
                     match inference {
                         Inference::SameAsMe(other) => {
                             let _ = types.insert(*other, my_ty.clone());
+                            set_anchor(anchors, *other, my_anchor);
                         }
                         Inference::VarSameAsMe(var_id) => {
                             let var = vars.get(me).unwrap().0[*var_id];
                             let _ = types.insert(var, my_ty.clone());
+                            set_anchor(anchors, var, my_anchor);
                         }
-                        Inference::StateVarSameAsMe(_) => {
+                        Inference::StateVarSameAsMe(..) => {
                             // No need to back propagate to a state
                             // variable, as it already is fully typed.
                         }
                         Inference::SameAsField(field_idx, other) => {
                             let my_fields = match &my_ty {
-                                Ty::Tuple(Tuple(fields)) => fields.read().unwrap(),
+                                Ty::Tuple(Tuple(fields))
+                                | Ty::Struct(Struct {
+                                    fields: Tuple(fields),
+                                    ..
+                                }) => fields.read().unwrap(),
                                 _ => unreachable!(),
                             };
                             if let Some(my_ty) = &my_fields[*field_idx] {
                                 let _ = types.insert(*other, my_ty.clone());
+                                set_anchor(anchors, *other, my_anchor);
+                            }
+                        }
+                        Inference::FieldSameAsMe(other, field) => {
+                            if let Some(ty) = types.get(*other) {
+                                if let Ok((Tuple(fields), index)) = ty.field(field) {
+                                    fields.write().unwrap()[index] = Some(my_ty.clone());
+                                }
                             }
                         }
                         Inference::TypeHint(_) => {
@@ -248,10 +321,84 @@ fn run_once(
                     }
                 }
                 let _ = types.insert(me, my_ty);
+                set_anchor(anchors, me, my_anchor);
                 *is_dirty = true;
             }
         }
     }
+}
+
+fn infer(
+    src: &Source,
+    state: &ast::State,
+    me: Entity,
+    inference_list: &[Inference],
+    my_ty: &mut Option<Ty>,
+    my_anchor: &mut Option<TypeAnchor>,
+    is_dirty: &mut bool,
+    types: &WriteStorage<Ty>,
+    vars: &ReadStorage<Vars>,
+    ranges: &ReadStorage<SrcByteRange>,
+    anchors: &WriteStorage<TypeAnchor>,
+) -> RangeResult<()> {
+    for inference in inference_list {
+        match inference {
+            Inference::SameAsMe(other) => {
+                let other = (types.get(*other), anchors.get(*other).cloned());
+                take_over(me, my_ty, my_anchor, other, is_dirty, types, ranges)?;
+            }
+            Inference::VarSameAsMe(var_id) => {
+                let var = vars.get(me).unwrap().0[*var_id];
+                let other = (types.get(var), anchors.get(var).cloned());
+                take_over(me, my_ty, my_anchor, other, is_dirty, types, ranges)?;
+            }
+            Inference::StateVarSameAsMe(field_name, accessors) => {
+                let path = state.lookup(field_name)?;
+                let element = src.element(path, accessors)?;
+                let anchor = TypeAnchor {
+                    kind: AnchorKind::StateVar,
+                    range: path.range,
+                };
+                let other = (Some(&element.ty), Some(anchor));
+                take_over(me, my_ty, my_anchor, other, is_dirty, types, ranges)?;
+            }
+            Inference::SameAsField(field_idx, other) => {
+                // TODO Maybe do this outside of the loop so we don't need
+                // to lock the fields all the time.
+                // TODO Probably should infer unknown type to tuple too.
+                // Would error out on "Not a tuple".
+                let mut my_fields = match my_ty {
+                    Some(Ty::Tuple(Tuple(fields)))
+                    | Some(Ty::Struct(Struct {
+                        fields: Tuple(fields),
+                        ..
+                    })) => fields.write().unwrap(),
+                    _ => panic!("Not a tuple"),
+                };
+                let other_ty = types.get(*other);
+                let my_ty = &mut my_fields[*field_idx];
+                if let Some(ty) = spread(my_ty.as_ref(), other_ty, is_dirty, types)? {
+                    *my_ty = Some(ty.clone());
+                }
+            }
+            Inference::FieldSameAsMe(other, field) => {
+                let field_ty = match types.get(*other) {
+                    Some(ty) => {
+                        let (Tuple(fields), index) = ty.field(field)?;
+                        let field_ty = fields.read().unwrap()[index].clone();
+                        field_ty
+                    }
+                    None => None,
+                };
+                let other = (field_ty.as_ref(), anchors.get(*other).cloned());
+                take_over(me, my_ty, my_anchor, other, is_dirty, types, ranges)?;
+            }
+            Inference::TypeHint(_) => {
+                // Type Hinting has very low priority, so we only do it once
+                // we normally would finish the type inference.
+            }
+        }
+    }
 
     Ok(())
 }
@@ -268,6 +415,86 @@ impl<'a> System<'a> for CheckForUnassignedTypes {
     }
 }
 
+/// Spreads the other type to mine. If I take it over, I also take over where
+/// it's coming from.
+fn take_over(
+    me: Entity,
+    my_ty: &mut Option<Ty>,
+    my_anchor: &mut Option<TypeAnchor>,
+    (other_ty, other_anchor): (Option<&Ty>, Option<TypeAnchor>),
+    is_dirty: &mut bool,
+    types: &WriteStorage<Ty>,
+    ranges: &ReadStorage<SrcByteRange>,
+) -> RangeResult<()> {
+    let ty = spread(my_ty.as_ref(), other_ty, is_dirty, types).map_err(|e| {
+        explain_conflict(
+            e,
+            me,
+            (my_ty.as_ref(), *my_anchor),
+            (other_ty, other_anchor),
+            ranges,
+        )
+    })?;
+    if let Some(ty) = ty {
+        *my_ty = Some(ty.clone());
+        *my_anchor = other_anchor;
+    }
+    Ok(())
+}
+
+fn set_anchor(anchors: &mut WriteStorage<TypeAnchor>, entity: Entity, anchor: Option<TypeAnchor>) {
+    match anchor {
+        Some(anchor) => {
+            let _ = anchors.insert(entity, anchor);
+        }
+        None => {
+            anchors.remove(entity);
+        }
+    }
+}
+
+/// Rephrases a type conflict in terms of where both of the types are coming
+/// from and points at that code, unless that's where the error already points
+/// at.
+fn explain_conflict(
+    error: RangeError,
+    me: Entity,
+    (expected, expected_anchor): (Option<&Ty>, Option<TypeAnchor>),
+    (found, found_anchor): (Option<&Ty>, Option<TypeAnchor>),
+    ranges: &ReadStorage<SrcByteRange>,
+) -> RangeError {
+    let (expected, found) = match (error.code, expected, found) {
+        (ErrorCode::TypeConflict, Some(expected), Some(found)) => (expected, found),
+        _ => return error,
+    };
+
+    let because = |ty: &Ty, anchor: Option<TypeAnchor>| match anchor {
+        Some(anchor) => format!("{} because of the {}", ty, anchor.kind),
+        None => ty.to_string(),
+    };
+    let mut error = RangeError::new(
+        ErrorCode::TypeConflict,
+        format!(
+            "Expected {}, found {}",
+            because(expected, expected_anchor),
+            because(found, found_anchor)
+        ),
+    );
+
+    for (ty, anchor) in vec![(expected, expected_anchor), (found, found_anchor)] {
+        if let Some(anchor) = anchor {
+            if ranges.get(me) != Some(&anchor.range) {
+                error = error.with_label(
+                    Some(anchor.range),
+                    format!("{} because of this {}", ty, anchor.kind),
+                );
+            }
+        }
+    }
+
+    error
+}
+
 fn inner_spread<'a>(
     a: Option<&Ty>,
     b: Option<&'a Ty>,
@@ -281,6 +508,8 @@ fn inner_spread<'a>(
     };
     Ok(match (a, b) {
         (a, b) if a == b => None,
+        (Ty::Never, x) => Some(x),
+        (_, Ty::Never) => None,
         (Ty::Int, x) if x.is_specific_int() => Some(x),
         (Ty::Float, x) if x.is_specific_float() => Some(x),
         (Ty::Number, x) if x.is_more_specific_number() => Some(x),
@@ -291,8 +520,13 @@ fn inner_spread<'a>(
         (x, Ty::Number) if x.is_more_specific_number() => None,
         (x, Ty::Bits) if x.is_more_specific_bits_type() => None,
         (Ty::Tuple(Tuple(x)), Ty::Tuple(Tuple(y))) => {
+            if x.read().unwrap().len() != y.read().unwrap().len() {
+                return Err(RangeError::new(
+                    ErrorCode::TypeConflict,
+                    format!("Type conflict between {} and {}", a, b),
+                ));
+            }
             // TODO Recursive types (that's the unwrap)
-            // TODO todo zip_exact or something like that
             for (a, b) in x.write().unwrap().iter_mut().zip(y.read().unwrap().iter()) {
                 if spread(a.as_ref(), b.as_ref(), is_dirty, types)?.is_some() {
                     *a = b.clone();
@@ -301,10 +535,10 @@ fn inner_spread<'a>(
             None
         }
         _ => {
-            return Err(RangeError::new(format!(
-                "Type conflict between {} and {}",
-                a, b,
-            )))
+            return Err(RangeError::new(
+                ErrorCode::TypeConflict,
+                format!("Type conflict between {} and {}", a, b),
+            ))
         }
     })
 }
@@ -316,11 +550,38 @@ fn spread<'a>(
     types: &WriteStorage<Ty>,
 ) -> RangeResult<Option<&'a Ty>> {
     let new_val = inner_spread(a, b, is_dirty, types)?;
-    *is_dirty |= a != b;
+    // Tuples only compare equal if they are the same tuple, so two separate
+    // tuples with the same element types would never stop being dirty. The
+    // spreading of their elements already tracks whether anything changed.
+    *is_dirty |= match (a, b) {
+        (Some(Ty::Tuple(_)), Some(Ty::Tuple(_))) => false,
+        _ => a != b,
+    };
     Ok(new_val)
 }
 
 impl Ty {
+    /// Looks up which field of a tuple or a struct is accessed.
+    pub fn field(&self, field: &Field) -> RangeResult<(&Tuple, usize)> {
+        let index = match (self, field) {
+            (Ty::Tuple(Tuple(fields)), Field::Index(index))
+                if *index < fields.read().unwrap().len() =>
+            {
+                Some(*index)
+            }
+            (Ty::Struct(s), Field::Name(name)) => s.field_index(name),
+            _ => None,
+        };
+        match (self, index) {
+            (Ty::Tuple(tuple), Some(index)) => Ok((tuple, index)),
+            (Ty::Struct(s), Some(index)) => Ok((&s.fields, index)),
+            _ => Err(RangeError::new(
+                ErrorCode::UnknownField,
+                format!("The type {} has no field '{}'", self, field),
+            )),
+        }
+    }
+
     fn is_specific_int(&self) -> bool {
         use self::Ty::*;
         match self {
@@ -362,12 +623,11 @@ impl Ty {
     }
 }
 
-use std::fmt;
-
 impl fmt::Display for Ty {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Ty::Unit => write!(f, "unit"),
+            Ty::Never => write!(f, "!"),
             Ty::Bool => write!(f, "bool"),
             Ty::U8 => write!(f, "u8"),
             Ty::U16 => write!(f, "u16"),
@@ -397,6 +657,7 @@ impl fmt::Display for Ty {
                 }
                 write!(f, ")")
             }
+            Ty::Struct(s) => write!(f, "{}", s.name),
         }
     }
 }
@@ -0,0 +1,211 @@
+use ast::{Block, Children, Source};
+use code_gen::{CodeGenDesc, Op};
+use debug_info::SrcByteRange;
+use error::RangeWarning;
+use name_resolution::{DeclaredBy, FunctionDecl, LoopLabel, VarDecl, VarNames, Vars};
+use specs::prelude::*;
+use std::collections::HashSet;
+use types::Ty;
+
+/// Looks for functions, variables and state variables that are never used and
+/// for code that is never reached. The functions that are never used are
+/// returned as well, as they don't need to be type checked or compiled. So are
+/// the declarations of the variables that are never used, as their types don't
+/// matter.
+pub struct UsageAnalysis<'s> {
+    src: &'s Source,
+    warnings: Vec<RangeWarning>,
+    unused_functions: Vec<Entity>,
+    unused_decls: Vec<Entity>,
+}
+
+impl<'s> UsageAnalysis<'s> {
+    pub fn new(src: &'s Source) -> Self {
+        Self {
+            src,
+            warnings: Vec::new(),
+            unused_functions: Vec::new(),
+            unused_decls: Vec::new(),
+        }
+    }
+    pub fn run(mut self, world: &World) -> (Vec<RangeWarning>, Vec<Entity>, Vec<Entity>) {
+        self.run_now(&world.res);
+        (self.warnings, self.unused_functions, self.unused_decls)
+    }
+}
+
+impl<'a, 's> System<'a> for UsageAnalysis<'s> {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Children>,
+        ReadStorage<'a, Block>,
+        ReadStorage<'a, Vars>,
+        ReadStorage<'a, VarNames>,
+        ReadStorage<'a, VarDecl>,
+        ReadStorage<'a, DeclaredBy>,
+        ReadStorage<'a, FunctionDecl>,
+        ReadStorage<'a, LoopLabel>,
+        ReadStorage<'a, CodeGenDesc>,
+        ReadStorage<'a, Ty>,
+        ReadStorage<'a, SrcByteRange>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            children,
+            blocks,
+            vars,
+            var_names,
+            var_decls,
+            declared_bys,
+            function_decls,
+            loop_labels,
+            code_gen_descs,
+            types,
+            ranges,
+        ): Self::SystemData,
+    ) {
+        // Everything that can be reached from the actions is used, including
+        // the functions they call.
+        let src = self.src;
+        let mut used_code = HashSet::new();
+        let mut pending = src
+            .code_items()
+            .filter(|(kind, _)| kind.is_some())
+            .map(|(_, entity)| entity)
+            .collect::<Vec<_>>();
+        while let Some(entity) = pending.pop() {
+            if !used_code.insert(entity) {
+                continue;
+            }
+            if let Some(Vars(vars)) = vars.get(entity) {
+                pending.extend(
+                    vars.iter()
+                        .filter(|&&var| function_decls.get(var).is_some()),
+                );
+            }
+            if let Some(Children(children)) = children.get(entity) {
+                pending.extend(children);
+            }
+        }
+
+        for (kind, entity) in src.code_items() {
+            if kind.is_none() && !used_code.contains(&entity) {
+                let FunctionDecl(name_id, _) = function_decls.get(entity).unwrap();
+                let name = &var_names.get(entity).unwrap().0[*name_id];
+                self.warnings.push(RangeWarning::new(
+                    format!("The function '{}' is never used", name),
+                    ranges.get(entity).cloned(),
+                ));
+                self.unused_functions.push(entity);
+            }
+        }
+
+        let mut read_vars = HashSet::new();
+        let mut used_state_vars = HashSet::new();
+        let mut broken_loops = HashSet::new();
+        for &entity in &used_code {
+            if let Some(CodeGenDesc(ops)) = code_gen_descs.get(entity) {
+                for op in ops {
+                    match op {
+                        Op::LoadVar(i) | Op::LoadField(i, _) => {
+                            read_vars.insert(vars.get(entity).unwrap().0[*i]);
+                        }
+                        Op::Break(i) => {
+                            broken_loops.insert(vars.get(entity).unwrap().0[*i]);
+                        }
+                        Op::StateVar(_, name)
+                        | Op::StateVarElement(_, name, _)
+                        | Op::ReadStateVarElement(name, _) => {
+                            used_state_vars.insert(name.as_str());
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        for (var, DeclaredBy(decl)) in (&*entities, &declared_bys).join() {
+            if used_code.contains(decl) && !read_vars.contains(&var) {
+                let VarDecl(name_id) = var_decls.get(*decl).unwrap();
+                let name = &var_names.get(*decl).unwrap().0[*name_id];
+                self.warnings.push(RangeWarning::new(
+                    format!("The variable '{}' is never used", name),
+                    ranges.get(*decl).cloned(),
+                ));
+                self.unused_decls.push(*decl);
+            }
+        }
+
+        if let Ok(state) = src.state() {
+            for path in &state.paths {
+                if !used_state_vars.contains(path.name.as_str()) {
+                    self.warnings.push(RangeWarning::new(
+                        format!("The state variable '{}' is never used", path.name),
+                        Some(path.range),
+                    ));
+                }
+            }
+        }
+
+        for (entity, Children(statements), _) in (&*entities, &children, &blocks).join() {
+            if !used_code.contains(&entity) {
+                continue;
+            }
+            let diverging = statements.iter().position(|&statement| {
+                diverges(statement, &children, &loop_labels, &types, &broken_loops)
+            });
+            if let Some(&unreachable) = diverging.and_then(|index| statements.get(index + 1)) {
+                self.warnings.push(RangeWarning::new(
+                    "This code is never reached",
+                    find_range(unreachable, &children, &ranges),
+                ));
+            }
+        }
+
+        self.warnings
+            .sort_by_key(|warning| warning.range.map(|range| range.0));
+    }
+}
+
+/// Whether the code never finishes. This only looks at jumps and loops that
+/// are never broken out of, along with the code wrapping them.
+fn diverges(
+    entity: Entity,
+    children: &ReadStorage<Children>,
+    loop_labels: &ReadStorage<LoopLabel>,
+    types: &ReadStorage<Ty>,
+    broken_loops: &HashSet<Entity>,
+) -> bool {
+    match types.get(entity) {
+        Some(Ty::Never) => loop_labels.get(entity).is_none() || !broken_loops.contains(&entity),
+        Some(_) => false,
+        None => match children.get(entity) {
+            Some(Children(children_of_entity)) if children_of_entity.len() == 1 => diverges(
+                children_of_entity[0],
+                children,
+                loop_labels,
+                types,
+                broken_loops,
+            ),
+            _ => false,
+        },
+    }
+}
+
+fn find_range(
+    entity: Entity,
+    children: &ReadStorage<Children>,
+    ranges: &ReadStorage<SrcByteRange>,
+) -> Option<SrcByteRange> {
+    ranges.get(entity).cloned().or_else(|| {
+        children
+            .get(entity)?
+            .0
+            .iter()
+            .filter_map(|&child| find_range(child, children, ranges))
+            .next()
+    })
+}
@@ -0,0 +1,434 @@
+use pointer::PointerSize;
+use process::{self, Address, MemoryReader, Offset, Process};
+use std::result;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum Error {
+        UnknownRegion {}
+        AddressOutOfRange {}
+        SignatureNotFound {}
+        Parse(line: usize, reason: &'static str) {
+            display("Line {}: {}", line, reason)
+        }
+        Process(err: process::Error) {
+            from()
+        }
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// Describes how to find the memory backing a region of the emulated system.
+#[derive(Debug)]
+pub enum Base {
+    /// The region starts where the pointer path ends.
+    PointerPath { module: String, offsets: Vec<Offset> },
+    /// The pointer path starts at the 32-bit address found at the operand
+    /// offset of the first match of the signature in the module. `None`
+    /// matches any byte.
+    Signature {
+        module: String,
+        signature: Vec<Option<u8>>,
+        operand: u64,
+        offsets: Vec<Offset>,
+    },
+}
+
+/// A contiguous part of the emulated system's address space.
+#[derive(Debug)]
+pub struct Region {
+    pub name: String,
+    pub start: u64,
+    pub size: u64,
+    pub base: Base,
+}
+
+/// The way a specific emulator lays out the emulated system's memory.
+#[derive(Debug)]
+pub struct Strategy {
+    pub process: String,
+    pub regions: Vec<Region>,
+}
+
+#[derive(Debug)]
+pub struct Emulator {
+    pub name: String,
+    /// Whether the emulated system stores its values as big endian.
+    pub big_endian: bool,
+    /// The emulators supported for this system, in the order they are tried.
+    pub strategies: Vec<Strategy>,
+}
+
+/// A strategy along with where the pointer paths of its regions start in the
+/// attached emulator.
+#[derive(Debug)]
+pub struct Attachment {
+    strategy: &'static Strategy,
+    starts: Vec<Address>,
+}
+
+lazy_static! {
+    pub static ref EMULATORS: Vec<Emulator> =
+        parse(include_str!("emulators.txt")).expect("The emulator table is invalid");
+}
+
+pub fn emulator(name: &str) -> Option<&'static Emulator> {
+    EMULATORS.iter().find(|e| e.name == name)
+}
+
+/// Parses the emulator table. See `emulators.txt` for the format.
+pub fn parse(table: &str) -> Result<Vec<Emulator>> {
+    let mut emulators = Vec::<Emulator>::new();
+    for (index, line) in table.lines().enumerate() {
+        let error = |reason| Error::Parse(index + 1, reason);
+        let line = line.split('#').next().unwrap();
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        match keyword {
+            "system" => {
+                let name = words.next().ok_or(error("The system has no name"))?;
+                let big_endian = match words.next() {
+                    Some("little") => false,
+                    Some("big") => true,
+                    _ => return Err(error("The endianness needs to be little or big")),
+                };
+                emulators.push(Emulator {
+                    name: name.to_owned(),
+                    big_endian,
+                    strategies: Vec::new(),
+                });
+            }
+            "emulator" => {
+                let process = words.next().ok_or(error("The emulator has no process name"))?;
+                emulators
+                    .last_mut()
+                    .ok_or(error("The emulator isn't part of a system"))?
+                    .strategies
+                    .push(Strategy {
+                        process: process.to_owned(),
+                        regions: Vec::new(),
+                    });
+            }
+            "region" => {
+                let name = words.next().ok_or(error("The region has no name"))?;
+                let start = words
+                    .next()
+                    .and_then(parse_number)
+                    .ok_or(error("The region has no valid start"))?;
+                let size = words
+                    .next()
+                    .and_then(parse_number)
+                    .ok_or(error("The region has no valid size"))?;
+                let kind = words.next();
+                let module = words.next().ok_or(error("The region has no module"))?;
+                let base = match kind {
+                    Some("module") => Base::PointerPath {
+                        module: module.to_owned(),
+                        offsets: parse_offsets(words).ok_or(error("The offsets are invalid"))?,
+                    },
+                    Some("signature") => Base::Signature {
+                        module: module.to_owned(),
+                        signature: words
+                            .next()
+                            .and_then(parse_signature)
+                            .ok_or(error("The signature is invalid"))?,
+                        operand: words
+                            .next()
+                            .and_then(parse_number)
+                            .ok_or(error("The operand offset is invalid"))?
+                            as u64,
+                        offsets: parse_offsets(words).ok_or(error("The offsets are invalid"))?,
+                    },
+                    _ => return Err(error("The region needs a module or signature as its base")),
+                };
+                emulators
+                    .last_mut()
+                    .and_then(|e| e.strategies.last_mut())
+                    .ok_or(error("The region isn't part of an emulator"))?
+                    .regions
+                    .push(Region {
+                        name: name.to_owned(),
+                        start: start as u64,
+                        size: size as u64,
+                        base,
+                    });
+            }
+            _ => return Err(error("Unknown keyword")),
+        }
+    }
+    Ok(emulators)
+}
+
+fn parse_number(text: &str) -> Option<Offset> {
+    let negative = text.starts_with('-');
+    let text = if negative { &text[1..] } else { text };
+    let number = if text.starts_with("0x") {
+        Offset::from_str_radix(&text[2..], 16).ok()?
+    } else {
+        text.parse().ok()?
+    };
+    Some(if negative { -number } else { number })
+}
+
+fn parse_offsets<'a, I: Iterator<Item = &'a str>>(words: I) -> Option<Vec<Offset>> {
+    let offsets = words.map(parse_number).collect::<Option<Vec<_>>>()?;
+    if offsets.is_empty() {
+        None
+    } else {
+        Some(offsets)
+    }
+}
+
+fn parse_signature(text: &str) -> Option<Vec<Option<u8>>> {
+    if text.is_empty() || text.len() % 2 != 0 || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| match &text[i..i + 2] {
+            "??" => Some(None),
+            byte => u8::from_str_radix(byte, 16).ok().map(Some),
+        }).collect()
+}
+
+/// Finds the first match of the signature in the module's image. Pages that
+/// can't be read are treated as zeroes.
+fn scan<R: MemoryReader>(reader: &R, module: &str, signature: &[Option<u8>]) -> Result<Address> {
+    const PAGE_SIZE: usize = 0x1000;
+
+    let address = reader.module_address(module)?;
+    let size = reader.module_size(module)? as usize;
+    let mut image = vec![0; size];
+    for (index, page) in image.chunks_mut(PAGE_SIZE).enumerate() {
+        let _ = reader.read_buf(address + (index * PAGE_SIZE) as u64, page);
+    }
+
+    image
+        .windows(signature.len())
+        .position(|window| {
+            window
+                .iter()
+                .zip(signature)
+                .all(|(byte, expected)| expected.map_or(true, |e| e == *byte))
+        }).map(|position| address + position as u64)
+        .ok_or(Error::SignatureNotFound)
+}
+
+impl Emulator {
+    /// Whether all the emulators for this system provide the region.
+    pub fn has_region(&self, region: &str) -> bool {
+        self.strategies
+            .iter()
+            .all(|s| s.regions.iter().any(|r| r.name == region))
+    }
+
+    /// Attaches to the first supported emulator that is running and whose
+    /// regions can be found.
+    pub fn attach(&'static self) -> Option<(Process, Attachment)> {
+        self.strategies
+            .iter()
+            .filter_map(|strategy| {
+                let process = Process::with_name(&strategy.process).ok()?;
+                let starts = strategy.locate(&process).ok()?;
+                Some((process, Attachment { strategy, starts }))
+            }).next()
+    }
+}
+
+impl Strategy {
+    /// Finds where the pointer path of each region starts.
+    pub fn locate<R: MemoryReader>(&self, reader: &R) -> Result<Vec<Address>> {
+        self.regions
+            .iter()
+            .map(|region| match &region.base {
+                Base::PointerPath { module, .. } => Ok(reader.module_address(module)?),
+                Base::Signature {
+                    module,
+                    signature,
+                    operand,
+                    ..
+                } => {
+                    let found = scan(reader, module, signature)?;
+                    Ok(reader.read_u32(found + operand)? as Address)
+                }
+            }).collect()
+    }
+
+    /// Translates an address of the emulated system into an address in the
+    /// emulator's memory, given the starts found by `locate`.
+    pub fn resolve<R: MemoryReader>(
+        &self,
+        reader: &R,
+        starts: &[Address],
+        region: &str,
+        address: u64,
+    ) -> Result<Address> {
+        let index = self
+            .regions
+            .iter()
+            .position(|r| r.name == region)
+            .ok_or(Error::UnknownRegion)?;
+        let region = &self.regions[index];
+
+        if address < region.start || address - region.start >= region.size {
+            return Err(Error::AddressOutOfRange);
+        }
+
+        let offsets = match &region.base {
+            Base::PointerPath { offsets, .. } | Base::Signature { offsets, .. } => offsets,
+        };
+        let base = reader.follow_offsets(starts[index], offsets, false, PointerSize::Process)?;
+
+        Ok(base + (address - region.start))
+    }
+}
+
+impl Attachment {
+    /// Translates an address of the emulated system into an address in the
+    /// emulator's memory.
+    pub fn resolve(&self, process: &Process, region: &str, address: u64) -> Result<Address> {
+        self.strategy.resolve(process, &self.starts, region, address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: Address = 0x400000;
+    const WRAM: Address = 0x2000;
+
+    /// An emulator whose module is followed by the emulated work RAM.
+    struct FakeEmulator {
+        memory: Vec<u8>,
+    }
+
+    impl FakeEmulator {
+        fn new() -> Self {
+            let mut emulator = FakeEmulator {
+                memory: vec![0; 0x3000],
+            };
+            // The global that points to the emulator's state, and the state's
+            // pointer to the work RAM.
+            emulator.write_u32(BASE + 0x100, (BASE + 0x1000) as u32);
+            emulator.write_u32(BASE + 0x1000 + 0x74, (BASE + WRAM) as u32);
+            // mov eax, [BASE + 0x100]
+            emulator.memory[0x800..0x805].copy_from_slice(&[0xA1, 0x00, 0x01, 0x40, 0x00]);
+            emulator.memory[(WRAM + 0x10) as usize] = 0x42;
+            emulator
+        }
+
+        fn write_u32(&mut self, address: Address, value: u32) {
+            let start = (address - BASE) as usize;
+            for (i, byte) in self.memory[start..start + 4].iter_mut().enumerate() {
+                *byte = (value >> (8 * i)) as u8;
+            }
+        }
+    }
+
+    impl MemoryReader for FakeEmulator {
+        fn is_64bit(&self) -> bool {
+            false
+        }
+
+        fn module_address(&self, module: &str) -> process::Result<Address> {
+            match module {
+                "emulator.exe" => Ok(BASE),
+                _ => Err(process::Error::ModuleDoesntExist),
+            }
+        }
+
+        fn module_size(&self, module: &str) -> process::Result<u64> {
+            self.module_address(module)?;
+            Ok(WRAM)
+        }
+
+        fn module_export(&self, _: &str, _: &str) -> process::Result<Address> {
+            Err(process::Error::ExportDoesntExist)
+        }
+
+        fn read_buf(&self, address: Address, buf: &mut [u8]) -> process::Result<()> {
+            let start = address
+                .checked_sub(BASE)
+                .ok_or(process::Error::ReadMemory)? as usize;
+            let bytes = self
+                .memory
+                .get(start..start + buf.len())
+                .ok_or(process::Error::ReadMemory)?;
+            buf.copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    fn strategy(region: &str) -> Strategy {
+        let table = format!("system test little\nemulator emulator.exe\n{}", region);
+        let mut emulators = parse(&table).unwrap();
+        emulators.remove(0).strategies.remove(0)
+    }
+
+    fn read(emulator: &FakeEmulator, strategy: &Strategy, address: u64) -> Result<u8> {
+        let starts = strategy.locate(emulator)?;
+        let address = strategy.resolve(emulator, &starts, "wram", address)?;
+        let mut value = [0];
+        emulator.read_buf(address, &mut value)?;
+        Ok(value[0])
+    }
+
+    #[test]
+    fn parses_the_bundled_table() {
+        let gb = emulator("gb").unwrap();
+        assert!(!gb.big_endian);
+        assert_eq!(gb.strategies[0].process, "bgb.exe");
+        assert!(gb.has_region("wram"));
+        assert!(!gb.has_region("vram"));
+    }
+
+    #[test]
+    fn follows_pointer_paths_from_the_module() {
+        let emulator = FakeEmulator::new();
+        let strategy = strategy("region wram 0xC000 0x2000 module emulator.exe 0x100 0x74 0");
+        assert_eq!(read(&emulator, &strategy, 0xC010).unwrap(), 0x42);
+        match read(&emulator, &strategy, 0xE000) {
+            Err(Error::AddressOutOfRange) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn scans_for_signatures() {
+        let emulator = FakeEmulator::new();
+        let found =
+            strategy("region wram 0xC000 0x2000 signature emulator.exe A1????4000 1 0 0x74 0");
+        assert_eq!(read(&emulator, &found, 0xC010).unwrap(), 0x42);
+
+        let missing =
+            strategy("region wram 0xC000 0x2000 signature emulator.exe A1????4001 1 0 0x74 0");
+        match read(&emulator, &missing, 0xC010) {
+            Err(Error::SignatureNotFound) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_tables() {
+        let invalid = [
+            ("emulator bgb.exe", 1),
+            ("system gb middle", 1),
+            ("system gb little\nregion wram 0 1 module bgb.exe 0", 2),
+            ("system gb little\nemulator bgb.exe\nregion wram 0 1 module bgb.exe", 3),
+            ("system gb little\nemulator bgb.exe\nregion wram 0 1 signature bgb.exe A1? 0 0", 3),
+            ("system gb little\nemulator bgb.exe\nregion wram 0 1 symbol bgb.exe 0", 3),
+            ("# comment\nsystems gb little", 2),
+        ];
+        for &(table, line) in &invalid {
+            match parse(table) {
+                Err(Error::Parse(l, _)) if l == line => {}
+                other => panic!("{}: {:?}", table, other),
+            }
+        }
+    }
+}
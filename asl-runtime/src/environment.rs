@@ -1,6 +1,11 @@
+use asl_abi as abi;
+use emulator::{self, Attachment, Emulator};
+use mono::{Mono, MonoPath};
 use num_traits::FromPrimitive;
-use pointer::{PointerType, PointerValue};
+use pointer::{PointerSize, PointerType, PointerValue, ReadStatus};
+use process::{MemoryReader, Offset, Process};
 use std::{fmt, str};
+use wasmi::nan_preserving_float::F64;
 use wasmi::{
     Error, Externals, FuncInstance, FuncRef, GlobalDescriptor, GlobalRef, HostError,
     ImportResolver, MemoryDescriptor, MemoryRef, RuntimeArgs, RuntimeValue, Signature,
@@ -20,14 +25,61 @@ const GET_I32_FUNC_INDEX: usize = 9;
 const GET_I64_FUNC_INDEX: usize = 10;
 const GET_F32_FUNC_INDEX: usize = 11;
 const GET_F64_FUNC_INDEX: usize = 12;
+const SET_POINTER_PATH_NAME_FUNC_INDEX: usize = 13;
+const SET_POINTER_PATH_OFFSETS_FUNC_INDEX: usize = 14;
+const SET_POINTER_PATH_MODULE_FUNC_INDEX: usize = 15;
+const READ_MEMORY_FUNC_INDEX: usize = 16;
+const ADD_SETTING_FUNC_INDEX: usize = 17;
+const GET_SETTING_FUNC_INDEX: usize = 18;
+const SET_TICK_RATE_FUNC_INDEX: usize = 19;
+const PRINT_MESSAGE_FUNC_INDEX: usize = 20;
+const SET_EMULATOR_FUNC_INDEX: usize = 21;
+const PUSH_EMULATOR_POINTER_PATH_FUNC_INDEX: usize = 22;
+const SET_POINTER_PATH_SIZE_FUNC_INDEX: usize = 23;
+const GET_BYTES_FUNC_INDEX: usize = 24;
+const GET_MODULE_ADDRESS_FUNC_INDEX: usize = 25;
+const GET_MODULE_SIZE_FUNC_INDEX: usize = 26;
+const GET_MODULE_EXPORT_FUNC_INDEX: usize = 27;
+const GET_MEMORY_REGION_FUNC_INDEX: usize = 28;
+const PUSH_MONO_POINTER_PATH_FUNC_INDEX: usize = 29;
+
+const KNOWN_FLAGS: u32 =
+    abi::BIG_ENDIAN_FLAG | abi::POINTER_SIZE_32_FLAG | abi::POINTER_SIZE_64_FLAG;
+
+const MAX_POINTER_PATHS: usize = 1024;
+const MAX_OFFSETS: usize = 32;
+const MAX_STRING_LEN: usize = 1024;
+const MAX_READ_LEN: usize = 4096;
+const MAX_SETTINGS: usize = 256;
 
 #[derive(Debug)]
 enum EnvironmentError {
     InvalidProcessName,
     InvalidModuleName,
+    InvalidPointerPathName,
     InvalidPointerPathId,
     InvalidPointerType,
+    InvalidOffsets,
+    InvalidBuffer,
     TypeMismatch,
+    OnlyDuringConfigure(&'static str),
+    TooManyPointerPaths,
+    TooManyOffsets,
+    StringTooLong,
+    BufferTooLarge,
+    InvalidSettingKey,
+    InvalidSettingId,
+    TooManySettings,
+    InvalidTickRate,
+    InvalidMessage,
+    UnknownEmulator,
+    NoEmulator,
+    UnknownRegion,
+    EmulatedPointerPath,
+    OutOfBounds,
+    InvalidSymbolName,
+    InvalidMonoPath,
+    MonoPointerPath,
 }
 
 impl fmt::Display for EnvironmentError {
@@ -37,41 +89,346 @@ impl fmt::Display for EnvironmentError {
             EnvironmentError::InvalidModuleName => {
                 write!(f, "Invalid module name provided to construct pointer path")
             }
+            EnvironmentError::InvalidPointerPathName => {
+                write!(f, "Invalid name provided for the pointer path")
+            }
             EnvironmentError::InvalidPointerPathId => write!(f, "Invalid pointer path id provided"),
             EnvironmentError::InvalidPointerType => write!(f, "Invalid pointer type provided"),
+            EnvironmentError::InvalidOffsets => write!(f, "Invalid list of offsets provided"),
+            EnvironmentError::InvalidBuffer => write!(f, "Invalid buffer provided to read into"),
             EnvironmentError::TypeMismatch => {
                 write!(f, "Attempt to read from a value of the wrong type")
             }
+            EnvironmentError::OnlyDuringConfigure(name) => write!(
+                f,
+                "`{}` can only be called while the script is being configured",
+                name
+            ),
+            EnvironmentError::TooManyPointerPaths => write!(
+                f,
+                "The script can't register more than {} pointer paths",
+                MAX_POINTER_PATHS
+            ),
+            EnvironmentError::TooManyOffsets => write!(
+                f,
+                "A pointer path can't have more than {} offsets",
+                MAX_OFFSETS
+            ),
+            EnvironmentError::StringTooLong => write!(
+                f,
+                "Strings passed to the runtime can't be longer than {} bytes",
+                MAX_STRING_LEN
+            ),
+            EnvironmentError::BufferTooLarge => write!(
+                f,
+                "Memory reads can't be larger than {} bytes",
+                MAX_READ_LEN
+            ),
+            EnvironmentError::InvalidSettingKey => write!(f, "Invalid setting key provided"),
+            EnvironmentError::InvalidSettingId => write!(f, "Invalid setting id provided"),
+            EnvironmentError::TooManySettings => write!(
+                f,
+                "The script can't register more than {} settings",
+                MAX_SETTINGS
+            ),
+            EnvironmentError::InvalidTickRate => {
+                write!(f, "The tick rate needs to be a positive number")
+            }
+            EnvironmentError::InvalidMessage => write!(f, "Invalid message provided"),
+            EnvironmentError::UnknownEmulator => write!(f, "Unknown emulated system provided"),
+            EnvironmentError::NoEmulator => {
+                write!(
+                    f,
+                    "The emulated system needs to be set before using its memory"
+                )
+            }
+            EnvironmentError::UnknownRegion => {
+                write!(
+                    f,
+                    "The emulated system doesn't have the memory region provided"
+                )
+            }
+            EnvironmentError::OutOfBounds => {
+                write!(
+                    f,
+                    "Attempt to access bytes outside of the pointer path's value"
+                )
+            }
+            EnvironmentError::InvalidSymbolName => write!(f, "Invalid symbol name"),
+            EnvironmentError::InvalidMonoPath => write!(
+                f,
+                "Mono pointer paths need to consist of a class and at least one field"
+            ),
+            EnvironmentError::MonoPointerPath => {
+                write!(f, "Mono pointer paths can't be re-pointed")
+            }
+            EnvironmentError::EmulatedPointerPath => write!(
+                f,
+                "Pointer paths into emulated memory can only be re-pointed to another address"
+            ),
         }
     }
 }
 
 impl HostError for EnvironmentError {}
 
+fn trap(error: EnvironmentError) -> Trap {
+    Trap::new(TrapKind::Host(Box::new(error)))
+}
+
+/// The script registers its process and pointer paths while it is being
+/// configured. Once it is running, that setup is frozen and only the pointer
+/// paths that already exist can be re-pointed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Configure,
+    Running,
+}
+
 #[derive(Debug)]
 pub struct Environment {
     memory: MemoryRef,
-    pub process_name: String,
-    // TODO Undo pub
-    pub pointer_paths: Vec<PointerPath>,
+    pub(crate) process_name: String,
+    pub(crate) pointer_paths: Vec<PointerPath>,
+    pub(crate) process: Option<Process>,
+    pub(crate) emulator: Option<&'static Emulator>,
+    /// The way the attached emulator lays out the emulated system's memory.
+    pub(crate) strategy: Option<Attachment>,
+    /// The Mono structures found in the attached process.
+    pub(crate) mono: Mono,
+    pub(crate) settings: Vec<Setting>,
+    pub(crate) tick_rate: Option<f64>,
+    pub(crate) messages: Vec<String>,
+    pub(crate) host_calls: u64,
+    phase: Phase,
+    abi_version: u32,
 }
 
+/// A pointer path registered by the script. The runtime reads its value once
+/// per tick, keeping the value of the previous tick around as the old value.
 #[derive(Debug)]
 pub struct PointerPath {
-    pub module_name: String,
-    pub offsets: Vec<i64>,
-    // TODO Undo pub
-    pub current: PointerValue,
-    pub old: PointerValue,
+    pub(crate) name: String,
+    pub(crate) module_name: String,
+    pub(crate) offsets: Vec<i64>,
+    pub(crate) emulated: bool,
+    pub(crate) mono: Option<MonoPath>,
+    pub(crate) big_endian: bool,
+    pub(crate) pointer_size: PointerSize,
+    pub(crate) current: PointerValue,
+    pub(crate) old: PointerValue,
+    pub(crate) status: ReadStatus,
+}
+
+impl PointerPath {
+    /// The name of the state variable this pointer path belongs to. This is
+    /// empty if the script didn't provide a name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn module_name(&self) -> &str {
+        &self.module_name
+    }
+
+    pub fn offsets(&self) -> &[i64] {
+        &self.offsets
+    }
+
+    /// Whether the pointer path points into the memory of the emulated
+    /// system. The module name is then the name of the memory region and the
+    /// only offset is the address in the emulated system's address space.
+    pub fn is_emulated(&self) -> bool {
+        self.emulated
+    }
+
+    /// Whether the pointer path starts at a static field of a class of a Unity
+    /// game running on Mono. The module name is then the name of the
+    /// assembly.
+    pub fn is_mono(&self) -> bool {
+        self.mono.is_some()
+    }
+
+    /// Whether the value and the pointers along the way are stored as big
+    /// endian. Values in emulated memory of big endian systems are always
+    /// read as big endian.
+    pub fn is_big_endian(&self) -> bool {
+        self.big_endian
+    }
+
+    pub fn pointer_size(&self) -> PointerSize {
+        self.pointer_size
+    }
+
+    pub fn ty(&self) -> PointerType {
+        self.current.ty()
+    }
+
+    pub fn current(&self) -> &PointerValue {
+        &self.current
+    }
+
+    pub fn old(&self) -> &PointerValue {
+        &self.old
+    }
+
+    pub fn status(&self) -> ReadStatus {
+        self.status
+    }
+}
+
+/// A boolean option the script exposes to the user.
+#[derive(Debug)]
+pub struct Setting {
+    pub(crate) key: String,
+    pub(crate) value: bool,
+    pub(crate) default: bool,
+}
+
+impl Setting {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn value(&self) -> bool {
+        self.value
+    }
+
+    pub fn default_value(&self) -> bool {
+        self.default
+    }
 }
 
 impl Environment {
-    pub fn new(memory: MemoryRef) -> Self {
+    pub fn new(memory: MemoryRef, abi_version: u32) -> Self {
         Self {
             memory,
             process_name: String::new(),
             pointer_paths: Vec::new(),
+            process: None,
+            emulator: None,
+            strategy: None,
+            mono: Mono::default(),
+            settings: Vec::new(),
+            tick_rate: None,
+            messages: Vec::new(),
+            host_calls: 0,
+            phase: Phase::Configure,
+            abi_version,
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Freezes the process and the pointer paths the script registered.
+    pub fn finish_configuration(&mut self) {
+        self.phase = Phase::Running;
+    }
+
+    fn ensure_configuring(&self, name: &'static str) -> Result<(), Trap> {
+        if self.phase() == Phase::Configure {
+            Ok(())
+        } else {
+            Err(trap(EnvironmentError::OnlyDuringConfigure(name)))
+        }
+    }
+
+    fn read_str(&self, ptr: u32, len: u32, invalid: EnvironmentError) -> Result<String, Trap> {
+        let (ptr, len) = (ptr as usize, len as usize);
+        if len > MAX_STRING_LEN {
+            return Err(trap(EnvironmentError::StringTooLong));
+        }
+        self.memory
+            .with_direct_access(|m| {
+                let bytes = m.get(ptr..ptr.checked_add(len)?)?;
+                Some(str::from_utf8(bytes).ok()?.to_owned())
+            }).ok_or_else(|| trap(invalid))
+    }
+
+    fn read_offsets(&self, ptr: u32, len: u32) -> Result<Vec<Offset>, Trap> {
+        let (ptr, len) = (ptr as usize, len as usize);
+        if len > MAX_OFFSETS {
+            return Err(trap(EnvironmentError::TooManyOffsets));
+        }
+        self.memory
+            .with_direct_access(|m| {
+                let bytes = m.get(ptr..ptr.checked_add(len.checked_mul(8)?)?)?;
+                Some(
+                    bytes
+                        .chunks(8)
+                        .map(|b| b.iter().rev().fold(0, |o, &b| o << 8 | b as Offset))
+                        .collect(),
+                )
+            }).ok_or_else(|| trap(EnvironmentError::InvalidOffsets))
+    }
+
+    fn pointer_path_mut(&mut self, pointer_path_id: u32) -> Result<&mut PointerPath, Trap> {
+        self.pointer_paths
+            .get_mut(pointer_path_id as usize)
+            .ok_or_else(|| trap(EnvironmentError::InvalidPointerPathId))
+    }
+
+    /// Splits the pointer type passed by the script into the type and the
+    /// flags stored in its upper bits. Scripts from before the flags existed
+    /// may pass garbage in those bits, so they get ignored.
+    fn pointer_type(&self, raw: u32) -> Result<(PointerType, u32), Trap> {
+        let flags = if self.abi_version < 4 { 0 } else { raw & !0xFF };
+        let known_flags = if self.abi_version < 5 {
+            abi::BIG_ENDIAN_FLAG
+        } else {
+            KNOWN_FLAGS
+        };
+        let pointer_sizes = abi::POINTER_SIZE_32_FLAG | abi::POINTER_SIZE_64_FLAG;
+        if flags & !known_flags != 0 || flags & pointer_sizes == pointer_sizes {
+            return Err(trap(EnvironmentError::InvalidPointerType));
         }
+        let pointer_type = PointerType::from_u8(raw as u8)
+            .filter(|&ty| ty != PointerType::Bytes || self.abi_version >= 6)
+            .ok_or_else(|| trap(EnvironmentError::InvalidPointerType))?;
+        Ok((pointer_type, flags))
+    }
+
+    fn native_pointer_path_mut(&mut self, pointer_path_id: u32) -> Result<&mut PointerPath, Trap> {
+        let pointer_path = self.pointer_path_mut(pointer_path_id)?;
+        if pointer_path.emulated {
+            return Err(trap(EnvironmentError::EmulatedPointerPath));
+        }
+        if pointer_path.mono.is_some() {
+            return Err(trap(EnvironmentError::MonoPointerPath));
+        }
+        Ok(pointer_path)
+    }
+
+    fn get_val(
+        &mut self,
+        args: RuntimeArgs,
+        ty: PointerType,
+        convert: impl FnOnce(&PointerValue) -> Option<RuntimeValue>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let pointer_path_id: u32 = args.nth_checked(0)?;
+        let current: bool = args.nth_checked(1)?;
+
+        let legacy = self.abi_version == 0;
+        let pointer_path = self.pointer_path_mut(pointer_path_id)?;
+        if legacy && pointer_path.ty() != ty {
+            // Modules from before the ABI was versioned don't reliably
+            // register the type of their pointer paths, so the first read
+            // determines it instead. The value gets read on the next tick.
+            pointer_path.current = PointerValue::zeroed(ty);
+            pointer_path.old = PointerValue::zeroed(ty);
+            pointer_path.status = ReadStatus::NotRead;
+        }
+
+        let value = if current {
+            &pointer_path.current
+        } else {
+            &pointer_path.old
+        };
+        convert(value)
+            .map(Some)
+            .ok_or_else(|| trap(EnvironmentError::TypeMismatch))
     }
 }
 
@@ -81,120 +438,417 @@ impl Externals for Environment {
         index: usize,
         args: RuntimeArgs,
     ) -> Result<Option<RuntimeValue>, Trap> {
+        self.host_calls += 1;
         match index {
             SET_PROCESS_NAME_FUNC_INDEX => {
+                self.ensure_configuring("set_process_name")?;
                 let ptr: u32 = args.nth_checked(0)?;
-                let ptr = ptr as usize;
                 let len: u32 = args.nth_checked(1)?;
-                let len = len as usize;
-
-                self.process_name = self
-                    .memory
-                    .with_direct_access(|m| {
-                        Some(str::from_utf8(m.get(ptr..ptr + len)?).ok()?.to_owned())
-                    }).ok_or_else(|| {
-                        Trap::new(TrapKind::Host(Box::new(
-                            EnvironmentError::InvalidProcessName,
-                        )))
-                    })?;
+
+                self.process_name =
+                    self.read_str(ptr, len, EnvironmentError::InvalidProcessName)?;
 
                 Ok(None)
             }
             PUSH_POINTER_PATH_FUNC_INDEX => {
+                self.ensure_configuring("push_pointer_path")?;
+                if self.pointer_paths.len() >= MAX_POINTER_PATHS {
+                    return Err(trap(EnvironmentError::TooManyPointerPaths));
+                }
                 let ptr: u32 = args.nth_checked(0)?;
-                let ptr = ptr as usize;
                 let len: u32 = args.nth_checked(1)?;
-                let len = len as usize;
-                let pointer_type: u8 = args.nth_checked(2)?;
-                let pointer_type = PointerType::from_u8(pointer_type).ok_or_else(|| {
-                    Trap::new(TrapKind::Host(Box::new(
-                        EnvironmentError::InvalidPointerType,
-                    )))
-                })?;
-                let current = match pointer_type {
-                    PointerType::U8 => PointerValue::U8(0),
-                    PointerType::U16 => PointerValue::U16(0),
-                    PointerType::U32 => PointerValue::U32(0),
-                    PointerType::U64 => PointerValue::U64(0),
-                    PointerType::I8 => PointerValue::I8(0),
-                    PointerType::I16 => PointerValue::I16(0),
-                    PointerType::I32 => PointerValue::I32(0),
-                    PointerType::I64 => PointerValue::I64(0),
-                    PointerType::F32 => PointerValue::F32(0.0),
-                    PointerType::F64 => PointerValue::F64(0.0),
-                    PointerType::String => PointerValue::String(String::new()),
-                };
+                let pointer_type: u32 = args.nth_checked(2)?;
+                let (pointer_type, flags) = self.pointer_type(pointer_type)?;
+                let current = PointerValue::zeroed(pointer_type);
 
-                let module_name = self
-                    .memory
-                    .with_direct_access(|m| {
-                        Some(str::from_utf8(m.get(ptr..ptr + len)?).ok()?.to_owned())
-                    }).ok_or_else(|| {
-                        Trap::new(TrapKind::Host(Box::new(
-                            EnvironmentError::InvalidModuleName,
-                        )))
-                    })?;
+                let module_name = self.read_str(ptr, len, EnvironmentError::InvalidModuleName)?;
 
                 let id = self.pointer_paths.len();
                 self.pointer_paths.push(PointerPath {
+                    name: String::new(),
                     module_name,
                     offsets: Vec::new(),
+                    emulated: false,
+                    mono: None,
+                    big_endian: flags & abi::BIG_ENDIAN_FLAG != 0,
+                    pointer_size: pointer_size(flags),
                     old: current.clone(),
                     current,
+                    status: ReadStatus::NotRead,
                 });
 
                 Ok(Some(RuntimeValue::I32(id as i32)))
             }
             PUSH_OFFSET_FUNC_INDEX => {
+                self.ensure_configuring("push_offset")?;
                 let pointer_path_id: u32 = args.nth_checked(0)?;
-                let pointer_path_id = pointer_path_id as usize;
                 let offset: i64 = args.nth_checked(1)?;
-                let pointer_path =
-                    self.pointer_paths.get_mut(pointer_path_id).ok_or_else(|| {
-                        Trap::new(TrapKind::Host(Box::new(
-                            EnvironmentError::InvalidPointerPathId,
-                        )))
-                    })?;
+                let pointer_path = self.native_pointer_path_mut(pointer_path_id)?;
+                if pointer_path.offsets.len() >= MAX_OFFSETS {
+                    return Err(trap(EnvironmentError::TooManyOffsets));
+                }
                 pointer_path.offsets.push(offset);
                 Ok(None)
             }
-            GET_U8_FUNC_INDEX => get_val(args, &self.pointer_paths, |v| match v {
+            SET_POINTER_PATH_NAME_FUNC_INDEX => {
+                self.ensure_configuring("set_pointer_path_name")?;
+                let pointer_path_id: u32 = args.nth_checked(0)?;
+                let ptr: u32 = args.nth_checked(1)?;
+                let len: u32 = args.nth_checked(2)?;
+
+                let name = self.read_str(ptr, len, EnvironmentError::InvalidPointerPathName)?;
+
+                self.pointer_path_mut(pointer_path_id)?.name = name;
+                Ok(None)
+            }
+            SET_POINTER_PATH_OFFSETS_FUNC_INDEX => {
+                let pointer_path_id: u32 = args.nth_checked(0)?;
+                let ptr: u32 = args.nth_checked(1)?;
+                let len: u32 = args.nth_checked(2)?;
+
+                let offsets = self.read_offsets(ptr, len)?;
+
+                let pointer_path = self.pointer_path_mut(pointer_path_id)?;
+                if pointer_path.emulated && offsets.len() != 1 {
+                    return Err(trap(EnvironmentError::EmulatedPointerPath));
+                }
+                if pointer_path.mono.is_some() {
+                    return Err(trap(EnvironmentError::MonoPointerPath));
+                }
+                pointer_path.offsets = offsets;
+                Ok(None)
+            }
+            SET_POINTER_PATH_MODULE_FUNC_INDEX => {
+                let pointer_path_id: u32 = args.nth_checked(0)?;
+                let ptr: u32 = args.nth_checked(1)?;
+                let len: u32 = args.nth_checked(2)?;
+
+                let module_name = self.read_str(ptr, len, EnvironmentError::InvalidModuleName)?;
+
+                self.native_pointer_path_mut(pointer_path_id)?.module_name = module_name;
+                Ok(None)
+            }
+            READ_MEMORY_FUNC_INDEX => {
+                let module_ptr: u32 = args.nth_checked(0)?;
+                let module_len: u32 = args.nth_checked(1)?;
+                let offsets_ptr: u32 = args.nth_checked(2)?;
+                let offsets_len: u32 = args.nth_checked(3)?;
+                let buf_ptr: u32 = args.nth_checked(4)?;
+                let buf_len: u32 = args.nth_checked(5)?;
+
+                let module_name =
+                    self.read_str(module_ptr, module_len, EnvironmentError::InvalidModuleName)?;
+                let offsets = self.read_offsets(offsets_ptr, offsets_len)?;
+                if buf_len as usize > MAX_READ_LEN {
+                    return Err(trap(EnvironmentError::BufferTooLarge));
+                }
+
+                let mut buf = vec![0; buf_len as usize];
+                let success = if let Some(process) = &self.process {
+                    let result = process
+                        .follow_pointer_path(&module_name, &offsets, false, PointerSize::Process)
+                        .and_then(|address| process.read_buf(address, &mut buf));
+                    result.is_ok()
+                } else {
+                    false
+                };
+                if !success {
+                    // Partial reads shouldn't leak into the script.
+                    for b in &mut buf {
+                        *b = 0;
+                    }
+                }
+
+                self.memory
+                    .set(buf_ptr, &buf)
+                    .map_err(|_| trap(EnvironmentError::InvalidBuffer))?;
+
+                Ok(Some(RuntimeValue::I32(success as i32)))
+            }
+            ADD_SETTING_FUNC_INDEX => {
+                self.ensure_configuring("add_setting")?;
+                if self.settings.len() >= MAX_SETTINGS {
+                    return Err(trap(EnvironmentError::TooManySettings));
+                }
+                let ptr: u32 = args.nth_checked(0)?;
+                let len: u32 = args.nth_checked(1)?;
+                let default: bool = args.nth_checked(2)?;
+
+                let key = self.read_str(ptr, len, EnvironmentError::InvalidSettingKey)?;
+                if key.is_empty() || self.settings.iter().any(|s| s.key == key) {
+                    return Err(trap(EnvironmentError::InvalidSettingKey));
+                }
+
+                let id = self.settings.len();
+                self.settings.push(Setting {
+                    key,
+                    value: default,
+                    default,
+                });
+
+                Ok(Some(RuntimeValue::I32(id as i32)))
+            }
+            GET_SETTING_FUNC_INDEX => {
+                let setting_id: u32 = args.nth_checked(0)?;
+                let setting = self
+                    .settings
+                    .get(setting_id as usize)
+                    .ok_or_else(|| trap(EnvironmentError::InvalidSettingId))?;
+                Ok(Some(RuntimeValue::I32(setting.value as i32)))
+            }
+            SET_TICK_RATE_FUNC_INDEX => {
+                let tick_rate: F64 = args.nth_checked(0)?;
+                let tick_rate = tick_rate.to_float();
+                if !(tick_rate > 0.0 && tick_rate.is_finite()) {
+                    return Err(trap(EnvironmentError::InvalidTickRate));
+                }
+                self.tick_rate = Some(tick_rate);
+                Ok(None)
+            }
+            PRINT_MESSAGE_FUNC_INDEX => {
+                let ptr: u32 = args.nth_checked(0)?;
+                let len: u32 = args.nth_checked(1)?;
+
+                let message = self.read_str(ptr, len, EnvironmentError::InvalidMessage)?;
+                self.messages.push(message);
+                Ok(None)
+            }
+            SET_EMULATOR_FUNC_INDEX => {
+                self.ensure_configuring("set_emulator")?;
+                let ptr: u32 = args.nth_checked(0)?;
+                let len: u32 = args.nth_checked(1)?;
+
+                let name = self.read_str(ptr, len, EnvironmentError::UnknownEmulator)?;
+                self.emulator = Some(
+                    emulator::emulator(&name)
+                        .ok_or_else(|| trap(EnvironmentError::UnknownEmulator))?,
+                );
+                Ok(None)
+            }
+            PUSH_EMULATOR_POINTER_PATH_FUNC_INDEX => {
+                self.ensure_configuring("push_emulator_pointer_path")?;
+                if self.pointer_paths.len() >= MAX_POINTER_PATHS {
+                    return Err(trap(EnvironmentError::TooManyPointerPaths));
+                }
+                let ptr: u32 = args.nth_checked(0)?;
+                let len: u32 = args.nth_checked(1)?;
+                let address: i64 = args.nth_checked(2)?;
+                let pointer_type: u32 = args.nth_checked(3)?;
+                let (pointer_type, flags) = self.pointer_type(pointer_type)?;
+                let current = PointerValue::zeroed(pointer_type);
+
+                let emulator = self
+                    .emulator
+                    .ok_or_else(|| trap(EnvironmentError::NoEmulator))?;
+                let region = self.read_str(ptr, len, EnvironmentError::UnknownRegion)?;
+                if !emulator.has_region(&region) {
+                    return Err(trap(EnvironmentError::UnknownRegion));
+                }
+
+                let id = self.pointer_paths.len();
+                self.pointer_paths.push(PointerPath {
+                    name: String::new(),
+                    module_name: region,
+                    offsets: vec![address],
+                    emulated: true,
+                    mono: None,
+                    big_endian: flags & abi::BIG_ENDIAN_FLAG != 0 || emulator.big_endian,
+                    pointer_size: pointer_size(flags),
+                    old: current.clone(),
+                    current,
+                    status: ReadStatus::NotRead,
+                });
+
+                Ok(Some(RuntimeValue::I32(id as i32)))
+            }
+            SET_POINTER_PATH_SIZE_FUNC_INDEX => {
+                self.ensure_configuring("set_pointer_path_size")?;
+                let pointer_path_id: u32 = args.nth_checked(0)?;
+                let size: u32 = args.nth_checked(1)?;
+                if size as usize > MAX_READ_LEN {
+                    return Err(trap(EnvironmentError::BufferTooLarge));
+                }
+
+                let pointer_path = self.pointer_path_mut(pointer_path_id)?;
+                if pointer_path.ty() != PointerType::Bytes {
+                    return Err(trap(EnvironmentError::TypeMismatch));
+                }
+                pointer_path.current = PointerValue::Bytes(vec![0; size as usize]);
+                pointer_path.old = PointerValue::Bytes(vec![0; size as usize]);
+                Ok(None)
+            }
+            GET_BYTES_FUNC_INDEX => {
+                let pointer_path_id: u32 = args.nth_checked(0)?;
+                let current: bool = args.nth_checked(1)?;
+                let offset: u32 = args.nth_checked(2)?;
+                let buf_ptr: u32 = args.nth_checked(3)?;
+                let buf_len: u32 = args.nth_checked(4)?;
+
+                // The memory is written while the value is still borrowed, so
+                // this can only borrow the pointer paths.
+                let pointer_path = self
+                    .pointer_paths
+                    .get(pointer_path_id as usize)
+                    .ok_or_else(|| trap(EnvironmentError::InvalidPointerPathId))?;
+                let value = if current {
+                    &pointer_path.current
+                } else {
+                    &pointer_path.old
+                };
+                let bytes = value
+                    .as_bytes()
+                    .ok_or_else(|| trap(EnvironmentError::TypeMismatch))?;
+                let (offset, len) = (offset as usize, buf_len as usize);
+                let bytes = offset
+                    .checked_add(len)
+                    .and_then(|end| bytes.get(offset..end))
+                    .ok_or_else(|| trap(EnvironmentError::OutOfBounds))?;
+
+                self.memory
+                    .set(buf_ptr, bytes)
+                    .map_err(|_| trap(EnvironmentError::InvalidBuffer))?;
+                Ok(None)
+            }
+            GET_MODULE_ADDRESS_FUNC_INDEX => {
+                let ptr: u32 = args.nth_checked(0)?;
+                let len: u32 = args.nth_checked(1)?;
+
+                let module_name = self.read_str(ptr, len, EnvironmentError::InvalidModuleName)?;
+                let address = self
+                    .process
+                    .as_ref()
+                    .and_then(|p| p.module_address(&module_name).ok())
+                    .unwrap_or_default();
+                Ok(Some(RuntimeValue::I64(address as i64)))
+            }
+            GET_MODULE_SIZE_FUNC_INDEX => {
+                let ptr: u32 = args.nth_checked(0)?;
+                let len: u32 = args.nth_checked(1)?;
+
+                let module_name = self.read_str(ptr, len, EnvironmentError::InvalidModuleName)?;
+                let size = self
+                    .process
+                    .as_ref()
+                    .and_then(|p| p.module_size(&module_name).ok())
+                    .unwrap_or_default();
+                Ok(Some(RuntimeValue::I64(size as i64)))
+            }
+            GET_MODULE_EXPORT_FUNC_INDEX => {
+                let module_ptr: u32 = args.nth_checked(0)?;
+                let module_len: u32 = args.nth_checked(1)?;
+                let symbol_ptr: u32 = args.nth_checked(2)?;
+                let symbol_len: u32 = args.nth_checked(3)?;
+
+                let module_name =
+                    self.read_str(module_ptr, module_len, EnvironmentError::InvalidModuleName)?;
+                let symbol =
+                    self.read_str(symbol_ptr, symbol_len, EnvironmentError::InvalidSymbolName)?;
+                let address = self
+                    .process
+                    .as_ref()
+                    .and_then(|p| p.module_export(&module_name, &symbol).ok())
+                    .unwrap_or_default();
+                Ok(Some(RuntimeValue::I64(address as i64)))
+            }
+            GET_MEMORY_REGION_FUNC_INDEX => {
+                let address: i64 = args.nth_checked(0)?;
+                let region_ptr: u32 = args.nth_checked(1)?;
+
+                let region = self
+                    .process
+                    .as_ref()
+                    .and_then(|p| p.memory_region(address as u64).ok());
+                // The region is stored as its base and size as u64 followed
+                // by its protection as u32, all little endian.
+                let mut buf = Vec::with_capacity(20);
+                if let Some(region) = region {
+                    buf.extend((0..8).map(|i| (region.base >> (8 * i)) as u8));
+                    buf.extend((0..8).map(|i| (region.size >> (8 * i)) as u8));
+                    buf.extend((0..4).map(|i| (region.protection >> (8 * i)) as u8));
+                } else {
+                    buf.resize(20, 0);
+                }
+
+                self.memory
+                    .set(region_ptr, &buf)
+                    .map_err(|_| trap(EnvironmentError::InvalidBuffer))?;
+                Ok(Some(RuntimeValue::I32(region.is_some() as i32)))
+            }
+            PUSH_MONO_POINTER_PATH_FUNC_INDEX => {
+                self.ensure_configuring("push_mono_pointer_path")?;
+                if self.pointer_paths.len() >= MAX_POINTER_PATHS {
+                    return Err(trap(EnvironmentError::TooManyPointerPaths));
+                }
+                let assembly_ptr: u32 = args.nth_checked(0)?;
+                let assembly_len: u32 = args.nth_checked(1)?;
+                let path_ptr: u32 = args.nth_checked(2)?;
+                let path_len: u32 = args.nth_checked(3)?;
+                let pointer_type: u32 = args.nth_checked(4)?;
+                let (pointer_type, flags) = self.pointer_type(pointer_type)?;
+                let current = PointerValue::zeroed(pointer_type);
+
+                let assembly = self.read_str(
+                    assembly_ptr,
+                    assembly_len,
+                    EnvironmentError::InvalidModuleName,
+                )?;
+                let path = self.read_str(path_ptr, path_len, EnvironmentError::InvalidMonoPath)?;
+                let mono = MonoPath::parse(assembly.clone(), &path)
+                    .ok_or_else(|| trap(EnvironmentError::InvalidMonoPath))?;
+
+                let id = self.pointer_paths.len();
+                self.pointer_paths.push(PointerPath {
+                    name: String::new(),
+                    module_name: assembly,
+                    offsets: Vec::new(),
+                    emulated: false,
+                    mono: Some(mono),
+                    big_endian: flags & abi::BIG_ENDIAN_FLAG != 0,
+                    pointer_size: pointer_size(flags),
+                    old: current.clone(),
+                    current,
+                    status: ReadStatus::NotRead,
+                });
+
+                Ok(Some(RuntimeValue::I32(id as i32)))
+            }
+            GET_U8_FUNC_INDEX => self.get_val(args, PointerType::U8, |v| match v {
                 PointerValue::U8(v) => Some(RuntimeValue::I32(*v as i32)),
                 _ => None,
             }),
-            GET_U16_FUNC_INDEX => get_val(args, &self.pointer_paths, |v| match v {
+            GET_U16_FUNC_INDEX => self.get_val(args, PointerType::U16, |v| match v {
                 PointerValue::U16(v) => Some(RuntimeValue::I32(*v as i32)),
                 _ => None,
             }),
-            GET_U32_FUNC_INDEX => get_val(args, &self.pointer_paths, |v| match v {
+            GET_U32_FUNC_INDEX => self.get_val(args, PointerType::U32, |v| match v {
                 PointerValue::U32(v) => Some(RuntimeValue::I32(*v as i32)),
                 _ => None,
             }),
-            GET_U64_FUNC_INDEX => get_val(args, &self.pointer_paths, |v| match v {
+            GET_U64_FUNC_INDEX => self.get_val(args, PointerType::U64, |v| match v {
                 PointerValue::U64(v) => Some(RuntimeValue::I64(*v as i64)),
                 _ => None,
             }),
-            GET_I8_FUNC_INDEX => get_val(args, &self.pointer_paths, |v| match v {
+            GET_I8_FUNC_INDEX => self.get_val(args, PointerType::I8, |v| match v {
                 PointerValue::I8(v) => Some(RuntimeValue::I32(*v as i32)),
                 _ => None,
             }),
-            GET_I16_FUNC_INDEX => get_val(args, &self.pointer_paths, |v| match v {
+            GET_I16_FUNC_INDEX => self.get_val(args, PointerType::I16, |v| match v {
                 PointerValue::I16(v) => Some(RuntimeValue::I32(*v as i32)),
                 _ => None,
             }),
-            GET_I32_FUNC_INDEX => get_val(args, &self.pointer_paths, |v| match v {
+            GET_I32_FUNC_INDEX => self.get_val(args, PointerType::I32, |v| match v {
                 PointerValue::I32(v) => Some(RuntimeValue::I32(*v)),
                 _ => None,
             }),
-            GET_I64_FUNC_INDEX => get_val(args, &self.pointer_paths, |v| match v {
+            GET_I64_FUNC_INDEX => self.get_val(args, PointerType::I64, |v| match v {
                 PointerValue::I64(v) => Some(RuntimeValue::I64(*v)),
                 _ => None,
             }),
-            GET_F32_FUNC_INDEX => get_val(args, &self.pointer_paths, |v| match v {
+            GET_F32_FUNC_INDEX => self.get_val(args, PointerType::F32, |v| match v {
                 &PointerValue::F32(v) => Some(RuntimeValue::F32(v.into())),
                 _ => None,
             }),
-            GET_F64_FUNC_INDEX => get_val(args, &self.pointer_paths, |v| match v {
+            GET_F64_FUNC_INDEX => self.get_val(args, PointerType::F64, |v| match v {
                 &PointerValue::F64(v) => Some(RuntimeValue::F64(v.into())),
                 _ => None,
             }),
@@ -203,6 +857,61 @@ impl Externals for Environment {
     }
 }
 
+fn pointer_size(flags: u32) -> PointerSize {
+    if flags & abi::POINTER_SIZE_32_FLAG != 0 {
+        PointerSize::Bits32
+    } else if flags & abi::POINTER_SIZE_64_FLAG != 0 {
+        PointerSize::Bits64
+    } else {
+        PointerSize::Process
+    }
+}
+
+fn func_index(name: &str) -> Option<usize> {
+    Some(match name {
+        "set_process_name" => SET_PROCESS_NAME_FUNC_INDEX,
+        "push_pointer_path" => PUSH_POINTER_PATH_FUNC_INDEX,
+        "push_offset" => PUSH_OFFSET_FUNC_INDEX,
+        "get_u8" => GET_U8_FUNC_INDEX,
+        "get_u16" => GET_U16_FUNC_INDEX,
+        "get_u32" => GET_U32_FUNC_INDEX,
+        "get_u64" => GET_U64_FUNC_INDEX,
+        "get_i8" => GET_I8_FUNC_INDEX,
+        "get_i16" => GET_I16_FUNC_INDEX,
+        "get_i32" => GET_I32_FUNC_INDEX,
+        "get_i64" => GET_I64_FUNC_INDEX,
+        "get_f32" => GET_F32_FUNC_INDEX,
+        "get_f64" => GET_F64_FUNC_INDEX,
+        "set_pointer_path_name" => SET_POINTER_PATH_NAME_FUNC_INDEX,
+        "set_pointer_path_offsets" => SET_POINTER_PATH_OFFSETS_FUNC_INDEX,
+        "set_pointer_path_module" => SET_POINTER_PATH_MODULE_FUNC_INDEX,
+        "read_memory" => READ_MEMORY_FUNC_INDEX,
+        "add_setting" => ADD_SETTING_FUNC_INDEX,
+        "get_setting" => GET_SETTING_FUNC_INDEX,
+        "set_tick_rate" => SET_TICK_RATE_FUNC_INDEX,
+        "print_message" => PRINT_MESSAGE_FUNC_INDEX,
+        "set_emulator" => SET_EMULATOR_FUNC_INDEX,
+        "push_emulator_pointer_path" => PUSH_EMULATOR_POINTER_PATH_FUNC_INDEX,
+        "set_pointer_path_size" => SET_POINTER_PATH_SIZE_FUNC_INDEX,
+        "get_bytes" => GET_BYTES_FUNC_INDEX,
+        "get_module_address" => GET_MODULE_ADDRESS_FUNC_INDEX,
+        "get_module_size" => GET_MODULE_SIZE_FUNC_INDEX,
+        "get_module_export" => GET_MODULE_EXPORT_FUNC_INDEX,
+        "get_memory_region" => GET_MEMORY_REGION_FUNC_INDEX,
+        "push_mono_pointer_path" => PUSH_MONO_POINTER_PATH_FUNC_INDEX,
+        _ => return None,
+    })
+}
+
+fn value_type(ty: abi::ValueType) -> ValueType {
+    match ty {
+        abi::ValueType::I32 => ValueType::I32,
+        abi::ValueType::I64 => ValueType::I64,
+        abi::ValueType::F32 => ValueType::F32,
+        abi::ValueType::F64 => ValueType::F64,
+    }
+}
+
 pub struct Imports;
 
 impl ImportResolver for Imports {
@@ -210,72 +919,32 @@ impl ImportResolver for Imports {
         &self,
         _module_name: &str,
         field_name: &str,
-        _signature: &Signature,
+        signature: &Signature,
     ) -> Result<FuncRef, Error> {
-        let instance = match field_name {
-            "set_process_name" => FuncInstance::alloc_host(
-                Signature::new(&[ValueType::I32, ValueType::I32][..], None),
-                SET_PROCESS_NAME_FUNC_INDEX,
-            ),
-            "push_pointer_path" => FuncInstance::alloc_host(
-                Signature::new(
-                    &[ValueType::I32, ValueType::I32, ValueType::I32][..],
-                    Some(ValueType::I32),
-                ),
-                PUSH_POINTER_PATH_FUNC_INDEX,
-            ),
-            "push_offset" => FuncInstance::alloc_host(
-                Signature::new(&[ValueType::I32, ValueType::I64][..], None),
-                PUSH_OFFSET_FUNC_INDEX,
-            ),
-            "get_u8" => FuncInstance::alloc_host(
-                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
-                GET_U8_FUNC_INDEX,
-            ),
-            "get_u16" => FuncInstance::alloc_host(
-                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
-                GET_U16_FUNC_INDEX,
-            ),
-            "get_u32" => FuncInstance::alloc_host(
-                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
-                GET_U32_FUNC_INDEX,
-            ),
-            "get_u64" => FuncInstance::alloc_host(
-                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I64)),
-                GET_U64_FUNC_INDEX,
-            ),
-            "get_i8" => FuncInstance::alloc_host(
-                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
-                GET_I8_FUNC_INDEX,
-            ),
-            "get_i16" => FuncInstance::alloc_host(
-                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
-                GET_I16_FUNC_INDEX,
-            ),
-            "get_i32" => FuncInstance::alloc_host(
-                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
-                GET_I32_FUNC_INDEX,
-            ),
-            "get_i64" => FuncInstance::alloc_host(
-                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I64)),
-                GET_I64_FUNC_INDEX,
-            ),
-            "get_f32" => FuncInstance::alloc_host(
-                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::F32)),
-                GET_F32_FUNC_INDEX,
-            ),
-            "get_f64" => FuncInstance::alloc_host(
-                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::F64)),
-                GET_F64_FUNC_INDEX,
-            ),
-            _ => {
-                return Err(Error::Instantiation(format!(
-                    "Export {} not found",
+        let (import, func_index) = abi::import(field_name)
+            .and_then(|import| Some((import, func_index(import.name)?)))
+            .ok_or_else(|| {
+                Error::Instantiation(format!(
+                    "The environment doesn't provide the function {}",
                     field_name
-                )))
-            }
-        };
-        Ok(instance)
+                ))
+            })?;
+        let params = import
+            .params
+            .iter()
+            .map(|&ty| value_type(ty))
+            .collect::<Vec<_>>();
+        let result = import.result.map(value_type);
+        if signature.params() != &params[..] || signature.return_type() != result {
+            return Err(Error::Instantiation(format!(
+                "The function {} is imported with the wrong signature",
+                field_name
+            )));
+        }
+        Ok(FuncInstance::alloc_host(
+            Signature::new(params, result),
+            func_index,
+        ))
     }
 
     fn resolve_global(
@@ -303,31 +972,3 @@ impl ImportResolver for Imports {
         Err(Error::Instantiation("Table not found".to_string()))
     }
 }
-
-fn get_val(
-    args: RuntimeArgs,
-    pointer_paths: &[PointerPath],
-    convert: impl FnOnce(&PointerValue) -> Option<RuntimeValue>,
-) -> Result<Option<RuntimeValue>, Trap> {
-    let pointer_path_id: u32 = args.nth_checked(0)?;
-    let pointer_path_id = pointer_path_id as usize;
-    let current: bool = args.nth_checked(1)?;
-
-    let pointer_path = pointer_paths.get(pointer_path_id).ok_or_else(|| {
-        Trap::new(TrapKind::Host(Box::new(
-            EnvironmentError::InvalidPointerPathId,
-        )))
-    })?;
-    let value = if current {
-        &pointer_path.current
-    } else {
-        &pointer_path.old
-    };
-    if let Some(val) = convert(value) {
-        Ok(Some(val))
-    } else {
-        Err(Trap::new(TrapKind::Host(Box::new(
-            EnvironmentError::TypeMismatch,
-        ))))
-    }
-}
@@ -0,0 +1,323 @@
+use runtime::{ReloadReport, Runtime, TimerAction, TimerState};
+use std::error::Error;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Something that happened on the runtime's thread.
+#[derive(Debug)]
+pub enum Event {
+    Action(TimerAction),
+    Attached,
+    Detached,
+    /// The game started or stopped loading.
+    Loading(bool),
+    /// The game time in seconds. This is sent whenever it changes.
+    GameTime(f64),
+    Log(String),
+    Error(String),
+    Reloaded(ReloadReport),
+}
+
+enum Command {
+    SetState(TimerState),
+    SetSetting(String, bool),
+    Reload(Vec<u8>),
+    Shutdown,
+}
+
+/// Owns a runtime running on its own thread. The runtime ticks at the rate
+/// requested by the script and reports everything that happens as events.
+/// Dropping the handle shuts the thread down.
+pub struct RuntimeHandle {
+    commands: Sender<Command>,
+    events: Receiver<Event>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RuntimeHandle {
+    /// Starts a runtime for the script on a new thread. Fails if the script
+    /// can't be instantiated.
+    pub fn spawn(binary: Vec<u8>) -> Result<Self, Box<Error>> {
+        let (commands, command_receiver) = mpsc::channel();
+        let (event_sender, events) = mpsc::channel();
+        let (init_sender, init_receiver) = mpsc::channel();
+
+        // The runtime isn't Send, so it needs to be created on the thread.
+        let thread = thread::spawn(move || {
+            let mut runtime = match Runtime::new(&binary) {
+                Ok(runtime) => {
+                    let _ = init_sender.send(Ok(()));
+                    runtime
+                }
+                Err(e) => {
+                    let _ = init_sender.send(Err(e.to_string()));
+                    return;
+                }
+            };
+            drop(binary);
+            run(&mut runtime, &command_receiver, event_sender);
+        });
+
+        match init_receiver.recv() {
+            Ok(Ok(())) => Ok(Self {
+                commands,
+                events,
+                thread: Some(thread),
+            }),
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => Err("The runtime thread panicked".into()),
+        }
+    }
+
+    pub fn set_state(&self, state: TimerState) {
+        let _ = self.commands.send(Command::SetState(state));
+    }
+
+    pub fn set_setting(&self, key: &str, value: bool) {
+        let _ = self
+            .commands
+            .send(Command::SetSetting(key.to_owned(), value));
+    }
+
+    /// Replaces the script. The outcome is reported as either a `Reloaded` or
+    /// an `Error` event.
+    pub fn reload(&self, binary: Vec<u8>) {
+        let _ = self.commands.send(Command::Reload(binary));
+    }
+
+    /// Returns the next event without blocking.
+    pub fn poll(&self) -> Option<Event> {
+        self.events.try_recv().ok()
+    }
+
+    /// Blocks until the next event arrives or the runtime thread stopped.
+    pub fn wait(&self) -> Option<Event> {
+        self.events.recv().ok()
+    }
+}
+
+impl Drop for RuntimeHandle {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(runtime: &mut Runtime, commands: &Receiver<Command>, events: Sender<Event>) {
+    let log_events = events.clone();
+    runtime.set_log_callback(move |message| {
+        let _ = log_events.send(Event::Log(message.to_owned()));
+    });
+
+    let mut was_attached = false;
+    let mut was_loading = None;
+    let mut was_game_time = None;
+    loop {
+        let tick_start = Instant::now();
+
+        loop {
+            match commands.try_recv() {
+                Ok(command) => {
+                    if !handle_command(runtime, command, &events) {
+                        return;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+
+        match runtime.step() {
+            Ok(Some(action)) => {
+                let _ = events.send(Event::Action(action));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                let _ = events.send(Event::Error(e.to_string()));
+            }
+        }
+
+        let is_attached = runtime.is_attached();
+        if is_attached != was_attached {
+            let event = if is_attached {
+                Event::Attached
+            } else {
+                Event::Detached
+            };
+            let _ = events.send(event);
+            was_attached = is_attached;
+        }
+
+        match runtime.is_loading() {
+            Ok(Some(is_loading)) => {
+                if was_loading != Some(is_loading) {
+                    let _ = events.send(Event::Loading(is_loading));
+                    was_loading = Some(is_loading);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                let _ = events.send(Event::Error(e.to_string()));
+            }
+        }
+
+        match runtime.game_time() {
+            Ok(Some(game_time)) => {
+                if was_game_time != Some(game_time) {
+                    let _ = events.send(Event::GameTime(game_time));
+                    was_game_time = Some(game_time);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                let _ = events.send(Event::Error(e.to_string()));
+            }
+        }
+
+        // Waiting for commands instead of sleeping keeps the thread
+        // responsive, so shutting down doesn't have to wait for a tick.
+        let tick_duration = Duration::from_nanos((1e9 / runtime.tick_rate()) as u64);
+        while let Some(remaining) = tick_duration.checked_sub(tick_start.elapsed()) {
+            match commands.recv_timeout(remaining) {
+                Ok(command) => {
+                    if !handle_command(runtime, command, &events) {
+                        return;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+}
+
+/// Returns whether the thread should keep running.
+fn handle_command(runtime: &mut Runtime, command: Command, events: &Sender<Event>) -> bool {
+    match command {
+        Command::SetState(state) => runtime.set_state(state),
+        Command::SetSetting(key, value) => {
+            runtime.set_setting(&key, value);
+        }
+        Command::Reload(binary) => {
+            let event = match runtime.reload(&binary) {
+                Ok(report) => Event::Reloaded(report),
+                Err(e) => Event::Error(e.to_string()),
+            };
+            let _ = events.send(event);
+        }
+        Command::Shutdown => return false,
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asl_abi::{VERSION_SECTION, VERSION_SECTION_PAYLOAD};
+    use parity_wasm::builder;
+    use parity_wasm::elements::Instruction::*;
+    use parity_wasm::elements::{CustomSection, Instructions, Section, Serialize, ValueType};
+    use std::env;
+
+    /// A script that attaches to the test binary and asks for a reset on
+    /// every tick.
+    fn resetting_script() -> Vec<u8> {
+        let exe = env::current_exe().unwrap();
+        let process_name = exe.file_name().unwrap().to_str().unwrap().as_bytes().to_vec();
+        let len = process_name.len() as i32;
+
+        let mut builder = builder::module();
+        let set_process_name = builder.push_signature(
+            builder::signature()
+                .with_params(vec![ValueType::I32, ValueType::I32])
+                .build_sig(),
+        );
+        let mut builder = builder
+            .import()
+            .path("env", "set_process_name")
+            .external()
+            .func(set_process_name)
+            .build()
+            .memory()
+            .with_min(1)
+            .build()
+            .export()
+            .field("memory")
+            .internal()
+            .memory(0)
+            .build()
+            .data()
+            .offset(I32Const(0))
+            .value(process_name)
+            .build();
+        let configure = builder.push_function(
+            builder::function()
+                .signature()
+                .build()
+                .body()
+                .with_instructions(Instructions::new(vec![
+                    I32Const(0),
+                    I32Const(len),
+                    Call(0),
+                    End,
+                ])).build()
+                .build(),
+        );
+        let should_reset = builder.push_function(
+            builder::function()
+                .signature()
+                .with_return_type(Some(ValueType::I32))
+                .build()
+                .body()
+                .with_instructions(Instructions::new(vec![I32Const(1), End]))
+                .build()
+                .build(),
+        );
+        // The imported function comes first in the function index space.
+        let mut module = builder
+            .export()
+            .field("configure")
+            .internal()
+            .func(configure.body + 1)
+            .build()
+            .export()
+            .field("should_reset")
+            .internal()
+            .func(should_reset.body + 1)
+            .build()
+            .build();
+
+        let mut section = CustomSection::default();
+        *section.name_mut() = VERSION_SECTION.to_owned();
+        *section.payload_mut() = VERSION_SECTION_PAYLOAD.to_vec();
+        module.sections_mut().push(Section::Custom(section));
+
+        let mut binary = Vec::new();
+        module.serialize(&mut binary).unwrap();
+        binary
+    }
+
+    fn wait_for_resets(handle: &RuntimeHandle, count: usize) {
+        let mut resets = 0;
+        while resets < count {
+            match handle.wait() {
+                Some(Event::Action(TimerAction::Reset)) => resets += 1,
+                Some(Event::Error(e)) => panic!("The runtime failed: {}", e),
+                Some(_) => {}
+                None => panic!("The runtime thread stopped"),
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_ticking_while_paused_or_finished() {
+        let handle = RuntimeHandle::spawn(resetting_script()).unwrap();
+        handle.set_state(TimerState::Paused);
+        wait_for_resets(&handle, 3);
+        handle.set_state(TimerState::Finished);
+        wait_for_resets(&handle, 3);
+    }
+}
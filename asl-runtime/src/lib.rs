@@ -1,14 +1,30 @@
+extern crate asl_abi;
 extern crate wasmi;
 #[macro_use]
 extern crate num_derive;
 extern crate num_traits;
 #[macro_use]
+extern crate lazy_static;
+#[macro_use]
 extern crate quick_error;
 extern crate winapi;
 
+#[cfg(test)]
+extern crate parity_wasm;
+
+mod emulator;
 mod environment;
+mod handle;
+mod mono;
+mod pe;
 mod pointer;
 mod process;
 mod runtime;
+mod stats;
 
-pub use runtime::{Runtime, TimerAction, TimerState};
+pub use asl_abi::{validate, Issue, Report};
+pub use environment::{PointerPath, Setting};
+pub use handle::{Event, RuntimeHandle};
+pub use pointer::{PointerSize, PointerType, PointerValue, ReadStatus};
+pub use runtime::{AttachPolicy, ReloadReport, Runtime, TimerAction, TimerState};
+pub use stats::{ExportStats, Stats, TickStats};
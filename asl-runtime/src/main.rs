@@ -2,18 +2,26 @@ extern crate asl_runtime;
 
 use asl_runtime::Runtime;
 use std::error::Error;
-use std::time::Duration;
-use std::{fs, thread};
+use std::time::{Duration, Instant};
+use std::{env, fs, thread};
 
 fn main() -> Result<(), Box<Error>> {
+    let print_stats = env::args().skip(1).any(|arg| arg == "--stats");
+
     let buffer = fs::read("asl-language/out.wasm")?;
     // let buffer = fs::read("asl-rust-example/target/wasm32-unknown-unknown/release/asl.wasm")?;
     let mut runtime = Runtime::new(&buffer)?;
+    let mut last_stats = Instant::now();
     loop {
         thread::sleep(Duration::from_millis(16));
         if let Some(action) = runtime.step()? {
             eprintln!("{:?}", action);
         }
+        if print_stats && last_stats.elapsed() >= Duration::from_secs(5) {
+            eprint!("{}", runtime.stats());
+            runtime.reset_stats();
+            last_stats = Instant::now();
+        }
     }
 
     // Ok(())
@@ -0,0 +1,552 @@
+use process::{self, Address, MemoryReader, Offset};
+use std::collections::HashMap;
+use std::result;
+
+/// The module of the Mono runtime Unity ships since 2017.
+const MONO_MODULE: &str = "mono-2.0-bdwgc.dll";
+/// The module games built with IL2CPP ship instead of a Mono runtime.
+const IL2CPP_MODULE: &str = "GameAssembly.dll";
+
+/// Where the fields of the Mono runtime's structures are in 64-bit processes.
+const ASSEMBLY_NAME: Address = 0x10;
+const ASSEMBLY_IMAGE: Address = 0x60;
+const IMAGE_CLASS_CACHE: Address = 0x4C0;
+const HASH_TABLE_SIZE: Address = 0x18;
+const HASH_TABLE_TABLE: Address = 0x20;
+const CLASS_PARENT: Address = 0x30;
+const CLASS_NAME: Address = 0x48;
+const CLASS_VTABLE_SIZE: Address = 0x5C;
+const CLASS_FIELDS: Address = 0x98;
+const CLASS_RUNTIME_INFO: Address = 0xD0;
+const CLASS_FIELD_COUNT: Address = 0x100;
+const CLASS_NEXT_CLASS_CACHE: Address = 0x108;
+const FIELD_NAME: Address = 0x8;
+const FIELD_OFFSET: Address = 0x18;
+const FIELD_SIZE: Address = 0x20;
+const RUNTIME_INFO_DOMAIN_VTABLES: Address = 0x8;
+const VTABLE_VTABLE: Address = 0x40;
+
+const MAX_NAME_LEN: usize = 256;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum Error {
+        NoMono {
+            description("The process doesn't run Mono")
+        }
+        UnsupportedProcess {
+            description("Only 64-bit Mono processes are supported")
+        }
+        Il2Cpp {
+            description("Games built with IL2CPP aren't supported")
+        }
+        AssemblyNotFound(name: String) {
+            description("The assembly couldn't be found")
+            display("The assembly '{}' couldn't be found", name)
+        }
+        ClassNotFound(name: String) {
+            description("The class couldn't be found")
+            display("The class '{}' couldn't be found", name)
+        }
+        FieldNotFound(name: String) {
+            description("The field couldn't be found")
+            display("The field '{}' couldn't be found", name)
+        }
+        NotInitialized {
+            description("The object the field belongs to doesn't exist yet")
+        }
+        Process(err: process::Error) {
+            from()
+        }
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    /// Whether the process runs a Unity runtime that can't be walked at all,
+    /// as opposed to one that just hasn't loaded everything yet.
+    pub fn is_unsupported(&self) -> bool {
+        match self {
+            Error::UnsupportedProcess | Error::Il2Cpp => true,
+            _ => false,
+        }
+    }
+}
+
+/// A pointer path starting at a static field of a class, followed by the
+/// instance fields of the objects along the way, like
+/// `GameManager.instance.level`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MonoPath {
+    pub assembly: String,
+    pub class: String,
+    pub fields: Vec<String>,
+}
+
+impl MonoPath {
+    /// Parses paths like `GameManager.instance.level`. There needs to be at
+    /// least the class and its static field.
+    pub fn parse(assembly: String, path: &str) -> Option<Self> {
+        let mut names = path.split('.').map(str::to_owned);
+        let class = names.next()?;
+        let fields = names.collect::<Vec<_>>();
+        if fields.is_empty() || class.is_empty() || fields.iter().any(String::is_empty) {
+            return None;
+        }
+        Some(Self {
+            assembly,
+            class,
+            fields,
+        })
+    }
+}
+
+/// Resolves Mono pointer paths by walking the Mono runtime's structures. The
+/// structures that were found are cached for as long as the process is
+/// attached.
+#[derive(Debug, Default)]
+pub struct Mono {
+    assemblies: Option<Address>,
+    images: HashMap<String, Address>,
+    classes: HashMap<(Address, String), Address>,
+    paths: HashMap<MonoPath, Vec<Offset>>,
+}
+
+impl Mono {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Turns the Mono path into offsets that can be followed starting at
+    /// address 0. The first offset is the address of the static field and the
+    /// others are the offsets of the instance fields.
+    pub fn resolve<R: MemoryReader>(&mut self, process: &R, path: &MonoPath) -> Result<&[Offset]> {
+        if !self.paths.contains_key(path) {
+            let offsets = self.resolve_uncached(process, path)?;
+            self.paths.insert(path.clone(), offsets);
+        }
+        Ok(&self.paths[path])
+    }
+
+    fn resolve_uncached<R: MemoryReader>(
+        &mut self,
+        process: &R,
+        path: &MonoPath,
+    ) -> Result<Vec<Offset>> {
+        let image = self.image(process, &path.assembly)?;
+        let class = self.class(process, image, &path.class)?;
+
+        let (static_field, fields) = path.fields.split_first().unwrap();
+        let static_data = static_data(process, class)?;
+        let mut address = static_data + field_offset(process, class, static_field)? as Address;
+        let mut offsets = vec![address as Offset];
+
+        // The class of an instance field's value is only known once there is
+        // an object, as the field may hold a subclass.
+        for field in fields {
+            let object = process.read_pointer(address)?;
+            if object == 0 {
+                return Err(Error::NotInitialized);
+            }
+            let class = process.read_pointer(process.read_pointer(object)?)?;
+            let offset = field_offset(process, class, field)?;
+            offsets.push(offset as Offset);
+            address = object + offset as Address;
+        }
+
+        Ok(offsets)
+    }
+
+    fn image<R: MemoryReader>(&mut self, process: &R, name: &str) -> Result<Address> {
+        if let Some(&image) = self.images.get(name) {
+            return Ok(image);
+        }
+
+        let mut node = process.read_pointer(self.assemblies(process)?)?;
+        while node != 0 {
+            let assembly = process.read_pointer(node)?;
+            let assembly_name =
+                read_name(process, process.read_pointer(assembly + ASSEMBLY_NAME)?)?;
+            if assembly_name == name {
+                let image = process.read_pointer(assembly + ASSEMBLY_IMAGE)?;
+                self.images.insert(name.to_owned(), image);
+                return Ok(image);
+            }
+            node = process.read_pointer(node + 8)?;
+        }
+
+        Err(Error::AssemblyNotFound(name.to_owned()))
+    }
+
+    fn class<R: MemoryReader>(
+        &mut self,
+        process: &R,
+        image: Address,
+        name: &str,
+    ) -> Result<Address> {
+        let key = (image, name.to_owned());
+        if let Some(&class) = self.classes.get(&key) {
+            return Ok(class);
+        }
+
+        let class_cache = image + IMAGE_CLASS_CACHE;
+        let size = process.read_u32(class_cache + HASH_TABLE_SIZE)? as Address;
+        let table = process.read_pointer(class_cache + HASH_TABLE_TABLE)?;
+        for bucket in 0..size {
+            let mut class = process.read_pointer(table + 8 * bucket)?;
+            while class != 0 {
+                if read_name(process, process.read_pointer(class + CLASS_NAME)?)? == name {
+                    self.classes.insert(key, class);
+                    return Ok(class);
+                }
+                class = process.read_pointer(class + CLASS_NEXT_CLASS_CACHE)?;
+            }
+        }
+
+        Err(Error::ClassNotFound(name.to_owned()))
+    }
+
+    /// Finds the list of loaded assemblies through the code of
+    /// `mono_assembly_foreach`, which starts by loading it.
+    fn assemblies<R: MemoryReader>(&mut self, process: &R) -> Result<Address> {
+        if let Some(assemblies) = self.assemblies {
+            return Ok(assemblies);
+        }
+        if !process.is_64bit() {
+            return Err(Error::UnsupportedProcess);
+        }
+
+        let function = process
+            .module_export(MONO_MODULE, "mono_assembly_foreach")
+            .map_err(|_| {
+                if process
+                    .module_export(IL2CPP_MODULE, "il2cpp_domain_get")
+                    .is_ok()
+                {
+                    Error::Il2Cpp
+                } else {
+                    Error::NoMono
+                }
+            })?;
+        let mut code = [0; 0x100];
+        process.read_buf(function, &mut code)?;
+        // mov rcx, [rip + offset]
+        let index = code
+            .windows(7)
+            .position(|w| w[..3] == [0x48, 0x8B, 0x0D])
+            .ok_or(Error::NoMono)?;
+        let offset = code[index + 3..index + 7]
+            .iter()
+            .rev()
+            .fold(0, |v, &b| v << 8 | b as u32) as i32;
+        let assemblies = (function + index as Address + 7).wrapping_add(offset as Address);
+
+        self.assemblies = Some(assemblies);
+        Ok(assemblies)
+    }
+}
+
+/// The memory the static fields of the class are stored in.
+fn static_data<R: MemoryReader>(process: &R, class: Address) -> Result<Address> {
+    let runtime_info = process.read_pointer(class + CLASS_RUNTIME_INFO)?;
+    if runtime_info == 0 {
+        return Err(Error::NotInitialized);
+    }
+    let vtable = process.read_pointer(runtime_info + RUNTIME_INFO_DOMAIN_VTABLES)?;
+    if vtable == 0 {
+        return Err(Error::NotInitialized);
+    }
+    let vtable_size = process.read_u32(class + CLASS_VTABLE_SIZE)? as Address;
+    let static_data = process.read_pointer(vtable + VTABLE_VTABLE + 8 * vtable_size)?;
+    if static_data == 0 {
+        return Err(Error::NotInitialized);
+    }
+    Ok(static_data)
+}
+
+/// Looks up the offset of the field in the class or any of its parents.
+fn field_offset<R: MemoryReader>(process: &R, class: Address, name: &str) -> Result<u32> {
+    let mut class = class;
+    while class != 0 {
+        let fields = process.read_pointer(class + CLASS_FIELDS)?;
+        let field_count = process.read_u32(class + CLASS_FIELD_COUNT)? as Address;
+        for index in 0..field_count {
+            let field = fields + FIELD_SIZE * index;
+            if read_name(process, process.read_pointer(field + FIELD_NAME)?)? == name {
+                return Ok(process.read_u32(field + FIELD_OFFSET)?);
+            }
+        }
+        class = process.read_pointer(class + CLASS_PARENT)?;
+    }
+    Err(Error::FieldNotFound(name.to_owned()))
+}
+
+/// Reads a nul terminated name byte by byte, as it may be right in front of
+/// memory that can't be read.
+fn read_name<R: MemoryReader>(process: &R, address: Address) -> Result<String> {
+    let mut name = Vec::new();
+    for i in 0..MAX_NAME_LEN as Address {
+        let mut byte = [0];
+        process.read_buf(address + i, &mut byte)?;
+        if byte[0] == 0 {
+            break;
+        }
+        name.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&name).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: Address = 0x10000;
+    const FOREACH: Address = BASE;
+
+    /// A process with a single chunk of memory starting at `BASE`.
+    struct FakeProcess {
+        memory: Vec<u8>,
+        is_64bit: bool,
+        runtime: &'static str,
+    }
+
+    impl FakeProcess {
+        fn write(&mut self, address: Address, bytes: &[u8]) {
+            let start = (address - BASE) as usize;
+            self.memory[start..start + bytes.len()].copy_from_slice(bytes);
+        }
+
+        fn write_u32(&mut self, address: Address, value: u32) {
+            let bytes = (0..4).map(|i| (value >> (8 * i)) as u8).collect::<Vec<_>>();
+            self.write(address, &bytes);
+        }
+
+        fn write_pointer(&mut self, address: Address, value: Address) {
+            let bytes = (0..8).map(|i| (value >> (8 * i)) as u8).collect::<Vec<_>>();
+            self.write(address, &bytes);
+        }
+
+        fn write_class(&mut self, class: Address, name: Address, fields: &[(Address, u32)]) {
+            self.write_pointer(class + CLASS_NAME, name);
+            let field_list = class + 0x200;
+            self.write_pointer(class + CLASS_FIELDS, field_list);
+            self.write_u32(class + CLASS_FIELD_COUNT, fields.len() as u32);
+            for (i, &(name, offset)) in fields.iter().enumerate() {
+                let field = field_list + FIELD_SIZE * i as Address;
+                self.write_pointer(field + FIELD_NAME, name);
+                self.write_u32(field + FIELD_OFFSET, offset);
+            }
+        }
+    }
+
+    impl MemoryReader for FakeProcess {
+        fn is_64bit(&self) -> bool {
+            self.is_64bit
+        }
+
+        fn module_address(&self, _: &str) -> process::Result<Address> {
+            Err(process::Error::ModuleDoesntExist)
+        }
+
+        fn module_size(&self, _: &str) -> process::Result<u64> {
+            Err(process::Error::ModuleDoesntExist)
+        }
+
+        fn module_export(&self, module: &str, symbol: &str) -> process::Result<Address> {
+            match (module, symbol) {
+                (MONO_MODULE, "mono_assembly_foreach") | (IL2CPP_MODULE, "il2cpp_domain_get")
+                    if module == self.runtime =>
+                {
+                    Ok(FOREACH)
+                }
+                _ => Err(process::Error::ExportDoesntExist),
+            }
+        }
+
+        fn read_buf(&self, address: Address, buf: &mut [u8]) -> process::Result<()> {
+            let start = address
+                .checked_sub(BASE)
+                .ok_or(process::Error::ReadMemory)? as usize;
+            let bytes = self
+                .memory
+                .get(start..start + buf.len())
+                .ok_or(process::Error::ReadMemory)?;
+            buf.copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    /// Lays out an `Assembly-CSharp` assembly with a `GameManager` class whose
+    /// static `instance` field holds a `Player` deriving from `Entity`.
+    fn game() -> FakeProcess {
+        let mut process = FakeProcess {
+            memory: vec![0; 0x10000],
+            is_64bit: true,
+            runtime: MONO_MODULE,
+        };
+
+        // mono_assembly_foreach: sub rsp, 0x28; mov rcx, [rip + 0x1000]
+        process.write(FOREACH, &[0x48, 0x83, 0xEC, 0x28, 0x48, 0x8B, 0x0D]);
+        process.write_u32(FOREACH + 7, 0x1000);
+        let assemblies = FOREACH + 11 + 0x1000;
+
+        let strings = BASE + 0x2000;
+        let names: &[&[u8]] = &[
+            b"mscorlib\0",
+            b"Assembly-CSharp\0",
+            b"GameManager\0",
+            b"instance\0",
+            b"level\0",
+            b"hp\0",
+            b"Player\0",
+            b"Entity\0",
+        ];
+        let mut name_addresses = Vec::new();
+        let mut address = strings;
+        for name in names {
+            process.write(address, name);
+            name_addresses.push(address);
+            address += 0x20;
+        }
+
+        // Two assemblies, the one we look for is the second one.
+        let (node_a, node_b) = (BASE + 0x3000, BASE + 0x3010);
+        let (assembly_a, assembly_b) = (BASE + 0x3100, BASE + 0x3200);
+        let image = BASE + 0x4000;
+        process.write_pointer(assemblies, node_a);
+        process.write_pointer(node_a, assembly_a);
+        process.write_pointer(node_a + 8, node_b);
+        process.write_pointer(node_b, assembly_b);
+        process.write_pointer(assembly_a + ASSEMBLY_NAME, name_addresses[0]);
+        process.write_pointer(assembly_b + ASSEMBLY_NAME, name_addresses[1]);
+        process.write_pointer(assembly_b + ASSEMBLY_IMAGE, image);
+
+        let table = BASE + 0x4800;
+        let (game_manager, player, entity) = (BASE + 0x5000, BASE + 0x6000, BASE + 0x7000);
+        process.write_u32(image + IMAGE_CLASS_CACHE + HASH_TABLE_SIZE, 2);
+        process.write_pointer(image + IMAGE_CLASS_CACHE + HASH_TABLE_TABLE, table);
+        process.write_pointer(table + 8, game_manager);
+
+        process.write_class(
+            game_manager,
+            name_addresses[2],
+            &[(name_addresses[3], 0x10)],
+        );
+        process.write_class(player, name_addresses[6], &[(name_addresses[4], 0x24)]);
+        process.write_class(entity, name_addresses[7], &[(name_addresses[5], 0x18)]);
+        process.write_pointer(player + CLASS_PARENT, entity);
+
+        // The static data of GameManager comes after its 2 virtual methods.
+        let (runtime_info, vtable, static_data) = (BASE + 0x8000, BASE + 0x8100, BASE + 0x8200);
+        process.write_pointer(game_manager + CLASS_RUNTIME_INFO, runtime_info);
+        process.write_pointer(runtime_info + RUNTIME_INFO_DOMAIN_VTABLES, vtable);
+        process.write_u32(game_manager + CLASS_VTABLE_SIZE, 2);
+        process.write_pointer(vtable + VTABLE_VTABLE + 16, static_data);
+
+        let (object, object_vtable) = (BASE + 0x9000, BASE + 0x9100);
+        process.write_pointer(static_data + 0x10, object);
+        process.write_pointer(object, object_vtable);
+        process.write_pointer(object_vtable, player);
+
+        process
+    }
+
+    fn path(path: &str) -> MonoPath {
+        MonoPath::parse("Assembly-CSharp".to_owned(), path).unwrap()
+    }
+
+    #[test]
+    fn resolves_static_and_instance_fields() {
+        let process = game();
+        let mut mono = Mono::default();
+
+        let offsets = mono
+            .resolve(&process, &path("GameManager.instance.level"))
+            .unwrap()
+            .to_vec();
+        assert_eq!(offsets, [(BASE + 0x8210) as Offset, 0x24]);
+
+        let offsets = mono
+            .resolve(&process, &path("GameManager.instance.hp"))
+            .unwrap()
+            .to_vec();
+        assert_eq!(offsets, [(BASE + 0x8210) as Offset, 0x18]);
+
+        let offsets = mono
+            .resolve(&process, &path("GameManager.instance"))
+            .unwrap()
+            .to_vec();
+        assert_eq!(offsets, [(BASE + 0x8210) as Offset]);
+    }
+
+    #[test]
+    fn reports_missing_names() {
+        let process = game();
+        let mut mono = Mono::default();
+
+        match mono.resolve(&process, &path("Missing.instance")) {
+            Err(Error::ClassNotFound(name)) => assert_eq!(name, "Missing"),
+            other => panic!("{:?}", other),
+        }
+        match mono.resolve(&process, &path("GameManager.instance.missing")) {
+            Err(Error::FieldNotFound(name)) => assert_eq!(name, "missing"),
+            other => panic!("{:?}", other),
+        }
+        let other_assembly = MonoPath::parse("Missing".to_owned(), "GameManager.instance");
+        match mono.resolve(&process, &other_assembly.unwrap()) {
+            Err(Error::AssemblyNotFound(name)) => assert_eq!(name, "Missing"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn waits_for_objects() {
+        let mut process = game();
+        let mut mono = Mono::default();
+        process.write_pointer(BASE + 0x8210, 0);
+
+        match mono.resolve(&process, &path("GameManager.instance.level")) {
+            Err(Error::NotInitialized) => {}
+            other => panic!("{:?}", other),
+        }
+
+        process.write_pointer(BASE + 0x8210, BASE + 0x9000);
+        assert!(mono
+            .resolve(&process, &path("GameManager.instance.level"))
+            .is_ok());
+    }
+
+    #[test]
+    fn rejects_unsupported_runtimes() {
+        let mut process = game();
+        process.is_64bit = false;
+        match Mono::default().resolve(&process, &path("GameManager.instance")) {
+            Err(Error::UnsupportedProcess) => {}
+            other => panic!("{:?}", other),
+        }
+
+        let mut process = game();
+        process.runtime = IL2CPP_MODULE;
+        match Mono::default().resolve(&process, &path("GameManager.instance")) {
+            Err(Error::Il2Cpp) => {}
+            other => panic!("{:?}", other),
+        }
+
+        process.runtime = "";
+        match Mono::default().resolve(&process, &path("GameManager.instance")) {
+            Err(Error::NoMono) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_paths() {
+        assert_eq!(
+            path("GameManager.instance.level").fields,
+            ["instance", "level"]
+        );
+        assert!(MonoPath::parse(String::new(), "GameManager").is_none());
+        assert!(MonoPath::parse(String::new(), "GameManager..level").is_none());
+    }
+}
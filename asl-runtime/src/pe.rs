@@ -0,0 +1,238 @@
+use process::{Address, Error, MemoryReader, Result};
+use std::cmp::Ordering;
+
+const DOS_MAGIC: u16 = 0x5A4D;
+const PE_SIGNATURE: u32 = 0x4550;
+const PE32_MAGIC: u16 = 0x10B;
+const PE32_PLUS_MAGIC: u16 = 0x20B;
+
+/// Looks up the address of an exported symbol of the PE module loaded at the
+/// base address by reading its export table out of the process's memory.
+/// Symbols that are forwarded to other modules aren't resolved.
+pub fn export_address<R: MemoryReader>(
+    process: &R,
+    base: Address,
+    symbol: &str,
+) -> Result<Address> {
+    if process.read_u16(base)? != DOS_MAGIC {
+        return Err(Error::InvalidModule);
+    }
+    let nt_headers = base + process.read_u32(base + 0x3C)? as Address;
+    if process.read_u32(nt_headers)? != PE_SIGNATURE {
+        return Err(Error::InvalidModule);
+    }
+
+    let optional_header = nt_headers + 0x18;
+    let data_directories = match process.read_u16(optional_header)? {
+        PE32_MAGIC => optional_header + 0x60,
+        PE32_PLUS_MAGIC => optional_header + 0x70,
+        _ => return Err(Error::InvalidModule),
+    };
+    // The export table is the first data directory.
+    let export_rva = process.read_u32(data_directories)?;
+    let export_size = process.read_u32(data_directories + 4)?;
+    if export_rva == 0 {
+        return Err(Error::ExportDoesntExist);
+    }
+
+    let export_directory = base + export_rva as Address;
+    let names_len = process.read_u32(export_directory + 0x18)? as Address;
+    let functions = base + process.read_u32(export_directory + 0x1C)? as Address;
+    let names = base + process.read_u32(export_directory + 0x20)? as Address;
+    let ordinals = base + process.read_u32(export_directory + 0x24)? as Address;
+
+    // The names are sorted, so they can be binary searched.
+    let (mut low, mut high) = (0, names_len);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let name = base + process.read_u32(names + 4 * mid)? as Address;
+        match compare_name(process, name, symbol)? {
+            Ordering::Less => low = mid + 1,
+            Ordering::Greater => high = mid,
+            Ordering::Equal => {
+                let ordinal = process.read_u16(ordinals + 2 * mid)? as Address;
+                let rva = process.read_u32(functions + 4 * ordinal)?;
+                if rva >= export_rva && rva - export_rva < export_size {
+                    // The export is forwarded to a symbol of another module.
+                    return Err(Error::ExportDoesntExist);
+                }
+                return Ok(base + rva as Address);
+            }
+        }
+    }
+
+    Err(Error::ExportDoesntExist)
+}
+
+/// Compares the nul terminated name at the address to the symbol.
+fn compare_name<R: MemoryReader>(process: &R, address: Address, symbol: &str) -> Result<Ordering> {
+    // Reading one more byte than the symbol is long is enough to know whether
+    // the name is longer.
+    let mut buf = vec![0; symbol.len() + 1];
+    process.read_buf(address, &mut buf)?;
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Ok(buf[..len].cmp(symbol.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: Address = 0x10000;
+    const EXPORT_RVA: u32 = 0x200;
+    const EXPORT_SIZE: u32 = 0x400;
+
+    /// A module loaded at `BASE` that exports the symbols with the RVAs. The
+    /// symbols need to be sorted, like the linker sorts them.
+    struct FakeModule {
+        memory: Vec<u8>,
+    }
+
+    impl FakeModule {
+        fn new(magic: u16, exports: &[(&str, u32)]) -> Self {
+            let mut module = FakeModule {
+                memory: vec![0; 0x1000],
+            };
+            module.write_u16(0, DOS_MAGIC);
+            module.write_u32(0x3C, 0x80);
+            module.write_u32(0x80, PE_SIGNATURE);
+            module.write_u16(0x98, magic);
+            let data_directories = if magic == PE32_MAGIC { 0x98 + 0x60 } else { 0x98 + 0x70 };
+            module.write_u32(data_directories, EXPORT_RVA);
+            module.write_u32(data_directories + 4, EXPORT_SIZE);
+
+            let len = exports.len() as u32;
+            let functions = EXPORT_RVA + 0x28;
+            let names = functions + 4 * len;
+            let ordinals = names + 4 * len;
+            let mut strings = ordinals + 2 * len;
+            module.write_u32(EXPORT_RVA + 0x18, len);
+            module.write_u32(EXPORT_RVA + 0x1C, functions);
+            module.write_u32(EXPORT_RVA + 0x20, names);
+            module.write_u32(EXPORT_RVA + 0x24, ordinals);
+            for (i, &(name, rva)) in exports.iter().enumerate() {
+                let i = i as u32;
+                // The functions are stored in reverse, so the lookup has to go
+                // through the ordinals.
+                let ordinal = len - 1 - i;
+                module.write_u32(functions + 4 * ordinal, rva);
+                module.write_u32(names + 4 * i, strings);
+                module.write_u16(ordinals + 2 * i, ordinal as u16);
+                module.write(strings, name.as_bytes());
+                strings += name.len() as u32 + 1;
+            }
+            module
+        }
+
+        fn write(&mut self, rva: u32, bytes: &[u8]) {
+            let start = rva as usize;
+            self.memory[start..start + bytes.len()].copy_from_slice(bytes);
+        }
+
+        fn write_u16(&mut self, rva: u32, value: u16) {
+            self.write(rva, &[value as u8, (value >> 8) as u8]);
+        }
+
+        fn write_u32(&mut self, rva: u32, value: u32) {
+            let bytes = (0..4).map(|i| (value >> (8 * i)) as u8).collect::<Vec<_>>();
+            self.write(rva, &bytes);
+        }
+    }
+
+    impl MemoryReader for FakeModule {
+        fn is_64bit(&self) -> bool {
+            true
+        }
+
+        fn module_address(&self, _: &str) -> Result<Address> {
+            Ok(BASE)
+        }
+
+        fn module_size(&self, _: &str) -> Result<u64> {
+            Ok(self.memory.len() as u64)
+        }
+
+        fn module_export(&self, _: &str, _: &str) -> Result<Address> {
+            Err(Error::ExportDoesntExist)
+        }
+
+        fn read_buf(&self, address: Address, buf: &mut [u8]) -> Result<()> {
+            let start = address.checked_sub(BASE).ok_or(Error::ReadMemory)? as usize;
+            let bytes = self
+                .memory
+                .get(start..start + buf.len())
+                .ok_or(Error::ReadMemory)?;
+            buf.copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    const EXPORTS: &[(&str, u32)] = &[
+        ("mono_assembly_foreach", 0x1000),
+        ("mono_class_from_name", 0x2000),
+        ("mono_get_root_domain", 0x3000),
+        ("mono_get_root_domain_ex", 0x4000),
+        ("mono_thread_attach", 0x5000),
+    ];
+
+    #[test]
+    fn finds_every_export() {
+        for &magic in &[PE32_MAGIC, PE32_PLUS_MAGIC] {
+            let module = FakeModule::new(magic, EXPORTS);
+            for &(name, rva) in EXPORTS {
+                assert_eq!(
+                    export_address(&module, BASE, name).unwrap(),
+                    BASE + rva as Address
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_missing_and_forwarded_exports() {
+        let mut exports = EXPORTS.to_vec();
+        exports.push(("mono_thread_detach", EXPORT_RVA + 0x10));
+        let module = FakeModule::new(PE32_PLUS_MAGIC, &exports);
+        for name in &[
+            "",
+            "a",
+            "mono_get_root",
+            "mono_get_root_domain_",
+            "mono_thread_detach",
+            "zzz",
+        ] {
+            match export_address(&module, BASE, name) {
+                Err(Error::ExportDoesntExist) => {}
+                result => panic!("{} resolved to {:?}", name, result),
+            }
+        }
+
+        let module = FakeModule::new(PE32_PLUS_MAGIC, &[]);
+        match export_address(&module, BASE, "mono_thread_attach") {
+            Err(Error::ExportDoesntExist) => {}
+            result => panic!("Resolved to {:?}", result),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_modules() {
+        let mut module = FakeModule::new(PE32_PLUS_MAGIC, EXPORTS);
+        module.write_u16(0x98, 0x107);
+        match export_address(&module, BASE, "mono_thread_attach") {
+            Err(Error::InvalidModule) => {}
+            result => panic!("Resolved to {:?}", result),
+        }
+
+        module.write_u32(0x80, 0);
+        match export_address(&module, BASE, "mono_thread_attach") {
+            Err(Error::InvalidModule) => {}
+            result => panic!("Resolved to {:?}", result),
+        }
+
+        module.write_u16(0, 0);
+        match export_address(&module, BASE, "mono_thread_attach") {
+            Err(Error::InvalidModule) => {}
+            result => panic!("Resolved to {:?}", result),
+        }
+    }
+}
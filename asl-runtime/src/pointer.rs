@@ -1,4 +1,4 @@
-#[derive(Copy, Clone, PartialEq, Eq, FromPrimitive)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive)]
 #[repr(u8)]
 pub enum PointerType {
     U8 = 0,
@@ -12,6 +12,8 @@ pub enum PointerType {
     F32 = 8,
     F64 = 9,
     String = 10,
+    /// A fixed amount of raw bytes, like an array or a struct.
+    Bytes = 11,
 }
 
 #[derive(Clone, Debug)]
@@ -27,4 +29,122 @@ pub enum PointerValue {
     F32(f32),
     F64(f64),
     String(String),
+    Bytes(Vec<u8>),
+}
+
+/// The width of the pointers that get dereferenced along a pointer path.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PointerSize {
+    /// Pointers are as wide as the ones of the attached process.
+    Process = 0,
+    Bits32 = 1,
+    Bits64 = 2,
+}
+
+/// Describes the outcome of the most recent attempt at reading a pointer path.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ReadStatus {
+    /// The pointer path hasn't been read yet, as no process is attached.
+    NotRead = 0,
+    /// The value was read successfully.
+    Ok = 1,
+    /// Either the module couldn't be found or reading the memory failed.
+    Failed = 2,
+}
+
+impl PointerValue {
+    pub(crate) fn zeroed(ty: PointerType) -> Self {
+        match ty {
+            PointerType::U8 => PointerValue::U8(0),
+            PointerType::U16 => PointerValue::U16(0),
+            PointerType::U32 => PointerValue::U32(0),
+            PointerType::U64 => PointerValue::U64(0),
+            PointerType::I8 => PointerValue::I8(0),
+            PointerType::I16 => PointerValue::I16(0),
+            PointerType::I32 => PointerValue::I32(0),
+            PointerType::I64 => PointerValue::I64(0),
+            PointerType::F32 => PointerValue::F32(0.0),
+            PointerType::F64 => PointerValue::F64(0.0),
+            PointerType::String => PointerValue::String(String::new()),
+            PointerType::Bytes => PointerValue::Bytes(Vec::new()),
+        }
+    }
+
+    pub fn ty(&self) -> PointerType {
+        match self {
+            PointerValue::U8(_) => PointerType::U8,
+            PointerValue::U16(_) => PointerType::U16,
+            PointerValue::U32(_) => PointerType::U32,
+            PointerValue::U64(_) => PointerType::U64,
+            PointerValue::I8(_) => PointerType::I8,
+            PointerValue::I16(_) => PointerType::I16,
+            PointerValue::I32(_) => PointerType::I32,
+            PointerValue::I64(_) => PointerType::I64,
+            PointerValue::F32(_) => PointerType::F32,
+            PointerValue::F64(_) => PointerType::F64,
+            PointerValue::String(_) => PointerType::String,
+            PointerValue::Bytes(_) => PointerType::Bytes,
+        }
+    }
+
+    /// Returns the value as an integer if it is one. Unsigned 64-bit integers
+    /// are reinterpreted as signed.
+    pub fn as_i64(&self) -> Option<i64> {
+        Some(match *self {
+            PointerValue::U8(v) => v as i64,
+            PointerValue::U16(v) => v as i64,
+            PointerValue::U32(v) => v as i64,
+            PointerValue::U64(v) => v as i64,
+            PointerValue::I8(v) => v as i64,
+            PointerValue::I16(v) => v as i64,
+            PointerValue::I32(v) => v as i64,
+            PointerValue::I64(v) => v,
+            _ => return None,
+        })
+    }
+
+    /// Returns the value as a float if it is a numeric value.
+    pub fn as_f64(&self) -> Option<f64> {
+        Some(match *self {
+            PointerValue::U64(v) => v as f64,
+            PointerValue::F32(v) => v as f64,
+            PointerValue::F64(v) => v,
+            PointerValue::String(_) | PointerValue::Bytes(_) => return None,
+            _ => self.as_i64()? as f64,
+        })
+    }
+
+    /// Reverses the byte order of numeric values.
+    pub(crate) fn swap_bytes(&mut self) {
+        match self {
+            PointerValue::U16(v) => *v = v.swap_bytes(),
+            PointerValue::U32(v) => *v = v.swap_bytes(),
+            PointerValue::U64(v) => *v = v.swap_bytes(),
+            PointerValue::I16(v) => *v = v.swap_bytes(),
+            PointerValue::I32(v) => *v = v.swap_bytes(),
+            PointerValue::I64(v) => *v = v.swap_bytes(),
+            PointerValue::F32(v) => *v = f32::from_bits(v.to_bits().swap_bytes()),
+            PointerValue::F64(v) => *v = f64::from_bits(v.to_bits().swap_bytes()),
+            PointerValue::U8(_)
+            | PointerValue::I8(_)
+            | PointerValue::String(_)
+            | PointerValue::Bytes(_) => {}
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PointerValue::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            PointerValue::Bytes(v) => Some(v),
+            _ => None,
+        }
+    }
 }
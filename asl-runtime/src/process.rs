@@ -1,21 +1,26 @@
+use pe;
+use pointer::PointerSize;
 use winapi::shared::minwindef::{BOOL, DWORD};
 use winapi::um::{
     handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
-    memoryapi::ReadProcessMemory,
+    memoryapi::{ReadProcessMemory, VirtualQueryEx},
     processthreadsapi::{GetProcessTimes, OpenProcess},
     tlhelp32::{
         CreateToolhelp32Snapshot, Module32FirstW, Module32NextW, Process32FirstW, Process32NextW,
         MODULEENTRY32W, PROCESSENTRY32W, TH32CS_SNAPMODULE, TH32CS_SNAPPROCESS,
     },
-    winnt::{HANDLE, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ},
+    winnt::{
+        HANDLE, MEMORY_BASIC_INFORMATION, MEM_COMMIT, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ,
+    },
 };
 
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::ffi::OsString;
 use std::os::windows::ffi::OsStringExt;
 use std::{mem, result, slice};
 
-type Address = u64;
+pub type Address = u64;
 pub type Offset = i64;
 
 quick_error! {
@@ -27,15 +32,147 @@ quick_error! {
         OpenProcess {}
         ModuleDoesntExist {}
         ReadMemory {}
+        QueryMemory {}
+        InvalidModule {}
+        ExportDoesntExist {}
     }
 }
 
 pub type Result<T> = result::Result<T, Error>;
 
+/// The parts of a process the Mono structures and export tables get read from.
+/// Implemented by anything that can stand in for a process.
+pub trait MemoryReader {
+    fn is_64bit(&self) -> bool;
+    fn module_address(&self, module: &str) -> Result<Address>;
+    fn module_size(&self, module: &str) -> Result<u64>;
+    fn module_export(&self, module: &str, symbol: &str) -> Result<Address>;
+    fn read_buf(&self, address: Address, buf: &mut [u8]) -> Result<()>;
+
+    fn read_u16(&self, address: Address) -> Result<u16> {
+        let mut buf = [0; 2];
+        self.read_buf(address, &mut buf)?;
+        Ok(buf.iter().rev().fold(0, |v, &b| v << 8 | b as u16))
+    }
+
+    fn read_u32(&self, address: Address) -> Result<u32> {
+        let mut buf = [0; 4];
+        self.read_buf(address, &mut buf)?;
+        Ok(buf.iter().rev().fold(0, |v, &b| v << 8 | b as u32))
+    }
+
+    fn read_pointer(&self, address: Address) -> Result<Address> {
+        let mut buf = [0; 8];
+        self.read_buf(address, &mut buf)?;
+        Ok(buf.iter().rev().fold(0, |v, &b| v << 8 | b as Address))
+    }
+
+    /// Resolves the address a pointer path points to. Every offset but the
+    /// last one is followed by a pointer dereference. An empty module name
+    /// starts the pointer path at address 0, so the first offset can be an
+    /// absolute address. Big endian pointers get byte swapped.
+    fn follow_pointer_path(
+        &self,
+        module: &str,
+        offsets: &[Offset],
+        big_endian: bool,
+        pointer_size: PointerSize,
+    ) -> Result<Address> {
+        let start = if module.is_empty() {
+            0
+        } else {
+            self.module_address(module)?
+        };
+        self.follow_offsets(start, offsets, big_endian, pointer_size)
+    }
+
+    /// Follows the offsets of a pointer path that starts at the address.
+    fn follow_offsets(
+        &self,
+        start: Address,
+        offsets: &[Offset],
+        big_endian: bool,
+        pointer_size: PointerSize,
+    ) -> Result<Address> {
+        let mut address = start;
+        let is_64bit = match pointer_size {
+            PointerSize::Process => self.is_64bit(),
+            PointerSize::Bits32 => false,
+            PointerSize::Bits64 => true,
+        };
+        // Modules of 64-bit processes can be above 4 GiB even when the pointers
+        // stored in them are 32-bit, so only the dereferencing depends on the
+        // pointer size.
+        let mut offsets = offsets.iter().cloned().peekable();
+        while let Some(offset) = offsets.next() {
+            address = (address as Offset).wrapping_add(offset) as u64;
+            if offsets.peek().is_some() {
+                address = if is_64bit {
+                    let pointer = self.read_pointer(address)?;
+                    if big_endian {
+                        pointer.swap_bytes()
+                    } else {
+                        pointer
+                    }
+                } else {
+                    let pointer = self.read_u32(address)?;
+                    if big_endian {
+                        pointer.swap_bytes() as u64
+                    } else {
+                        pointer as u64
+                    }
+                };
+            }
+        }
+        Ok(address)
+    }
+}
+
+impl MemoryReader for Process {
+    fn is_64bit(&self) -> bool {
+        Process::is_64bit(self)
+    }
+
+    fn module_address(&self, module: &str) -> Result<Address> {
+        Process::module_address(self, module)
+    }
+
+    fn module_size(&self, module: &str) -> Result<u64> {
+        Process::module_size(self, module)
+    }
+
+    fn module_export(&self, module: &str, symbol: &str) -> Result<Address> {
+        Process::module_export(self, module, symbol)
+    }
+
+    fn read_buf(&self, address: Address, buf: &mut [u8]) -> Result<()> {
+        Process::read_buf(self, address, buf)
+    }
+}
+
+/// A range of committed memory of a process.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub base: Address,
+    pub size: u64,
+    /// The `PAGE_*` protection flags of the region.
+    pub protection: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Module {
+    address: Address,
+    size: u64,
+}
+
+#[derive(Debug)]
 pub struct Process {
     handle: HANDLE,
-    modules: HashMap<String, Address>,
+    pid: DWORD,
+    modules: HashMap<String, Module>,
     is_64bit: bool,
+    bytes_read: Cell<u64>,
+    syscalls: Cell<u64>,
 }
 
 impl Drop for Process {
@@ -47,10 +184,24 @@ impl Drop for Process {
 }
 
 impl Process {
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
     pub fn is_64bit(&self) -> bool {
         self.is_64bit
     }
 
+    /// The number of bytes read from the process so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.get()
+    }
+
+    /// The number of system calls issued to read from the process so far.
+    pub fn syscalls(&self) -> u64 {
+        self.syscalls.get()
+    }
+
     pub fn with_name(name: &str) -> Result<Self> {
         unsafe {
             let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
@@ -136,12 +287,15 @@ impl Process {
                 if Module32FirstW(snapshot, &mut entry) != 0 {
                     loop {
                         {
-                            let base_address = entry.modBaseAddr as Address;
+                            let module = Module {
+                                address: entry.modBaseAddr as Address,
+                                size: entry.modBaseSize as u64,
+                            };
                             let name = &entry.szModule;
                             let len = name.iter().take_while(|&&c| c != 0).count();
                             let name = &name[..len];
                             let name = OsString::from_wide(name).to_string_lossy().into_owned();
-                            modules.insert(name, base_address);
+                            modules.insert(name, module);
                         }
 
                         if Module32NextW(snapshot, &mut entry) == 0 {
@@ -170,8 +324,11 @@ impl Process {
 
                 Ok(Self {
                     handle,
+                    pid,
                     modules,
                     is_64bit,
+                    bytes_read: Cell::new(0),
+                    syscalls: Cell::new(0),
                 })
             } else {
                 Err(Error::OpenProcess)
@@ -182,10 +339,55 @@ impl Process {
     pub fn module_address(&self, module: &str) -> Result<Address> {
         self.modules
             .get(module)
-            .cloned()
+            .map(|m| m.address)
             .ok_or(Error::ModuleDoesntExist)
     }
 
+    pub fn module_size(&self, module: &str) -> Result<u64> {
+        self.modules
+            .get(module)
+            .map(|m| m.size)
+            .ok_or(Error::ModuleDoesntExist)
+    }
+
+    /// Looks up the address of a symbol exported by the module by reading its
+    /// export table from the process's memory.
+    pub fn module_export(&self, module: &str, symbol: &str) -> Result<Address> {
+        pe::export_address(self, self.module_address(module)?, symbol)
+    }
+
+    /// Finds the first region of committed memory that contains the address
+    /// or comes after it.
+    pub fn memory_region(&self, address: Address) -> Result<MemoryRegion> {
+        let mut address = address;
+        loop {
+            let info = unsafe {
+                let mut info: MEMORY_BASIC_INFORMATION = mem::zeroed();
+                let len = VirtualQueryEx(
+                    self.handle,
+                    address as _,
+                    &mut info,
+                    mem::size_of_val(&info) as _,
+                );
+                self.syscalls.set(self.syscalls.get() + 1);
+                if len == 0 {
+                    return Err(Error::QueryMemory);
+                }
+                info
+            };
+
+            let (base, size) = (info.BaseAddress as Address, info.RegionSize as u64);
+            if info.State == MEM_COMMIT {
+                return Ok(MemoryRegion {
+                    base,
+                    size,
+                    protection: info.Protect,
+                });
+            }
+            address = base.checked_add(size).ok_or(Error::QueryMemory)?;
+        }
+    }
+
     pub fn read_buf(&self, address: Address, buf: &mut [u8]) -> Result<()> {
         unsafe {
             let mut bytes_read = mem::uninitialized();
@@ -198,6 +400,12 @@ impl Process {
                 &mut bytes_read,
             ) != 0;
 
+            self.syscalls.set(self.syscalls.get() + 1);
+            if successful {
+                self.bytes_read
+                    .set(self.bytes_read.get() + bytes_read as u64);
+            }
+
             if successful && bytes_read as usize == buf.len() {
                 Ok(())
             } else {
@@ -1,20 +1,36 @@
-use environment::{Environment, Imports};
-use pointer::PointerValue;
-use process::{Offset, Process};
+use asl_abi::validate;
+use emulator::Attachment;
+use environment::{Environment, Imports, PointerPath, Setting};
+use mono::{self, Mono};
+use pointer::{PointerValue, ReadStatus};
+use process::{MemoryReader, Process};
+use stats::{Stats, TickStats};
 use std::error::Error;
 use std::mem;
+use std::time::{Duration, Instant};
 use wasmi::{
-    ExternVal, FuncInstance, FuncRef, MemoryRef, Module, ModuleInstance, ModuleRef, RuntimeValue,
+    self, ExternVal, FuncInstance, FuncRef, MemoryRef, Module, ModuleInstance, ModuleRef,
+    RuntimeValue,
 };
 
+const DEFAULT_TICK_RATE: f64 = 60.0;
+
 pub struct Runtime {
     _instance: ModuleRef,
     environment: Environment,
-    process: Option<Process>,
     timer_state: TimerState,
     should_start: Option<FuncRef>,
     should_split: Option<FuncRef>,
     should_reset: Option<FuncRef>,
+    is_loading: Option<FuncRef>,
+    game_time: Option<FuncRef>,
+    tick_rate: Option<f64>,
+    attach_policy: AttachPolicy,
+    /// The process that turned out to run a Unity runtime the Mono pointer
+    /// paths can't be read from. It doesn't get attached to again.
+    unsupported_process: Option<u32>,
+    log: Option<Box<FnMut(&str)>>,
+    stats: Stats,
 }
 
 #[repr(u8)]
@@ -25,6 +41,16 @@ pub enum TimerState {
     Finished = 3,
 }
 
+/// Decides when the runtime attaches to the script's process.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AttachPolicy {
+    /// Every step tries to attach until the process is found.
+    Automatic = 0,
+    /// The runtime only attaches when `Runtime::attach` is called.
+    Manual = 1,
+}
+
 #[derive(Debug)]
 pub enum TimerAction {
     Start,
@@ -32,103 +58,371 @@ pub enum TimerAction {
     Reset,
 }
 
+/// Describes how well the pointer paths of a reloaded script line up with the
+/// ones of the script it replaced.
+#[derive(Debug)]
+pub struct ReloadReport {
+    /// The new script targets a different process, so the runtime detached
+    /// from the old one.
+    pub process_changed: bool,
+    /// The pointer paths that are unchanged and kept their values.
+    pub kept_pointer_paths: usize,
+    /// The pointer paths that are new or changed and got read from scratch.
+    pub reset_pointer_paths: usize,
+    /// The pointer paths of the old script that don't exist anymore.
+    pub removed_pointer_paths: usize,
+}
+
+impl ReloadReport {
+    /// A reload is compatible if the new script attaches to the same process
+    /// and registers the exact same pointer paths.
+    pub fn is_compatible(&self) -> bool {
+        !self.process_changed && self.reset_pointer_paths == 0 && self.removed_pointer_paths == 0
+    }
+}
+
 impl Runtime {
     pub fn new(binary: &[u8]) -> Result<Self, Box<Error>> {
-        let module = Module::from_buffer(binary)?;
-        let instance = ModuleInstance::new(&module, &Imports)?;
-        let memory = into_memory(
-            instance
-                .not_started_instance()
-                .export_by_name("memory")
-                .ok_or("memory not exported")?,
-        )?;
-        let mut environment = Environment::new(memory);
-        let instance = instance.run_start(&mut environment)?;
-        instance.invoke_export("configure", &[], &mut environment)?;
-
-        let should_start = instance
-            .export_by_name("should_start")
-            .and_then(|e| e.as_func()?.clone().into());
-        let should_split = instance
-            .export_by_name("should_split")
-            .and_then(|e| e.as_func()?.clone().into());
-        let should_reset = instance
-            .export_by_name("should_reset")
-            .and_then(|e| e.as_func()?.clone().into());
-
-        Ok(Self {
+        let (instance, environment, configure_time) = instantiate(binary)?;
+
+        let mut runtime = Self {
+            should_start: export_func(&instance, "should_start"),
+            should_split: export_func(&instance, "should_split"),
+            should_reset: export_func(&instance, "should_reset"),
+            is_loading: export_func(&instance, "is_loading"),
+            game_time: export_func(&instance, "game_time"),
             _instance: instance,
             environment,
-            process: None,
             timer_state: TimerState::NotRunning,
-            should_start,
-            should_split,
-            should_reset,
-        })
+            tick_rate: None,
+            attach_policy: AttachPolicy::Automatic,
+            unsupported_process: None,
+            log: None,
+            stats: Stats::default(),
+        };
+        runtime.stats.record_export("configure", configure_time);
+        runtime.flush_messages();
+        Ok(runtime)
+    }
+
+    /// Replaces the script with a new one while staying attached to the
+    /// process. Pointer paths that didn't change keep their current and old
+    /// values. If the new script fails to instantiate or configure, the old
+    /// script keeps running.
+    pub fn reload(&mut self, binary: &[u8]) -> Result<ReloadReport, Box<Error>> {
+        let (instance, mut environment, configure_time) = instantiate(binary)?;
+        self.stats.record_export("configure", configure_time);
+
+        let process_changed = environment.process_name != self.environment.process_name
+            || environment.emulator.map(|e| &e.name) != self.environment.emulator.map(|e| &e.name);
+        if !process_changed {
+            environment.process = self.environment.process.take();
+            environment.strategy = self.environment.strategy.take();
+            mem::swap(&mut environment.mono, &mut self.environment.mono);
+        }
+
+        let matches = if process_changed {
+            vec![None; environment.pointer_paths.len()]
+        } else {
+            match_previous(&self.environment.pointer_paths, &environment.pointer_paths)
+        };
+        let mut kept = Vec::with_capacity(environment.pointer_paths.len());
+        for (pointer_path, &previous) in environment.pointer_paths.iter_mut().zip(&matches) {
+            if let Some(previous) = previous {
+                let previous = &self.environment.pointer_paths[previous];
+                pointer_path.current.clone_from(&previous.current);
+                pointer_path.old.clone_from(&previous.old);
+                pointer_path.status = previous.status;
+                kept.push(true);
+            } else {
+                kept.push(false);
+            }
+        }
+
+        let strategy = environment.strategy.as_ref();
+        if let Some(process) = &environment.process {
+            // The new pointer paths would otherwise start out with zeroes as
+            // their old values, which may trigger actions spuriously.
+            for (pointer_path, &is_kept) in environment.pointer_paths.iter_mut().zip(&kept) {
+                if is_kept {
+                    continue;
+                }
+                let result =
+                    read_pointer_path(process, strategy, &mut environment.mono, pointer_path);
+                pointer_path.status = result.unwrap_or(ReadStatus::Failed);
+                pointer_path.current.clone_from(&pointer_path.old);
+            }
+        }
+
+        for setting in &mut environment.settings {
+            let previous = self
+                .environment
+                .settings
+                .iter()
+                .find(|s| s.key == setting.key);
+            if let Some(previous) = previous {
+                setting.value = previous.value;
+            }
+        }
+
+        let kept_pointer_paths = kept.iter().filter(|&&k| k).count();
+        let report = ReloadReport {
+            process_changed,
+            kept_pointer_paths,
+            reset_pointer_paths: kept.len() - kept_pointer_paths,
+            removed_pointer_paths: self.environment.pointer_paths.len() - kept_pointer_paths,
+        };
+
+        self.should_start = export_func(&instance, "should_start");
+        self.should_split = export_func(&instance, "should_split");
+        self.should_reset = export_func(&instance, "should_reset");
+        self.is_loading = export_func(&instance, "is_loading");
+        self.game_time = export_func(&instance, "game_time");
+        self._instance = instance;
+        self.environment = environment;
+        self.unsupported_process = None;
+        self.flush_messages();
+
+        Ok(report)
     }
 
     pub fn step(&mut self) -> Result<Option<TimerAction>, Box<Error>> {
+        let mut tick = TickStats::default();
+        let result = self.tick(&mut tick);
+        tick.host_calls = mem::replace(&mut self.environment.host_calls, 0);
+        self.stats.record_tick(tick);
+        result
+    }
+
+    fn tick(&mut self, tick: &mut TickStats) -> Result<Option<TimerAction>, Box<Error>> {
         let mut just_connected = false;
-        if self.process.is_none() {
-            self.process = match Process::with_name(&self.environment.process_name) {
-                Ok(p) => Some(p),
-                Err(_) => return Ok(None),
-            };
-            eprintln!("Connected");
+        if self.environment.process.is_none() {
+            if self.attach_policy == AttachPolicy::Manual || !self.attach() {
+                return Ok(None);
+            }
             just_connected = true;
         }
 
-        if self.update_values(just_connected).is_err() {
-            eprintln!("Disconnected");
-            self.process = None;
-            return Ok(None);
+        if let Err(e) = self.update_values(just_connected, tick) {
+            let is_unsupported = e
+                .downcast_ref::<mono::Error>()
+                .map_or(false, mono::Error::is_unsupported);
+            if is_unsupported {
+                self.unsupported_process = self.environment.process.as_ref().map(Process::pid);
+            }
+            self.detach();
+            return if is_unsupported { Err(e) } else { Ok(None) };
         }
         // println!("{:#?}", self.environment);
-        self.run_script()
+        let action = self.run_script();
+        self.flush_messages();
+        action
+    }
+
+    /// Tries to attach to the script's process. Scripts for an emulated
+    /// system attach to the first supported emulator that is running. Returns
+    /// whether the runtime is attached afterwards.
+    pub fn attach(&mut self) -> bool {
+        if self.environment.process.is_none() {
+            self.stats.attach_attempts += 1;
+            if let Some(emulator) = self.environment.emulator {
+                if let Some((process, strategy)) = emulator.attach() {
+                    self.environment.process = Some(process);
+                    self.environment.strategy = Some(strategy);
+                }
+            } else {
+                self.environment.process = Process::with_name(&self.environment.process_name).ok();
+            }
+            let is_unsupported = self.environment.process.as_ref().map_or(false, |process| {
+                Some(process.pid()) == self.unsupported_process
+            });
+            if is_unsupported {
+                self.environment.process = None;
+                self.environment.strategy = None;
+            }
+            if self.environment.process.is_some() {
+                self.log("Connected");
+            }
+        }
+        self.environment.process.is_some()
+    }
+
+    pub fn detach(&mut self) {
+        self.environment.strategy = None;
+        self.environment.mono.clear();
+        if self.environment.process.take().is_some() {
+            self.log("Disconnected");
+        }
+    }
+
+    pub fn set_attach_policy(&mut self, attach_policy: AttachPolicy) {
+        self.attach_policy = attach_policy;
+    }
+
+    /// Asks the script whether the game is currently loading. Returns `None`
+    /// if the script doesn't track loading or no process is attached.
+    pub fn is_loading(&mut self) -> Result<Option<bool>, Box<Error>> {
+        let func = self.is_loading.clone();
+        let result = match self.call_export("is_loading", &func)? {
+            Some(RuntimeValue::I32(v)) => Some(v != 0),
+            _ => None,
+        };
+        Ok(result)
+    }
+
+    /// Asks the script for the game time in seconds. Returns `None` if the
+    /// script doesn't track game time or no process is attached.
+    pub fn game_time(&mut self) -> Result<Option<f64>, Box<Error>> {
+        let func = self.game_time.clone();
+        let result = match self.call_export("game_time", &func)? {
+            Some(RuntimeValue::F64(v)) => Some(v.to_float()),
+            _ => None,
+        };
+        Ok(result)
+    }
+
+    /// The number of times per second `step` should be called. The host can
+    /// override the rate requested by the script.
+    pub fn tick_rate(&self) -> f64 {
+        self.tick_rate
+            .or(self.environment.tick_rate)
+            .unwrap_or(DEFAULT_TICK_RATE)
+    }
+
+    /// Overrides the tick rate requested by the script. `None` goes back to
+    /// the script's tick rate.
+    pub fn set_tick_rate(&mut self, tick_rate: Option<f64>) {
+        self.tick_rate = tick_rate.filter(|&t| t > 0.0 && t.is_finite());
+    }
+
+    /// All the settings the script registered, in the order of their ids.
+    pub fn settings(&self) -> &[Setting] {
+        &self.environment.settings
+    }
+
+    /// Changes the value of a setting. Returns whether the setting exists.
+    pub fn set_setting(&mut self, key: &str, value: bool) -> bool {
+        match self.environment.settings.iter_mut().find(|s| s.key == key) {
+            Some(setting) => {
+                setting.value = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Receives the messages printed by the script as well as the runtime's
+    /// own status messages. Without a callback they go to stderr.
+    pub fn set_log_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut(&str) + 'static,
+    {
+        self.log = Some(Box::new(callback));
     }
 
     pub fn set_state(&mut self, state: TimerState) {
         self.timer_state = state;
     }
 
-    fn update_values(&mut self, just_connected: bool) -> Result<(), Box<Error>> {
+    /// The name of the process the script wants to attach to.
+    pub fn process_name(&self) -> &str {
+        &self.environment.process_name
+    }
+
+    /// The name of the emulated system the script targets, if any.
+    pub fn emulator(&self) -> Option<&str> {
+        self.environment.emulator.map(|e| e.name.as_str())
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.environment.process.is_some()
+    }
+
+    /// All the pointer paths the script registered, in the order of their ids.
+    pub fn pointer_paths(&self) -> &[PointerPath] {
+        &self.environment.pointer_paths
+    }
+
+    fn log(&mut self, message: &str) {
+        match &mut self.log {
+            Some(log) => log(message),
+            None => eprintln!("{}", message),
+        }
+    }
+
+    fn flush_messages(&mut self) {
+        let messages = mem::replace(&mut self.environment.messages, Vec::new());
+        for message in messages {
+            self.log(&message);
+        }
+    }
+
+    /// The metrics collected since the runtime was created.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+    }
+
+    fn invoke(
+        &mut self,
+        name: &'static str,
+        func: &FuncRef,
+    ) -> Result<Option<RuntimeValue>, wasmi::Error> {
+        let start = Instant::now();
+        let result = FuncInstance::invoke(func, &[], &mut self.environment);
+        self.stats.record_export(name, start.elapsed());
+        Ok(result?)
+    }
+
+    fn call_export(
+        &mut self,
+        name: &'static str,
+        func: &Option<FuncRef>,
+    ) -> Result<Option<RuntimeValue>, Box<Error>> {
+        if self.environment.process.is_none() {
+            return Ok(None);
+        }
+        let result = match func {
+            Some(func) => self.invoke(name, func)?,
+            None => None,
+        };
+        self.flush_messages();
+        Ok(result)
+    }
+
+    fn update_values(
+        &mut self,
+        just_connected: bool,
+        tick: &mut TickStats,
+    ) -> Result<(), Box<Error>> {
         let process = self
+            .environment
             .process
-            .as_mut()
+            .as_ref()
             .expect("The process should be connected at this point");
 
+        let strategy = self.environment.strategy.as_ref();
+
+        let start = Instant::now();
+        let (bytes_read, syscalls) = (process.bytes_read(), process.syscalls());
+        let mut result = Ok(());
         for pointer_path in &mut self.environment.pointer_paths {
-            let mut address = process.module_address(&pointer_path.module_name)?;
-            let mut offsets = pointer_path.offsets.iter().cloned().peekable();
-            if process.is_64bit() {
-                while let Some(offset) = offsets.next() {
-                    address = (address as Offset).wrapping_add(offset) as u64;
-                    if offsets.peek().is_some() {
-                        address = process.read(address)?;
-                    }
-                }
-            } else {
-                while let Some(offset) = offsets.next() {
-                    address = (address as i32).wrapping_add(offset as i32) as u64;
-                    if offsets.peek().is_some() {
-                        address = process.read::<u32>(address)? as u64;
-                    }
+            match read_pointer_path(process, strategy, &mut self.environment.mono, pointer_path) {
+                Ok(status) => pointer_path.status = status,
+                Err(e) => {
+                    pointer_path.status = ReadStatus::Failed;
+                    result = Err(e);
+                    break;
                 }
             }
-            match &mut pointer_path.old {
-                PointerValue::U8(v) => *v = process.read(address)?,
-                PointerValue::U16(v) => *v = process.read(address)?,
-                PointerValue::U32(v) => *v = process.read(address)?,
-                PointerValue::U64(v) => *v = process.read(address)?,
-                PointerValue::I8(v) => *v = process.read(address)?,
-                PointerValue::I16(v) => *v = process.read(address)?,
-                PointerValue::I32(v) => *v = process.read(address)?,
-                PointerValue::I64(v) => *v = process.read(address)?,
-                PointerValue::F32(v) => *v = process.read(address)?,
-                PointerValue::F64(v) => *v = process.read(address)?,
-                PointerValue::String(_) => unimplemented!(),
-            }
         }
+        tick.bytes_read = process.bytes_read() - bytes_read;
+        tick.syscalls = process.syscalls() - syscalls;
+        tick.update_time = start.elapsed();
+        result?;
 
         if just_connected {
             for pointer_path in &mut self.environment.pointer_paths {
@@ -144,41 +438,221 @@ impl Runtime {
     }
 
     fn run_script(&mut self) -> Result<Option<TimerAction>, Box<Error>> {
-        match &self.timer_state {
+        match self.timer_state {
             TimerState::NotRunning => {
-                if let Some(func) = &self.should_start {
-                    let ret_val = FuncInstance::invoke(func, &[], &mut self.environment)?;
+                if let Some(func) = self.should_start.clone() {
+                    let ret_val = self.invoke("should_start", &func)?;
 
                     if let Some(RuntimeValue::I32(1)) = ret_val {
                         return Ok(Some(TimerAction::Start));
                     }
                 }
+                Ok(None)
             }
             TimerState::Running => {
-                if let Some(func) = &self.should_split {
-                    let ret_val = FuncInstance::invoke(func, &[], &mut self.environment)?;
+                if let Some(func) = self.should_split.clone() {
+                    let ret_val = self.invoke("should_split", &func)?;
 
                     if let Some(RuntimeValue::I32(1)) = ret_val {
                         return Ok(Some(TimerAction::Split));
                     }
                 }
-                if let Some(func) = &self.should_reset {
-                    let ret_val = FuncInstance::invoke(func, &[], &mut self.environment)?;
+                self.run_should_reset()
+            }
+            // Nothing gets split while the timer is paused or finished, but
+            // the run can still be reset.
+            TimerState::Paused | TimerState::Finished => self.run_should_reset(),
+        }
+    }
 
-                    if let Some(RuntimeValue::I32(1)) = ret_val {
-                        return Ok(Some(TimerAction::Reset));
-                    }
-                }
+    fn run_should_reset(&mut self) -> Result<Option<TimerAction>, Box<Error>> {
+        if let Some(func) = self.should_reset.clone() {
+            let ret_val = self.invoke("should_reset", &func)?;
+
+            if let Some(RuntimeValue::I32(1)) = ret_val {
+                return Ok(Some(TimerAction::Reset));
             }
-            _ => unimplemented!(),
         }
         Ok(None)
     }
 }
 
+/// Also returns how long the script took to configure itself.
+fn instantiate(binary: &[u8]) -> Result<(ModuleRef, Environment, Duration), Box<Error>> {
+    let report = validate(binary);
+    if !report.is_valid() {
+        return Err(report.into());
+    }
+    let module = Module::from_buffer(binary)?;
+    let instance = ModuleInstance::new(&module, &Imports)?;
+    let memory = into_memory(
+        instance
+            .not_started_instance()
+            .export_by_name("memory")
+            .ok_or("memory not exported")?,
+    )?;
+    let mut environment = Environment::new(memory, report.abi_version());
+    let instance = instance.run_start(&mut environment)?;
+    let start = Instant::now();
+    instance.invoke_export("configure", &[], &mut environment)?;
+    let configure_time = start.elapsed();
+    environment.finish_configuration();
+    Ok((instance, environment, configure_time))
+}
+
+fn export_func(instance: &ModuleRef, name: &str) -> Option<FuncRef> {
+    instance
+        .export_by_name(name)
+        .and_then(|e| e.as_func()?.clone().into())
+}
+
+/// Looks up the pointer paths of the previous script that the pointer paths of
+/// a reloaded script correspond to. Named pointer paths are matched by name,
+/// unnamed ones by their id. They only match if they are read the same way.
+/// Each pointer path of the previous script is matched at most once.
+fn match_previous(previous: &[PointerPath], pointer_paths: &[PointerPath]) -> Vec<Option<usize>> {
+    let mut is_matched = vec![false; previous.len()];
+    pointer_paths
+        .iter()
+        .enumerate()
+        .map(|(index, pointer_path)| {
+            let candidate = if pointer_path.name.is_empty() {
+                Some(index).filter(|&i| previous.get(i).map_or(false, |p| p.name.is_empty()))
+            } else {
+                previous.iter().position(|p| p.name == pointer_path.name)
+            };
+            let candidate = candidate
+                .filter(|&i| !is_matched[i] && is_read_the_same(&previous[i], pointer_path));
+            if let Some(candidate) = candidate {
+                is_matched[candidate] = true;
+            }
+            candidate
+        }).collect()
+}
+
+fn is_read_the_same(candidate: &PointerPath, pointer_path: &PointerPath) -> bool {
+    let size = |p: &PointerPath| p.old.as_bytes().map(|b| b.len());
+    candidate.module_name == pointer_path.module_name
+        && candidate.offsets == pointer_path.offsets
+        && candidate.emulated == pointer_path.emulated
+        && candidate.mono == pointer_path.mono
+        && candidate.big_endian == pointer_path.big_endian
+        && candidate.pointer_size == pointer_path.pointer_size
+        && candidate.ty() == pointer_path.ty()
+        && size(candidate) == size(pointer_path)
+}
+
+/// Reads the value of the pointer path into its old value. Mono pointer paths
+/// that can't be resolved yet keep their value, as the game may not have
+/// loaded what they point to yet. Unity runtimes that aren't supported are
+/// reported as errors instead.
+fn read_pointer_path(
+    process: &Process,
+    strategy: Option<&Attachment>,
+    mono: &mut Mono,
+    pointer_path: &mut PointerPath,
+) -> Result<ReadStatus, Box<Error>> {
+    let address = if let Some(path) = &pointer_path.mono {
+        let offsets = match mono.resolve(process, path) {
+            Ok(offsets) => offsets,
+            Err(mono::Error::Process(e)) => return Err(e.into()),
+            Err(e) if e.is_unsupported() => return Err(e.into()),
+            Err(_) => return Ok(ReadStatus::Failed),
+        };
+        process.follow_pointer_path("", offsets, false, pointer_path.pointer_size)?
+    } else if pointer_path.emulated {
+        let strategy = strategy.ok_or("No emulator is attached")?;
+        strategy.resolve(
+            process,
+            &pointer_path.module_name,
+            pointer_path.offsets[0] as u64,
+        )?
+    } else {
+        process.follow_pointer_path(
+            &pointer_path.module_name,
+            &pointer_path.offsets,
+            pointer_path.big_endian,
+            pointer_path.pointer_size,
+        )?
+    };
+    match &mut pointer_path.old {
+        PointerValue::U8(v) => *v = process.read(address)?,
+        PointerValue::U16(v) => *v = process.read(address)?,
+        PointerValue::U32(v) => *v = process.read(address)?,
+        PointerValue::U64(v) => *v = process.read(address)?,
+        PointerValue::I8(v) => *v = process.read(address)?,
+        PointerValue::I16(v) => *v = process.read(address)?,
+        PointerValue::I32(v) => *v = process.read(address)?,
+        PointerValue::I64(v) => *v = process.read(address)?,
+        PointerValue::F32(v) => *v = process.read(address)?,
+        PointerValue::F64(v) => *v = process.read(address)?,
+        PointerValue::String(_) => unimplemented!(),
+        PointerValue::Bytes(v) => process.read_buf(address, v)?,
+    }
+    if pointer_path.big_endian {
+        pointer_path.old.swap_bytes();
+    }
+    Ok(ReadStatus::Ok)
+}
+
 fn into_memory(extern_val: ExternVal) -> Result<MemoryRef, Box<Error>> {
     match extern_val {
         ExternVal::Memory(memory) => Ok(memory),
         _ => Err("Memory is not exported correctly".into()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pointer::PointerSize;
+
+    fn pointer_path(name: &str, offset: i64) -> PointerPath {
+        PointerPath {
+            name: name.to_owned(),
+            module_name: "game.exe".to_owned(),
+            offsets: vec![offset],
+            emulated: false,
+            mono: None,
+            big_endian: false,
+            pointer_size: PointerSize::Process,
+            current: PointerValue::U8(0),
+            old: PointerValue::U8(0),
+            status: ReadStatus::NotRead,
+        }
+    }
+
+    #[test]
+    fn matches_by_name_and_id() {
+        let previous = [pointer_path("", 0x10), pointer_path("level", 0x20)];
+        let pointer_paths = [
+            pointer_path("level", 0x20),
+            pointer_path("", 0x10),
+            pointer_path("", 0x30),
+        ];
+        assert_eq!(
+            match_previous(&previous, &pointer_paths),
+            [Some(1), None, None]
+        );
+
+        let pointer_paths = [pointer_path("", 0x10), pointer_path("level", 0x30)];
+        assert_eq!(match_previous(&previous, &pointer_paths), [Some(0), None]);
+    }
+
+    #[test]
+    fn matches_each_previous_pointer_path_once() {
+        // The unnamed pointer path has the id of the named one, which is
+        // matched by name as well.
+        let previous = [pointer_path("level", 0x20)];
+        let pointer_paths = [pointer_path("", 0x20), pointer_path("level", 0x20)];
+        assert_eq!(match_previous(&previous, &pointer_paths), [None, Some(0)]);
+
+        let previous = [pointer_path("level", 0x20), pointer_path("lives", 0x30)];
+        let pointer_paths = [pointer_path("level", 0x20), pointer_path("level", 0x20)];
+        let matches = match_previous(&previous, &pointer_paths);
+        assert_eq!(matches, [Some(0), None]);
+
+        let kept = matches.iter().filter(|m| m.is_some()).count();
+        assert_eq!(previous.len() - kept, 1);
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+/// How often an export of the script got called and how long it ran for.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ExportStats {
+    pub invocations: u64,
+    pub time: Duration,
+}
+
+/// The work done during a single tick, or summed up over all of them.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TickStats {
+    /// The calls the script made into the environment.
+    pub host_calls: u64,
+    /// The bytes read from the process while updating the pointer paths.
+    pub bytes_read: u64,
+    /// The system calls issued while updating the pointer paths.
+    pub syscalls: u64,
+    /// The time spent updating the pointer paths.
+    pub update_time: Duration,
+}
+
+/// A snapshot of the metrics the runtime collected since it was created.
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    pub ticks: u64,
+    pub attach_attempts: u64,
+    pub exports: BTreeMap<&'static str, ExportStats>,
+    pub last_tick: TickStats,
+    pub total: TickStats,
+}
+
+impl Stats {
+    pub(crate) fn record_export(&mut self, name: &'static str, time: Duration) {
+        let export = self
+            .exports
+            .entry(name)
+            .or_insert_with(ExportStats::default);
+        export.invocations += 1;
+        export.time += time;
+    }
+
+    pub(crate) fn record_tick(&mut self, tick: TickStats) {
+        self.ticks += 1;
+        self.last_tick = tick;
+        self.total.host_calls += tick.host_calls;
+        self.total.bytes_read += tick.bytes_read;
+        self.total.syscalls += tick.syscalls;
+        self.total.update_time += tick.update_time;
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1e3 + duration.subsec_nanos() as f64 / 1e6
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ticks = self.ticks.max(1) as f64;
+        writeln!(
+            f,
+            "{} ticks, {} attach attempts",
+            self.ticks, self.attach_attempts
+        )?;
+        writeln!(
+            f,
+            "per tick: {:.1} host calls, {:.1} bytes read, {:.1} syscalls, {:.3} ms updating",
+            self.total.host_calls as f64 / ticks,
+            self.total.bytes_read as f64 / ticks,
+            self.total.syscalls as f64 / ticks,
+            millis(self.total.update_time) / ticks,
+        )?;
+        for (name, export) in &self.exports {
+            writeln!(
+                f,
+                "{}: {} calls, {:.3} ms total, {:.3} ms average",
+                name,
+                export.invocations,
+                millis(export.time),
+                millis(export.time) / export.invocations.max(1) as f64,
+            )?;
+        }
+        Ok(())
+    }
+}
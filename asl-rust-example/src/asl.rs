@@ -1,14 +1,71 @@
+use asl_abi;
+
+/// Tells the runtime which version of the ABI this module targets.
+#[used]
+#[link_section = "asl_abi_version"]
+static ABI_VERSION: [u8; 4] = asl_abi::VERSION_SECTION_PAYLOAD;
+
 mod sys {
-    use super::{PointerKind, State};
+    use super::{MemoryRegion, State};
 
     extern "C" {
         pub fn set_process_name(name_ptr: *const u8, name_len: usize);
-        pub fn push_pointer_path(
+        pub fn push_pointer_path(module_ptr: *const u8, module_len: usize, kind: u32) -> usize;
+        pub fn push_offset(pointer_path_id: usize, offset: i64);
+        pub fn set_pointer_path_name(pointer_path_id: usize, name_ptr: *const u8, name_len: usize);
+        pub fn set_pointer_path_offsets(
+            pointer_path_id: usize,
+            offsets_ptr: *const i64,
+            offsets_len: usize,
+        );
+        pub fn set_pointer_path_module(
+            pointer_path_id: usize,
             module_ptr: *const u8,
             module_len: usize,
-            kind: PointerKind,
+        );
+        pub fn read_memory(
+            module_ptr: *const u8,
+            module_len: usize,
+            offsets_ptr: *const i64,
+            offsets_len: usize,
+            buf_ptr: *mut u8,
+            buf_len: usize,
+        ) -> bool;
+        pub fn add_setting(key_ptr: *const u8, key_len: usize, default: bool) -> usize;
+        pub fn get_setting(setting_id: usize) -> bool;
+        pub fn set_tick_rate(ticks_per_second: f64);
+        pub fn print_message(message_ptr: *const u8, message_len: usize);
+        pub fn set_emulator(name_ptr: *const u8, name_len: usize);
+        pub fn push_emulator_pointer_path(
+            region_ptr: *const u8,
+            region_len: usize,
+            address: i64,
+            kind: u32,
         ) -> usize;
-        pub fn push_offset(pointer_path_id: usize, offset: i64);
+        pub fn push_mono_pointer_path(
+            assembly_ptr: *const u8,
+            assembly_len: usize,
+            path_ptr: *const u8,
+            path_len: usize,
+            kind: u32,
+        ) -> usize;
+        pub fn set_pointer_path_size(pointer_path_id: usize, size: usize);
+        pub fn get_bytes(
+            pointer_path_id: usize,
+            current: State,
+            offset: usize,
+            buf_ptr: *mut u8,
+            buf_len: usize,
+        );
+        pub fn get_module_address(module_ptr: *const u8, module_len: usize) -> u64;
+        pub fn get_module_size(module_ptr: *const u8, module_len: usize) -> u64;
+        pub fn get_module_export(
+            module_ptr: *const u8,
+            module_len: usize,
+            symbol_ptr: *const u8,
+            symbol_len: usize,
+        ) -> u64;
+        pub fn get_memory_region(address: u64, region: *mut MemoryRegion) -> bool;
         pub fn get_u8(pointer_path_id: usize, current: State) -> u8;
         pub fn get_u16(pointer_path_id: usize, current: State) -> u16;
         pub fn get_u32(pointer_path_id: usize, current: State) -> u32;
@@ -36,6 +93,48 @@ pub enum PointerKind {
     F32 = 8,
     F64 = 9,
     String = 10,
+    /// Reads a fixed amount of bytes, like an array or a struct. The amount
+    /// is set with `set_pointer_path_size`.
+    Bytes = 11,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+/// The width of the pointers that get dereferenced along a pointer path.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum PointerSize {
+    /// Pointers are as wide as the ones of the process.
+    Process,
+    Bits32,
+    Bits64,
+}
+
+/// Combines the kind with the flags stored in its upper bits.
+fn pointer_kind(kind: PointerKind, endianness: Endianness, pointer_size: PointerSize) -> u32 {
+    let mut kind = kind as u32;
+    if endianness == Endianness::Big {
+        kind |= asl_abi::BIG_ENDIAN_FLAG;
+    }
+    match pointer_size {
+        PointerSize::Process => {}
+        PointerSize::Bits32 => kind |= asl_abi::POINTER_SIZE_32_FLAG,
+        PointerSize::Bits64 => kind |= asl_abi::POINTER_SIZE_64_FLAG,
+    }
+    kind
+}
+
+/// A range of committed memory of the attached process.
+#[derive(Copy, Clone, Default)]
+#[repr(C, packed)]
+pub struct MemoryRegion {
+    pub base: u64,
+    pub size: u64,
+    /// The `PAGE_*` protection flags of the region.
+    pub protection: u32,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -51,12 +150,194 @@ pub fn set_process_name(module: &str) {
     }
 }
 
-pub fn push_pointer_path(module: &str, offsets: &[i64], kind: PointerKind) {
+pub fn push_pointer_path(
+    name: &str,
+    module: &str,
+    offsets: &[i64],
+    kind: PointerKind,
+    endianness: Endianness,
+    pointer_size: PointerSize,
+) -> usize {
     unsafe {
-        let id = sys::push_pointer_path(module.as_ptr() as *const u8, module.len(), kind);
+        let id = sys::push_pointer_path(
+            module.as_ptr() as *const u8,
+            module.len(),
+            pointer_kind(kind, endianness, pointer_size),
+        );
         for &offset in offsets {
             sys::push_offset(id, offset);
         }
+        sys::set_pointer_path_name(id, name.as_ptr() as *const u8, name.len());
+        id
+    }
+}
+
+/// Makes the runtime attach to any supported emulator of the system, like
+/// `gb`, instead of a specific process.
+pub fn set_emulator(name: &str) {
+    unsafe {
+        sys::set_emulator(name.as_ptr(), name.len());
+    }
+}
+
+/// Registers a pointer path to an address in a memory region of the emulated
+/// system. Requires `set_emulator` to be called first. Values of big endian
+/// systems are always read as big endian.
+pub fn push_emulator_pointer_path(
+    name: &str,
+    region: &str,
+    address: i64,
+    kind: PointerKind,
+    endianness: Endianness,
+    pointer_size: PointerSize,
+) -> usize {
+    unsafe {
+        let id = sys::push_emulator_pointer_path(
+            region.as_ptr(),
+            region.len(),
+            address,
+            pointer_kind(kind, endianness, pointer_size),
+        );
+        sys::set_pointer_path_name(id, name.as_ptr() as *const u8, name.len());
+        id
+    }
+}
+
+/// Pushes a pointer path that starts at a static field of a class of a Unity
+/// game running on Mono and follows the fields of the objects along the way.
+/// The path looks like `GameManager.instance.level`.
+pub fn push_mono_pointer_path(
+    name: &str,
+    assembly: &str,
+    path: &str,
+    kind: PointerKind,
+    endianness: Endianness,
+    pointer_size: PointerSize,
+) -> usize {
+    unsafe {
+        let id = sys::push_mono_pointer_path(
+            assembly.as_ptr(),
+            assembly.len(),
+            path.as_ptr(),
+            path.len(),
+            pointer_kind(kind, endianness, pointer_size),
+        );
+        sys::set_pointer_path_name(id, name.as_ptr() as *const u8, name.len());
+        id
+    }
+}
+
+/// Sets how many bytes a pointer path of the kind `Bytes` reads. Only allowed
+/// in `configure`.
+pub fn set_pointer_path_size(pointer_path_id: usize, size: usize) {
+    unsafe { sys::set_pointer_path_size(pointer_path_id, size) }
+}
+
+pub fn set_pointer_path_offsets(pointer_path_id: usize, offsets: &[i64]) {
+    unsafe {
+        sys::set_pointer_path_offsets(pointer_path_id, offsets.as_ptr(), offsets.len());
+    }
+}
+
+pub fn set_pointer_path_module(pointer_path_id: usize, module: &str) {
+    unsafe {
+        sys::set_pointer_path_module(pointer_path_id, module.as_ptr(), module.len());
+    }
+}
+
+/// Reads the bytes at the end of the pointer path into the buffer. An empty
+/// module name makes the first offset an absolute address. The buffer gets
+/// zeroed if reading fails.
+pub fn read_memory(module: &str, offsets: &[i64], buf: &mut [u8]) -> bool {
+    unsafe {
+        sys::read_memory(
+            module.as_ptr(),
+            module.len(),
+            offsets.as_ptr(),
+            offsets.len(),
+            buf.as_mut_ptr(),
+            buf.len(),
+        )
+    }
+}
+
+/// The address the module is loaded at. `None` if there is no process
+/// attached or it doesn't have the module.
+pub fn module_address(module: &str) -> Option<u64> {
+    match unsafe { sys::get_module_address(module.as_ptr(), module.len()) } {
+        0 => None,
+        address => Some(address),
+    }
+}
+
+pub fn module_size(module: &str) -> Option<u64> {
+    match unsafe { sys::get_module_size(module.as_ptr(), module.len()) } {
+        0 => None,
+        size => Some(size),
+    }
+}
+
+/// Looks up the address of a symbol the module exports, like
+/// `mono_get_root_domain`.
+pub fn module_export(module: &str, symbol: &str) -> Option<u64> {
+    match unsafe {
+        sys::get_module_export(module.as_ptr(), module.len(), symbol.as_ptr(), symbol.len())
+    } {
+        0 => None,
+        address => Some(address),
+    }
+}
+
+/// Finds the first region of committed memory that contains the address or
+/// comes after it.
+pub fn memory_region(address: u64) -> Option<MemoryRegion> {
+    let mut region = MemoryRegion::default();
+    if unsafe { sys::get_memory_region(address, &mut region) } {
+        Some(region)
+    } else {
+        None
+    }
+}
+
+/// Lists all the regions of committed memory of the attached process.
+pub fn memory_regions() -> Vec<MemoryRegion> {
+    let mut regions = Vec::new();
+    let mut address = Some(0);
+    while let Some(region) = address.and_then(memory_region) {
+        address = region.base.checked_add(region.size);
+        regions.push(region);
+    }
+    regions
+}
+
+/// Registers a setting the user can toggle. Only allowed in `configure`.
+pub fn add_setting(key: &str, default: bool) -> usize {
+    unsafe { sys::add_setting(key.as_ptr(), key.len(), default) }
+}
+
+pub fn get_setting(setting_id: usize) -> bool {
+    unsafe { sys::get_setting(setting_id) }
+}
+
+pub fn set_tick_rate(ticks_per_second: f64) {
+    unsafe { sys::set_tick_rate(ticks_per_second) }
+}
+
+pub fn print_message(message: &str) {
+    unsafe { sys::print_message(message.as_ptr(), message.len()) }
+}
+
+/// Copies the bytes starting at the offset out of the value of a pointer path
+/// of the kind `Bytes`. Traps if they are out of its bounds.
+pub fn get_bytes(pointer_path_id: usize, current: State, offset: usize, buf: &mut [u8]) {
+    unsafe {
+        sys::get_bytes(
+            pointer_path_id,
+            current,
+            offset,
+            buf.as_mut_ptr(),
+            buf.len(),
+        )
     }
 }
 
@@ -106,3 +387,67 @@ where
 {
     fn get() -> (Self, Self);
 }
+
+#[cfg(test)]
+mod tests {
+    use asl_abi::{self, ValueType};
+
+    /// The WebAssembly type the Rust type is passed as.
+    fn value_type(ty: &str) -> ValueType {
+        match ty {
+            "i64" | "u64" => ValueType::I64,
+            "f32" => ValueType::F32,
+            "f64" => ValueType::F64,
+            "usize" | "u32" | "i32" | "u16" | "i16" | "u8" | "i8" | "bool" | "State" => {
+                ValueType::I32
+            }
+            ty if ty.starts_with('*') => ValueType::I32,
+            ty => panic!("Unknown type {}", ty),
+        }
+    }
+
+    /// Parses the declarations of the `sys` module into their names,
+    /// parameters and results.
+    fn externs() -> Vec<(String, Vec<ValueType>, Option<ValueType>)> {
+        let src = include_str!("asl.rs");
+        let start = src.find("extern \"C\" {").unwrap();
+        let end = start + src[start..].find("\n    }\n").unwrap();
+        src[start..end]
+            .split("pub fn ")
+            .skip(1)
+            .map(|decl| {
+                let decl = decl.split_whitespace().collect::<Vec<_>>().join(" ");
+                let (name, rest) = decl.split_at(decl.find('(').unwrap());
+                let (params, result) = rest.split_at(rest.rfind(')').unwrap());
+                let params = params[1..]
+                    .split(',')
+                    .filter(|p| !p.trim().is_empty())
+                    .map(|p| value_type(p.split(':').nth(1).unwrap().trim()))
+                    .collect();
+                let result = result
+                    .trim_end_matches(';')
+                    .split("->")
+                    .nth(1)
+                    .map(|ty| value_type(ty.trim()));
+                (name.to_owned(), params, result)
+            }).collect()
+    }
+
+    #[test]
+    fn imports_match_abi() {
+        let externs = externs();
+        for (name, params, result) in &externs {
+            let import =
+                asl_abi::import(name).unwrap_or_else(|| panic!("{} isn't part of the ABI", name));
+            assert_eq!(&import.params[..], &params[..], "{}", name);
+            assert_eq!(import.result, *result, "{}", name);
+        }
+        for import in asl_abi::IMPORTS {
+            assert!(
+                externs.iter().any(|(name, ..)| name == import.name),
+                "{} isn't declared",
+                import.name
+            );
+        }
+    }
+}
@@ -1,3 +1,4 @@
+extern crate asl_abi;
 #[macro_use]
 extern crate asl_derive;
 
@@ -6,9 +7,9 @@ mod asl;
 use asl::ASLState;
 
 #[derive(ASLState)]
-#[Process = "bgb.exe"]
+#[Emulator = "gb"]
 struct MyState {
-    #[Pointer = "bgb.exe, 0x00166EDC, 0x274, 0x362"]
+    #[Pointer = "wram 0xC362"]
     x: u8,
 }
 
@@ -368,7 +368,7 @@ monaco.languages.setMonarchTokensProvider('asl', {
 
     keywords: [
         "if", "else", "for", "match", "while", "loop", "let",
-        "in", "as"
+        "in", "as", "read"
     ],
 
     functionFollows: ["fn"],
@@ -396,12 +396,17 @@ monaco.languages.setMonarchTokensProvider('asl', {
     // we include these common regular expressions
     symbols: /[=><!~?:&|\.+\-*\/\^%]+/,
 
-    // C# style strings
-    escapes: /\\(?:[abfnrtv\\"']|x[0-9A-Fa-f]{1,4}|u[0-9A-Fa-f]{4}|U[0-9A-Fa-f]{8})/,
+    escapes: /\\(?:[nrt0\\"']|x[0-7][0-9A-Fa-f]|u\{[0-9A-Fa-f]{1,6}\})/,
+
+    // the suffixes of number literals like 5u8
+    intSuffix: /[ui](?:8|16|32|64)/,
 
     // The main tokenizer for our languages
     tokenizer: {
         root: [
+            // raw strings
+            [/r(#*)"/, { token: 'string.quote', bracket: '@open', next: '@rawString.$1' }],
+
             // identifiers and keywords
             [/[a-z_$][\w$]*/, {
                 cases: {
@@ -428,9 +433,12 @@ monaco.languages.setMonarchTokensProvider('asl', {
             }],
 
             // numbers
-            [/\d*\.\d+([eE][\-+]?\d+)?/, 'number.float'],
-            [/0[xX][0-9a-fA-F]+/, 'number.hex'],
-            [/\d+/, 'number'],
+            [/\d[\d_]*(\.\d[\d_]*)?([eE][\-+]?\d[\d_]*)?f(32|64)/, 'number.float'],
+            [/\d[\d_]*(\.\d[\d_]*([eE][\-+]?\d[\d_]*)?|[eE][\-+]?\d[\d_]*)/, 'number.float'],
+            [/0x[0-9a-fA-F_]+(@intSuffix)?/, 'number.hex'],
+            [/0o[0-7_]+(@intSuffix)?/, 'number.octal'],
+            [/0b[01_]+(@intSuffix)?/, 'number.binary'],
+            [/\d[\d_]*(@intSuffix)?/, 'number'],
 
             // delimiter: after number because of .\d floats
             [/[;,.]/, 'delimiter'],
@@ -459,6 +467,16 @@ monaco.languages.setMonarchTokensProvider('asl', {
             [/"/, { token: 'string.quote', bracket: '@close', next: '@pop' }]
         ],
 
+        rawString: [
+            [/[^"]+/, 'string'],
+            [/"(#*)/, {
+                cases: {
+                    '$1==$S2': { token: 'string.quote', bracket: '@close', next: '@pop' },
+                    '@default': 'string'
+                }
+            }]
+        ],
+
         whitespace: [
             [/[ \t\r\n]+/, 'white'],
             [/\/\*/, 'comment', '@comment'],
@@ -609,12 +627,16 @@ monaco.languages.registerHoverProvider('asl', {
     provideHover: async function (model, position) {
         const result = await hover(position.lineNumber, position.column);
         if (result) {
-            const { span, ty } = result;
+            const { span, ty, docs } = result;
+            const contents = [
+                { value: "```asl\n" + ty + "\n```" },
+            ];
+            if (docs) {
+                contents.push({ value: docs });
+            }
             return {
                 range: new monaco.Range(span.lineFrom, span.columnFrom, span.lineTo, span.columnTo),
-                contents: [
-                    { value: "```asl\n" + ty + "\n```" },
-                ],
+                contents,
             }
         }
     }
@@ -750,24 +772,59 @@ function decodeSpan(wasm, spanPtr) {
     return { lineFrom, columnFrom, lineTo, columnTo };
 }
 
-async function baseCompile() {
-    const { wasm, src } = await prepareInstance();
-    const result = wasm.exports.ASL_compile(src.ptr);
-    if (wasm.exports.Result_is_ok(result)) {
-        const compiled = wasm.exports.Result_ok(result);
-        const compiledASL = getSlice(wasm, compiled);
-        return { isOk: true, val: compiledASL };
-    } else {
-        const err = wasm.exports.Result_error(result);
-        const msgPtr = wasm.exports.Error_msg_ptr(err);
-        const msgLen = wasm.exports.Error_msg_len(err);
-        const spanPtr = wasm.exports.Error_span(err);
+function decodeDiagnostics(wasm, diagnosticsPtr) {
+    let diagnostics = [];
+    let index = 0;
+    while (true) {
+        const diagnostic = wasm.exports.Diagnostics_get(diagnosticsPtr, index);
+        if (diagnostic == 0) {
+            break;
+        }
+        const isError = wasm.exports.Diagnostic_is_error(diagnostic) != 0;
+        const msgPtr = wasm.exports.Diagnostic_msg_ptr(diagnostic);
+        const msgLen = wasm.exports.Diagnostic_msg_len(diagnostic);
+        const spanPtr = wasm.exports.Diagnostic_span(diagnostic);
         let span = null;
         if (spanPtr != 0) {
             span = decodeSpan(wasm, spanPtr);
         }
         const msg = decodeStringPtrLen(wasm, msgPtr, msgLen);
-        return { isOk: false, err: { msg, span } };
+        const labels = decodeLabels(wasm, wasm.exports.Diagnostic_labels(diagnostic));
+        diagnostics.push({ isError, msg, span, labels });
+        index += 1;
+    }
+    return diagnostics;
+}
+
+function decodeLabels(wasm, labelsPtr) {
+    let labels = [];
+    let index = 0;
+    while (true) {
+        const label = wasm.exports.Labels_get(labelsPtr, index);
+        if (label == 0) {
+            break;
+        }
+        const msgPtr = wasm.exports.Label_msg_ptr(label);
+        const msgLen = wasm.exports.Label_msg_len(label);
+        const msg = decodeStringPtrLen(wasm, msgPtr, msgLen);
+        const span = decodeSpan(wasm, wasm.exports.Label_span(label));
+        labels.push({ msg, span });
+        index += 1;
+    }
+    return labels;
+}
+
+async function baseCompile() {
+    const { wasm, src } = await prepareInstance();
+    const result = wasm.exports.ASL_compile(src.ptr);
+    const diagnostics = decodeDiagnostics(wasm, wasm.exports.Compilation_diagnostics(result));
+    const compiled = wasm.exports.Compilation_module(result);
+    const compiledASL = compiled != 0 ? getSlice(wasm, compiled) : null;
+    wasm.exports.Compilation_drop(result);
+    if (compiledASL != null) {
+        return { isOk: true, val: compiledASL, diagnostics };
+    } else {
+        return { isOk: false, diagnostics };
     }
 }
 
@@ -791,9 +848,11 @@ async function hover(line, column) {
         const hover = wasm.exports.Result_ok(result);
         if (hover != 0) {
             const tyBuf = wasm.exports.Hover_ty(hover);
+            const docsBuf = wasm.exports.Hover_docs(hover);
             const span = wasm.exports.Hover_span(hover);
             const ty = decodeString(wasm, tyBuf);
-            return { ty, span: decodeSpan(wasm, span) };
+            const docs = docsBuf != 0 ? decodeString(wasm, docsBuf) : null;
+            return { ty, docs, span: decodeSpan(wasm, span) };
         }
     }
 }
@@ -835,26 +894,50 @@ compileButton.onclick = () => fullCompile();
 
 const body = document.getElementsByTagName("body")[0];
 
+// Diagnostics without a span of their own point at their first label.
+function markerSpan({ span, labels }) {
+    if (span) {
+        return span;
+    }
+    return labels.length > 0 ? labels[0].span : null;
+}
+
 async function validate() {
     let success = true;
     monaco.editor.setModelMarkers(monaco.editor.getModels()[0], "i made this", []);
     try {
         const result = await fullCompile();
+        const model = monaco.editor.getModels()[0];
+        const markers = result.diagnostics
+            .filter(diagnostic => markerSpan(diagnostic))
+            .map(diagnostic => {
+                const { isError, msg, labels } = diagnostic;
+                const span = markerSpan(diagnostic);
+                return {
+                    startLineNumber: span.lineFrom,
+                    endLineNumber: span.lineTo,
+                    startColumn: span.columnFrom,
+                    endColumn: span.columnTo,
+                    severity: isError ? monaco.MarkerSeverity.Error : monaco.MarkerSeverity.Warning,
+                    message: msg,
+                    relatedInformation: labels.map(label => ({
+                        resource: model.uri,
+                        message: label.msg,
+                        startLineNumber: label.span.lineFrom,
+                        endLineNumber: label.span.lineTo,
+                        startColumn: label.span.columnFrom,
+                        endColumn: label.span.columnTo,
+                    })),
+                };
+            });
+        monaco.editor.setModelMarkers(monaco.editor.getModels()[0], "i made this", markers);
         if (!result.isOk) {
             success = false;
-            const { msg, span } = result.err;
-            compileButton.textContent = `Error: ${msg}`;
-            if (span) {
-                monaco.editor.setModelMarkers(monaco.editor.getModels()[0], "i made this", [
-                    {
-                        startLineNumber: span.lineFrom,
-                        endLineNumber: span.lineTo,
-                        startColumn: span.columnFrom,
-                        endColumn: span.columnTo,
-                        severity: monaco.MarkerSeverity.Error,
-                        message: msg,
-                    }
-                ])
+            const errors = result.diagnostics.filter(({ isError }) => isError);
+            if (errors.length == 1) {
+                compileButton.textContent = `Error: ${errors[0].msg}`;
+            } else {
+                compileButton.textContent = `${errors.length} Errors`;
             }
         } else {
             compileButton.textContent = "Download";